@@ -1,51 +1,160 @@
-use anyhow::{Context, Result};
+use std::{env, fmt::Debug, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
 use tracing::{info, warn};
 
+#[cfg(target_os = "linux")]
+use joystick_rs::driver::evdev::Evdev;
+#[cfg(windows)]
+use joystick_rs::driver::rawinput::RawInput;
 use joystick_rs::{
-    driver::{rawinput::RawInput, Driver, Event},
+    driver::{recorder::Recorder, Attachment, Driver, Event},
+    gesture::{GestureConfig, GestureRecognizer},
+    hid::ds4::Ds4Event,
     logging::init_from_env,
-    profile::PS4Compact,
-    ObjectDiff,
+    normalize::{CalibrationStore, NormalizedDiff, Normalizer},
+    players::{Players, PlayersConfig},
+    profile::{PS4Compact, ProfileRegistry, SharedProfile},
+    response::{ResponseConfig, ResponseFilter},
+    Button,
 };
 
 pub fn main() -> Result<()> {
     init_from_env().context("init logging")?;
 
+    let mut record = None;
+    let mut calibrations = CalibrationStore::default();
+    let mut responses = ResponseConfig::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => {
+                record.replace(args.next().context("--record <file>")?);
+            }
+            "--calibrations" => {
+                let path = args.next().context("--calibrations <file>")?;
+                calibrations = CalibrationStore::load(path)?;
+            }
+            "--responses" => {
+                let path = args.next().context("--responses <file>")?;
+                responses = ResponseConfig::load(path)?;
+            }
+            other => return Err(anyhow!("unexpected argument {}", other)),
+        }
+    }
+
+    #[cfg(windows)]
     let hdl = RawInput::background().context("init rawinput in background")?;
 
+    #[cfg(target_os = "linux")]
+    let hdl = Evdev::background().context("init evdev in background")?;
+
+    #[cfg(any(windows, target_os = "linux"))]
+    return match record {
+        Some(path) => {
+            info!("recording into {}", path);
+            run(
+                Recorder::create(hdl, &path).context("init recorder")?,
+                &calibrations,
+                &responses,
+            )
+        }
+        None => run(hdl, &calibrations, &responses),
+    };
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    return Err(anyhow!("no driver available on this platform"));
+}
+
+struct Device<DI> {
+    id: DI,
+    profile: SharedProfile,
+    normalizer: Normalizer,
+    filter: ResponseFilter,
+    gestures: GestureRecognizer,
+}
+
+fn run<D: Driver>(hdl: D, calibrations: &CalibrationStore, responses: &ResponseConfig) -> Result<()>
+where
+    D::DeviceIdent: Debug + Clone,
+{
+    let mut registry = ProfileRegistry::new();
+    // DualShock 4, first & second revisions
+    registry.register_ids(0x054c, 0x05c4, None, Arc::new(PS4Compact));
+    registry.register_ids(0x054c, 0x09cc, None, Arc::new(PS4Compact));
+
+    let gestures = GestureConfig {
+        chords: vec![vec![Button::Select, Button::Start]],
+        ..Default::default()
+    };
+
+    let mut devices: Vec<Device<D::DeviceIdent>> = Vec::new();
+    let mut players = Players::new(PlayersConfig::default());
     let rx = hdl.as_event_receiver();
     // let mut state_count = 0;
 
     loop {
         let evt = rx.recv()?;
+        for player_evt in players.event(&evt) {
+            info!("{:?}", player_evt);
+        }
+
         match evt {
-            Event::Attached(id, info) => {
-                info!("device {} attached: {:?}", id, info);
+            Event::Attached(id, info, attachment, _) => {
+                match attachment {
+                    Attachment::New => info!("device {:?} attached: {:?}", id, info),
+                    Attachment::Reconnect(prev) => {
+                        info!("device {:?} reconnected, was {:?}", id, prev)
+                    }
+                }
+
+                devices.retain(|dev| dev.id != id);
+                devices.push(Device {
+                    profile: registry.select(&info),
+                    normalizer: calibrations.normalizer(&info),
+                    filter: ResponseFilter::new(responses.clone()),
+                    gestures: GestureRecognizer::new(gestures.clone()),
+                    id,
+                });
             }
 
-            Event::Deattached(id) => {
-                info!("device {} deattached", id);
+            Event::Deattached(id, _) => {
+                info!("device {:?} deattached", id);
                 break;
             }
 
-            Event::StateDiff { id, is_sink, diff } => {
-                let obj_diffs = diff.diffs(&PS4Compact);
+            Event::StateDiff {
+                id,
+                is_sink,
+                diff,
+                ts,
+            } => {
+                let dev = match devices.iter_mut().find(|dev| dev.id == id) {
+                    Some(dev) => dev,
+                    None => continue,
+                };
+
+                let obj_diffs = dev
+                    .filter
+                    .filter(dev.normalizer.diffs(&diff, dev.profile.as_ref()));
                 // state_count += obj_diffs.len();
 
+                for gesture in dev.gestures.normalized_diffs(ts, &obj_diffs) {
+                    info!(dev = ?id, is_sink, ?ts, "gesture {:?}", gesture);
+                }
+
                 for odiff in obj_diffs {
                     match odiff {
-                        ObjectDiff::Button(bid, bst) => {
-                            info!(dev = id, is_sink, "button state ({:?}, {:?})", bid, bst);
+                        NormalizedDiff::Button(bid, bst) => {
+                            info!(dev = ?id, is_sink, ?ts, "button state ({:?}, {:?})", bid, bst);
                         }
 
-                        ObjectDiff::Axis(aid, ast) => {
-                            if ast == 0 || ast == 255 {
-                                info!(dev = id, is_sink, "axis edge state ({:?}, {:?})", aid, ast);
-                            }
+                        NormalizedDiff::Axis(aid, ast) if ast.abs() == 1.0 => {
+                            info!(dev = ?id, is_sink, ?ts, "axis edge state ({:?}, {:?})", aid, ast);
                         }
 
-                        ObjectDiff::DPad(dst) => {
-                            info!(dev = id, is_sink, "dpad state {:?}", dst);
+                        NormalizedDiff::DPad(hidx, dst) => {
+                            info!(dev = ?id, is_sink, ?ts, "dpad {} state {:?}", hidx, dst);
                         }
 
                         _ => {}
@@ -58,12 +167,21 @@ pub fn main() -> Result<()> {
                 // }
             }
 
-            Event::Warn(e) => {
+            Event::Ds4(id, Ds4Event::Battery { level, charging }, ts) => match level {
+                Some(level) => {
+                    info!(dev = ?id, ?ts, "battery {}%, charging {}", level, charging)
+                }
+                None => warn!(dev = ?id, ?ts, "battery error"),
+            },
+
+            Event::Ds4(..) => {}
+
+            Event::Warn(e, _) => {
                 warn!("err received: {:?}", e);
                 break;
             }
 
-            Event::Interruption(res) => {
+            Event::Interruption(res, _) => {
                 warn!("interrupted: {:?}", res);
                 break;
             }
@@ -0,0 +1,507 @@
+//! application actions bound to buttons, axis & dpad directions, rebindable per player
+//!
+//! Actions are declared once with their kinds, bound by default, and rebound
+//! player by player; a mapper per player turns normalized diffs into action events.
+
+use std::{
+    collections::BTreeMap,
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
+
+use crate::{normalize::NormalizedDiff, Axis, Button, ButtonState, DPadState, HatIdent, HAT_LIMIT};
+
+/// physical input an action is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Input {
+    Button(Button),
+    Axis(Axis),
+    /// a direction of a hat, cardinal directions are also held by the diagonals around them
+    DPad {
+        #[serde(default)]
+        hat: HatIdent,
+        dir: DPadState,
+    },
+}
+
+impl Serialize for Input {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct DPad {
+            hat: HatIdent,
+            dir: DPadState,
+        }
+
+        // a single entry map like the derived form, which TOML can't serialize
+        let mut map = serializer.serialize_map(Some(1))?;
+        match *self {
+            Self::Button(btn) => map.serialize_entry("button", &btn)?,
+            Self::Axis(axis) => map.serialize_entry("axis", &axis)?,
+            Self::DPad { hat, dir } => map.serialize_entry("dpad", &DPad { hat, dir })?,
+        }
+
+        map.end()
+    }
+}
+
+impl Input {
+    /// value of the input in the current states, 0 or 1 for buttons & dpad directions
+    fn value(&self, states: &InputStates) -> f32 {
+        match self {
+            Self::Button(btn) => states.buttons.iter().any(|b| b == btn) as u8 as f32,
+            Self::Axis(axis) => states.axis.get(axis).copied().unwrap_or(0.0),
+            Self::DPad { hat, dir } => states
+                .hats
+                .get(*hat)
+                .is_some_and(|st| dpad_holds(*st, *dir))
+                as u8 as f32,
+        }
+    }
+}
+
+/// whether the dpad state holds the direction
+fn dpad_holds(state: DPadState, dir: DPadState) -> bool {
+    use DPadState::*;
+
+    state == dir
+        || matches!(
+            (dir, state),
+            (Up, UpLeft | UpRight)
+                | (Down, DownLeft | DownRight)
+                | (Left, UpLeft | DownLeft)
+                | (Right, UpRight | DownRight)
+        )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionKind {
+    /// pressed or released, axis count as pressed above the threshold
+    Digital,
+    /// a value, in `[-1, 1]` or `[0, 1]` as normalized, buttons being 0 or 1
+    Analog,
+}
+
+pub type PlayerIdent = usize;
+
+/// declared actions with their default & per player bindings, e.g. loaded from a TOML file like
+///
+/// ```toml
+/// [actions]
+/// jump = "digital"
+/// throttle = "analog"
+///
+/// [defaults]
+/// jump = [{ button = "South" }, { dpad = { dir = "Up" } }]
+/// throttle = [{ axis = "RTrigger" }]
+///
+/// # rebindings of the first player
+/// [[players]]
+/// jump = [{ button = "East" }]
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BindingConfig {
+    #[serde(default)]
+    pub actions: BTreeMap<String, ActionKind>,
+    #[serde(default)]
+    pub defaults: BTreeMap<String, Vec<Input>>,
+    /// rebindings by player, overriding the defaults action by action
+    #[serde(default)]
+    pub players: Vec<BTreeMap<String, Vec<Input>>>,
+    /// magnitude from which axis bound to digital actions count as pressed
+    #[serde(default = "BindingConfig::default_threshold")]
+    pub threshold: f32,
+}
+
+impl Default for BindingConfig {
+    fn default() -> Self {
+        Self {
+            actions: BTreeMap::new(),
+            defaults: BTreeMap::new(),
+            players: Vec::new(),
+            threshold: Self::default_threshold(),
+        }
+    }
+}
+
+impl BindingConfig {
+    fn default_threshold() -> f32 {
+        0.5
+    }
+
+    pub fn from_toml_str(s: &str) -> Result<Self> {
+        toml::from_str(s).context("parse toml bindings")
+    }
+
+    pub fn from_json_str(s: &str) -> Result<Self> {
+        serde_json::from_str(s).context("parse json bindings")
+    }
+
+    /// load a bindings file, the format is chosen by the file extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = read_to_string(path).with_context(|| format!("read bindings {:?}", path))?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml_str(&content),
+            Some("json") => Self::from_json_str(&content),
+            _ => Err(anyhow!("unknown bindings format of {:?}", path)),
+        }
+        .with_context(|| format!("load bindings {:?}", path))
+    }
+
+    /// save into a bindings file, the format is chosen by the file extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::to_string_pretty(self).context("serialize toml bindings"),
+            Some("json") => serde_json::to_string_pretty(self).context("serialize json bindings"),
+            _ => Err(anyhow!("unknown bindings format of {:?}", path)),
+        }?;
+
+        write(path, content).with_context(|| format!("write bindings {:?}", path))
+    }
+
+    /// declare an action, keeping its bindings if it's already declared
+    pub fn declare(&mut self, action: &str, kind: ActionKind) {
+        self.actions.insert(action.to_owned(), kind);
+    }
+
+    pub fn bind_default(&mut self, action: &str, inputs: Vec<Input>) {
+        self.defaults.insert(action.to_owned(), inputs);
+    }
+
+    /// inputs of the action for the player, the defaults unless rebound
+    pub fn bindings(&self, player: PlayerIdent, action: &str) -> &[Input] {
+        self.players
+            .get(player)
+            .and_then(|p| p.get(action))
+            .or_else(|| self.defaults.get(action))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn rebind(&mut self, player: PlayerIdent, action: &str, inputs: Vec<Input>) {
+        if self.players.len() <= player {
+            self.players.resize_with(player + 1, Default::default);
+        }
+
+        self.players[player].insert(action.to_owned(), inputs);
+    }
+
+    /// restore the default bindings of the action for the player
+    pub fn reset(&mut self, player: PlayerIdent, action: &str) {
+        if let Some(p) = self.players.get_mut(player) {
+            p.remove(action);
+        }
+    }
+
+    /// mapper of the player, with the bindings as of now
+    pub fn mapper(&self, player: PlayerIdent) -> ActionMapper {
+        ActionMapper::new(self, player)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActionEvent {
+    Pressed(String),
+    Released(String),
+    /// new value of an analog action
+    Changed(String, f32),
+}
+
+/// latest states of the bound inputs of a device
+#[derive(Debug, Clone)]
+struct InputStates {
+    /// pressed buttons
+    buttons: Vec<Button>,
+    axis: BTreeMap<Axis, f32>,
+    hats: [DPadState; HAT_LIMIT],
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    action: String,
+    kind: ActionKind,
+    inputs: Vec<Input>,
+    value: f32,
+}
+
+/// turns the normalized diffs of the device of a player into action events
+#[derive(Debug, Clone)]
+pub struct ActionMapper {
+    player: PlayerIdent,
+    threshold: f32,
+    bindings: Vec<Binding>,
+    states: InputStates,
+}
+
+impl ActionMapper {
+    pub fn new(config: &BindingConfig, player: PlayerIdent) -> Self {
+        let bindings = config
+            .actions
+            .iter()
+            .map(|(action, kind)| Binding {
+                action: action.clone(),
+                kind: *kind,
+                inputs: config.bindings(player, action).to_vec(),
+                value: 0.0,
+            })
+            .collect();
+
+        Self {
+            player,
+            threshold: config.threshold,
+            bindings,
+            states: InputStates {
+                buttons: Vec::new(),
+                axis: BTreeMap::new(),
+                hats: [DPadState::Null; HAT_LIMIT],
+            },
+        }
+    }
+
+    pub fn player(&self) -> PlayerIdent {
+        self.player
+    }
+
+    /// current value of the action, 0 or 1 for digital actions
+    pub fn value(&self, action: &str) -> f32 {
+        self.bindings
+            .iter()
+            .find(|b| b.action == action)
+            .map_or(0.0, |b| b.value)
+    }
+
+    pub fn is_pressed(&self, action: &str) -> bool {
+        self.value(action) != 0.0
+    }
+
+    /// fold the diffs into the input states, and report the changed actions
+    pub fn diffs(&mut self, diffs: &[NormalizedDiff]) -> Vec<ActionEvent> {
+        for diff in diffs {
+            match diff {
+                NormalizedDiff::Button(btn, ButtonState::Pressed) => {
+                    if !self.states.buttons.contains(btn) {
+                        self.states.buttons.push(*btn);
+                    }
+                }
+
+                NormalizedDiff::Button(btn, ButtonState::Released) => {
+                    self.states.buttons.retain(|b| b != btn);
+                }
+
+                NormalizedDiff::Axis(axis, v) => {
+                    self.states.axis.insert(*axis, *v);
+                }
+
+                NormalizedDiff::DPad(hat, st) => {
+                    if let Some(slot) = self.states.hats.get_mut(*hat) {
+                        *slot = *st;
+                    }
+                }
+
+                NormalizedDiff::Slider(..) => {}
+            }
+        }
+
+        let mut events = Vec::new();
+        for binding in self.bindings.iter_mut() {
+            // the input with the largest magnitude wins
+            let raw = binding
+                .inputs
+                .iter()
+                .map(|i| i.value(&self.states))
+                .fold(0.0f32, |acc, v| if v.abs() > acc.abs() { v } else { acc });
+
+            let value = match binding.kind {
+                ActionKind::Digital => (raw.abs() >= self.threshold) as u8 as f32,
+                ActionKind::Analog => raw,
+            };
+
+            if value == binding.value {
+                continue;
+            }
+
+            binding.value = value;
+            let action = binding.action.clone();
+            events.push(match binding.kind {
+                ActionKind::Digital if value != 0.0 => ActionEvent::Pressed(action),
+                ActionKind::Digital => ActionEvent::Released(action),
+                ActionKind::Analog => ActionEvent::Changed(action, value),
+            });
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_file};
+
+    use super::*;
+
+    /// the documented example, with a digital action bound to an axis
+    fn config() -> BindingConfig {
+        let mut config = BindingConfig::from_toml_str(
+            r#"
+            [actions]
+            jump = "digital"
+            throttle = "analog"
+
+            [defaults]
+            jump = [{ button = "South" }, { dpad = { dir = "Up" } }]
+            throttle = [{ axis = "RTrigger" }]
+
+            [[players]]
+            jump = [{ button = "East" }]
+            "#,
+        )
+        .unwrap();
+
+        config.declare("fire", ActionKind::Digital);
+        config.bind_default("fire", vec![Input::Axis(Axis::LThumbY)]);
+        config
+    }
+
+    fn press(btn: Button, pressed: bool) -> NormalizedDiff {
+        NormalizedDiff::Button(btn, pressed.into())
+    }
+
+    fn pressed(action: &str) -> ActionEvent {
+        ActionEvent::Pressed(action.to_owned())
+    }
+
+    fn released(action: &str) -> ActionEvent {
+        ActionEvent::Released(action.to_owned())
+    }
+
+    #[test]
+    fn digital_threshold() {
+        let mut mapper = config().mapper(1);
+        let mut axis = |v| mapper.diffs(&[NormalizedDiff::Axis(Axis::LThumbY, v)]);
+
+        assert!(axis(0.49).is_empty());
+        assert_eq!(axis(0.5), [pressed("fire")]);
+        assert!(axis(1.0).is_empty());
+        assert_eq!(axis(0.2), [released("fire")]);
+
+        // either direction
+        assert_eq!(axis(-0.7), [pressed("fire")]);
+        assert!(mapper.is_pressed("fire"));
+        assert_eq!(mapper.value("fire"), 1.0);
+    }
+
+    #[test]
+    fn analog_changes() {
+        let mut mapper = config().mapper(1);
+        let changed = |v| vec![ActionEvent::Changed("throttle".to_owned(), v)];
+
+        let diffs = [NormalizedDiff::Axis(Axis::RTrigger, 0.25)];
+        assert_eq!(mapper.diffs(&diffs), changed(0.25));
+        assert!(mapper.diffs(&diffs).is_empty());
+        assert_eq!(mapper.value("throttle"), 0.25);
+
+        // only the bound axis
+        assert!(mapper
+            .diffs(&[NormalizedDiff::Axis(Axis::LTrigger, 0.5)])
+            .is_empty());
+        assert_eq!(
+            mapper.diffs(&[NormalizedDiff::Axis(Axis::RTrigger, 0.0)]),
+            changed(0.0)
+        );
+    }
+
+    #[test]
+    fn dpad_diagonals() {
+        let mut mapper = config().mapper(1);
+        let dpad = |hat, st| [NormalizedDiff::DPad(hat, st)];
+
+        assert_eq!(mapper.diffs(&dpad(0, DPadState::UpLeft)), [pressed("jump")]);
+        assert!(mapper.diffs(&dpad(0, DPadState::Up)).is_empty());
+        assert!(mapper.diffs(&dpad(0, DPadState::UpRight)).is_empty());
+        assert_eq!(mapper.diffs(&dpad(0, DPadState::Right)), [released("jump")]);
+        assert!(mapper.diffs(&dpad(0, DPadState::DownLeft)).is_empty());
+
+        // other hats
+        assert!(mapper.diffs(&dpad(1, DPadState::Up)).is_empty());
+        assert!(mapper.diffs(&dpad(HAT_LIMIT, DPadState::Up)).is_empty());
+
+        // held by any of the bound inputs
+        assert_eq!(mapper.diffs(&dpad(0, DPadState::Up)), [pressed("jump")]);
+        assert!(mapper.diffs(&[press(Button::South, true)]).is_empty());
+        assert!(mapper.diffs(&dpad(0, DPadState::Null)).is_empty());
+        assert_eq!(
+            mapper.diffs(&[press(Button::South, false)]),
+            [released("jump")]
+        );
+    }
+
+    #[test]
+    fn rebind_players() {
+        let mut config = config();
+        let jump = |mapper: &mut ActionMapper, btn| {
+            let events = mapper.diffs(&[press(btn, true)]);
+            mapper.diffs(&[press(btn, false)]);
+            events
+        };
+
+        // the first player is rebound by the config
+        assert!(jump(&mut config.mapper(0), Button::South).is_empty());
+        assert_eq!(jump(&mut config.mapper(0), Button::East), [pressed("jump")]);
+        assert_eq!(
+            jump(&mut config.mapper(1), Button::South),
+            [pressed("jump")]
+        );
+
+        config.rebind(2, "jump", vec![Input::Button(Button::North)]);
+        assert_eq!(config.players.len(), 3);
+        assert!(jump(&mut config.mapper(1), Button::North).is_empty());
+        assert_eq!(
+            jump(&mut config.mapper(2), Button::North),
+            [pressed("jump")]
+        );
+        assert_eq!(
+            config.bindings(2, "throttle"),
+            [Input::Axis(Axis::RTrigger)]
+        );
+
+        config.reset(0, "jump");
+        config.reset(5, "jump");
+        assert!(jump(&mut config.mapper(0), Button::East).is_empty());
+        assert_eq!(
+            jump(&mut config.mapper(0), Button::South),
+            [pressed("jump")]
+        );
+        assert!(config.bindings(0, "unknown").is_empty());
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let mut config = config();
+        config.rebind(
+            1,
+            "fire",
+            vec![Input::DPad {
+                hat: 1,
+                dir: DPadState::DownLeft,
+            }],
+        );
+
+        for ext in ["toml", "json"] {
+            let path = temp_dir().join(format!(
+                "joystick-rs-bindings-{}.{}",
+                std::process::id(),
+                ext
+            ));
+            config.save(&path).unwrap();
+            let loaded = BindingConfig::load(&path);
+            _ = remove_file(&path);
+            assert_eq!(loaded.unwrap(), config);
+        }
+    }
+}
@@ -1,12 +1,14 @@
-use std::ops::BitXor;
+use std::{fmt::Debug, ops::BitXor};
 
-pub trait Bits: Sized + BitXor<Output = Self> + Default {
+pub trait Bits: Sized + Clone + Debug + PartialEq + BitXor<Output = Self> + Default {
     const CAP: usize;
 
     fn bit(&self, pos: usize) -> Option<bool>;
 
     fn set(&mut self, pos: usize) -> bool;
 
+    fn clear(&mut self, pos: usize) -> bool;
+
     fn count_ones(&self) -> u32;
 }
 
@@ -33,6 +35,15 @@ macro_rules! impl_bits {
                 true
             }
 
+            fn clear(&mut self, pos: usize) -> bool {
+                if pos >= Self::CAP {
+                    return false;
+                }
+
+                *self &= !(1 << pos);
+                true
+            }
+
             #[inline]
             fn count_ones(&self) -> u32 {
                 <$t>::count_ones(*self)
@@ -46,7 +57,7 @@ impl_bits!(u64, 64);
 impl_bits!(u128, 128);
 
 #[repr(transparent)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct B256([u128; 2]);
 
 impl BitXor for B256 {
@@ -86,6 +97,20 @@ impl Bits for B256 {
         true
     }
 
+    fn clear(&mut self, pos: usize) -> bool {
+        if pos >= Self::CAP {
+            return false;
+        }
+
+        if pos < 128 {
+            self.0[0] &= !(1 << pos);
+        } else {
+            self.0[1] &= !(1 << (pos - 128));
+        }
+
+        true
+    }
+
     fn count_ones(&self) -> u32 {
         self.0[0].count_ones() + self.0[1].count_ones()
     }
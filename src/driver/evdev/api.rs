@@ -0,0 +1,648 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{c_int, c_ulong},
+    fs::{read_dir, File, OpenOptions},
+    io::{self, Read, Write},
+    iter::once,
+    mem::{size_of, zeroed},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    slice::from_raw_parts,
+    sync::atomic::{AtomicBool, Ordering},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use crossbeam_channel::Sender;
+use tracing::{debug, trace, warn_span};
+
+use super::{axis_code, codes::*, AbsInfo, Decoder, DeviceLayout, InputEvent, InputId};
+use crate::{
+    driver::{Reconnects, Rumble, Timestamp},
+    hid::ds4::{is_ds4, Ds4Decoder, REPORT_BT_LEN},
+    AxisIdent,
+};
+
+type Event = crate::driver::Event<u32, super::ButtonBits>;
+
+const INPUT_DIR: &str = "/dev/input";
+const SYS_INPUT_DIR: &str = "/sys/class/input";
+const RESCAN_INTERVAL: Duration = Duration::from_secs(1);
+const POLL_TIMEOUT_MS: c_int = 100;
+const READ_BUF_EVENTS: usize = 64;
+
+const IOC_WRITE: c_ulong = 1;
+const IOC_READ: c_ulong = 2;
+
+#[inline]
+const fn ioc(dir: c_ulong, nr: c_ulong, size: usize) -> c_ulong {
+    (dir << 30) | ((size as c_ulong) << 16) | ((b'E' as c_ulong) << 8) | nr
+}
+
+#[inline]
+const fn eviocgid() -> c_ulong {
+    ioc(IOC_READ, 0x02, size_of::<libc::input_id>())
+}
+
+#[inline]
+const fn eviocgname(len: usize) -> c_ulong {
+    ioc(IOC_READ, 0x06, len)
+}
+
+#[inline]
+const fn eviocgphys(len: usize) -> c_ulong {
+    ioc(IOC_READ, 0x07, len)
+}
+
+#[inline]
+const fn eviocguniq(len: usize) -> c_ulong {
+    ioc(IOC_READ, 0x08, len)
+}
+
+#[inline]
+const fn eviocgkey(len: usize) -> c_ulong {
+    ioc(IOC_READ, 0x18, len)
+}
+
+#[inline]
+const fn eviocgbit(ev: u16, len: usize) -> c_ulong {
+    ioc(IOC_READ, 0x20 + ev as c_ulong, len)
+}
+
+#[inline]
+const fn eviocsclockid() -> c_ulong {
+    ioc(IOC_WRITE, 0xa0, size_of::<c_int>())
+}
+
+#[inline]
+const fn eviocgabs(abs: u16) -> c_ulong {
+    ioc(IOC_READ, 0x40 + abs as c_ulong, size_of::<AbsInfo>())
+}
+
+#[inline]
+const fn eviocsff() -> c_ulong {
+    ioc(IOC_WRITE, 0x80, size_of::<libc::ff_effect>())
+}
+
+struct DeviceStatus {
+    file: File,
+    decoder: Decoder,
+    ds4: Option<Ds4Input>,
+}
+
+/// hidraw node of a DualShock 4, read for the data its event node lacks
+struct Ds4Input {
+    path: PathBuf,
+    file: File,
+    decoder: Ds4Decoder,
+}
+
+pub(super) fn start_event_loop(stop: &AtomicBool, event_tx: &Sender<Event>) -> Result<()> {
+    let mut devices = HashMap::new();
+    let mut ignored = HashSet::new();
+    let mut reconnects = Reconnects::default();
+    let mut last_scan: Option<Instant> = None;
+    let mut buf = vec![0u8; InputEvent::SIZE * READ_BUF_EVENTS];
+
+    while !stop.load(Ordering::Acquire) {
+        if last_scan.is_none_or(|t| t.elapsed() >= RESCAN_INTERVAL) {
+            scan_devices(&mut devices, &mut ignored, &mut reconnects, event_tx)
+                .context("scan devices")?;
+            last_scan.replace(Instant::now());
+        }
+
+        if devices.is_empty() {
+            sleep(Duration::from_millis(POLL_TIMEOUT_MS as u64));
+            continue;
+        }
+
+        // device idents, with whether the fd is the hidraw node of the device
+        let (ids, mut fds): (Vec<(u32, bool)>, Vec<libc::pollfd>) = devices
+            .iter()
+            .flat_map(|(id, status): (&u32, &DeviceStatus)| {
+                let hidraw = status.ds4.as_ref().map(|ds4| ((*id, true), &ds4.file));
+                once(((*id, false), &status.file)).chain(hidraw)
+            })
+            .map(|(key, file)| {
+                (
+                    key,
+                    libc::pollfd {
+                        fd: file.as_raw_fd(),
+                        events: libc::POLLIN,
+                        revents: 0,
+                    },
+                )
+            })
+            .unzip();
+
+        trace!("waiting for events");
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, POLL_TIMEOUT_MS) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            return Err(err).context("poll");
+        }
+
+        for ((id, hidraw), pfd) in ids.into_iter().zip(fds) {
+            if pfd.revents == 0 {
+                continue;
+            }
+
+            let _span = warn_span!("device", id, hidraw).entered();
+            let status = match devices.get_mut(&id) {
+                Some(s) => s,
+                None => continue,
+            };
+
+            if hidraw {
+                let evts = match status.ds4.as_mut().map(|ds4| read_ds4(id, ds4)) {
+                    Some(Ok(evts)) => evts,
+                    Some(Err(e)) => {
+                        // the event node tells whether the device is gone
+                        debug!("hidraw node closed: {:?}", e);
+                        status.ds4.take();
+                        continue;
+                    }
+                    None => continue,
+                };
+
+                for evt in evts {
+                    event_tx.send(evt).context("event chan broken")?;
+                }
+                continue;
+            }
+
+            let evts = match read_device(id, status, &mut buf) {
+                Ok(evts) => evts,
+                Err(e) => {
+                    debug!("removed: {:?}", e);
+                    devices.remove(&id);
+                    reconnects.detach(&id);
+                    vec![Event::Deattached(id, Timestamp::now())]
+                }
+            };
+
+            for evt in evts {
+                event_tx.send(evt).context("event chan broken")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn scan_devices(
+    devices: &mut HashMap<u32, DeviceStatus>,
+    ignored: &mut HashSet<u32>,
+    reconnects: &mut Reconnects<u32>,
+    event_tx: &Sender<Event>,
+) -> Result<()> {
+    let mut present = HashSet::new();
+    for entry in read_dir(INPUT_DIR).with_context(|| format!("read dir {}", INPUT_DIR))? {
+        let path = entry.context("read dir entry")?.path();
+        if let Some(id) = event_node_number(&path) {
+            present.insert((id, path));
+        }
+    }
+
+    ignored.retain(|id| present.iter().any(|(pid, _)| pid == id));
+
+    for (id, path) in present {
+        if devices.contains_key(&id) || ignored.contains(&id) {
+            continue;
+        }
+
+        let _span = warn_span!("open device", ?path).entered();
+        let evt = match open_device(&path) {
+            Ok(Some(mut status)) => {
+                let info = status.decoder.layout().info();
+                if is_ds4(info.vendor_id, info.product_id) {
+                    status.ds4 = match open_ds4_input(id, devices) {
+                        Ok(ds4) => ds4,
+                        Err(e) => {
+                            debug!("unable to open hidraw node: {:?}", e);
+                            None
+                        }
+                    };
+                }
+
+                devices.insert(id, status);
+                let attachment = reconnects.attach(&id, &info);
+                Event::Attached(id, info, attachment, Timestamp::now())
+            }
+
+            Ok(None) => {
+                trace!("not a joystick");
+                ignored.insert(id);
+                continue;
+            }
+
+            Err(e) => {
+                debug!("unable to open: {:?}", e);
+                ignored.insert(id);
+                continue;
+            }
+        };
+
+        event_tx.send(evt).context("event chan broken")?;
+    }
+
+    Ok(())
+}
+
+/// hidraw node of the HID device behind the event node
+fn hidraw_path(id: u32) -> Result<PathBuf> {
+    let hidraw_dir = Path::new(SYS_INPUT_DIR)
+        .join(format!("event{}", id))
+        .join("device/device/hidraw");
+
+    let node = read_dir(&hidraw_dir)
+        .with_context(|| format!("read {:?}", hidraw_dir))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name())
+        .next()
+        .ok_or_else(|| anyhow!("no hidraw node behind event{}", id))?;
+
+    Ok(Path::new("/dev").join(node))
+}
+
+/// open the hidraw node of a DualShock 4 for reading, none if the node is
+/// already read for another event node of the same device, e.g. its touchpad
+fn open_ds4_input(id: u32, devices: &HashMap<u32, DeviceStatus>) -> Result<Option<Ds4Input>> {
+    let path = hidraw_path(id)?;
+    if devices
+        .values()
+        .filter_map(|status| status.ds4.as_ref())
+        .any(|ds4| ds4.path == path)
+    {
+        return Ok(None);
+    }
+
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(&path)
+        .with_context(|| format!("open {:?}", path))?;
+
+    Ok(Some(Ds4Input {
+        path,
+        file,
+        decoder: Ds4Decoder::new(),
+    }))
+}
+
+/// read the pending reports of the hidraw node, a single one per read
+fn read_ds4(id: u32, ds4: &mut Ds4Input) -> io::Result<Vec<Event>> {
+    let mut evts = Vec::new();
+    let mut buf = [0u8; REPORT_BT_LEN];
+
+    loop {
+        let size = match ds4.file.read(&mut buf) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        match ds4.decoder.feed(&buf[..size]) {
+            Ok(ds4_evts) => {
+                let ts = Timestamp::now();
+                evts.extend(ds4_evts.into_iter().map(|e| Event::Ds4(id, e, ts)));
+            }
+            // e.g. the reduced reports sent over Bluetooth until the full ones are requested
+            Err(e) => trace!("report skipped: {:?}", e),
+        }
+    }
+
+    Ok(evts)
+}
+
+/// write an output report through the hidraw node of the HID device behind the event node
+pub(super) fn write_report(id: u32, report: &[u8]) -> Result<()> {
+    let path = hidraw_path(id)?;
+    OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("open {:?}", path))?
+        .write_all(report)
+        .with_context(|| format!("write report into {:?}", path))
+}
+
+/// rumble effect of a device, uploaded through a writable node kept open, as
+/// the effects uploaded through a node are erased once it's closed
+pub(super) struct RumbleEffect {
+    file: File,
+    id: i16,
+}
+
+pub(super) type RumbleEffects = HashMap<u32, RumbleEffect>;
+
+/// upload the rumble as the effect of the device, and play it
+pub(super) fn play_rumble(effects: &mut RumbleEffects, id: u32, rumble: &Rumble) -> Result<()> {
+    // the node is stale if the device was attached again under the same number
+    if let Some(effect) = effects.get_mut(&id) {
+        match upload_rumble(effect, rumble) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                debug!("drop rumble effect of event{}: {:?}", id, e);
+                effects.remove(&id);
+            }
+        }
+    }
+
+    let path = Path::new(INPUT_DIR).join(format!("event{}", id));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("open {:?}", path))?;
+
+    let mut effect = RumbleEffect { file, id: -1 };
+    upload_rumble(&mut effect, rumble)?;
+    effects.insert(id, effect);
+    Ok(())
+}
+
+pub(super) fn stop_rumble(effects: &mut RumbleEffects, id: u32) -> Result<()> {
+    match effects.get(&id) {
+        Some(effect) => write_ff(&effect.file, effect.id, 0).context("stop rumble effect"),
+        None => Ok(()),
+    }
+}
+
+fn upload_rumble(effect: &mut RumbleEffect, rumble: &Rumble) -> Result<()> {
+    let mut raw: libc::ff_effect = unsafe { zeroed() };
+    raw.type_ = FF_RUMBLE;
+    // -1 for a new effect, the kernel assigns its id
+    raw.id = effect.id;
+    // played until stopped when zero
+    raw.replay.length = rumble.duration.as_millis().min(u16::MAX as u128) as u16;
+
+    // the parameters are a union, of which the rumble ones are the head
+    let params = libc::ff_rumble_effect {
+        strong_magnitude: rumble.low,
+        weak_magnitude: rumble.high,
+    };
+    unsafe { (raw.u.as_mut_ptr() as *mut libc::ff_rumble_effect).write(params) };
+
+    sys_upload_ff(effect.file.as_raw_fd(), &mut raw).context("EVIOCSFF")?;
+    effect.id = raw.id;
+
+    write_ff(&effect.file, effect.id, 1).context("play rumble effect")
+}
+
+/// start or stop playing an effect
+fn write_ff(file: &File, effect: i16, value: i32) -> io::Result<()> {
+    let mut raw: libc::input_event = unsafe { zeroed() };
+    raw.type_ = EV_FF;
+    raw.code = effect as u16;
+    raw.value = value;
+
+    let bytes = unsafe {
+        from_raw_parts(
+            &raw as *const libc::input_event as *const u8,
+            size_of::<libc::input_event>(),
+        )
+    };
+
+    let mut file = file;
+    file.write_all(bytes)
+}
+
+#[inline]
+fn event_node_number(path: &Path) -> Option<u32> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix("event")?
+        .parse()
+        .ok()
+}
+
+fn open_device(path: &Path) -> Result<Option<DeviceStatus>> {
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .context("open event node")?;
+
+    let fd = file.as_raw_fd();
+
+    let mut ev_bits = [0u8; EV_MAX as usize / 8 + 1];
+    sys_ioctl_buf(fd, eviocgbit(0, ev_bits.len()), &mut ev_bits).context("EVIOCGBIT")?;
+    if !test_bit(&ev_bits, EV_KEY) && !test_bit(&ev_bits, EV_ABS) {
+        return Ok(None);
+    }
+
+    let mut key_bits = [0u8; KEY_MAX as usize / 8 + 1];
+    if test_bit(&ev_bits, EV_KEY) {
+        sys_ioctl_buf(fd, eviocgbit(EV_KEY, key_bits.len()), &mut key_bits)
+            .context("EVIOCGBIT for EV_KEY")?;
+    }
+
+    let mut abs_bits = [0u8; ABS_MAX as usize / 8 + 1];
+    if test_bit(&ev_bits, EV_ABS) {
+        sys_ioctl_buf(fd, eviocgbit(EV_ABS, abs_bits.len()), &mut abs_bits)
+            .context("EVIOCGBIT for EV_ABS")?;
+    }
+
+    let mut ff_bits = [0u8; FF_MAX as usize / 8 + 1];
+    if test_bit(&ev_bits, EV_FF) {
+        sys_ioctl_buf(fd, eviocgbit(EV_FF, ff_bits.len()), &mut ff_bits)
+            .context("EVIOCGBIT for EV_FF")?;
+    }
+
+    let mut abs = Vec::new();
+    for code in (0..=ABS_MAX).filter(|c| test_bit(&abs_bits, *c)) {
+        let info =
+            sys_get_abs_info(fd, code).with_context(|| format!("EVIOCGABS for abs {:#x}", code))?;
+        abs.push((code, info));
+    }
+
+    let name = sys_get_string(fd, eviocgname).context("EVIOCGNAME")?;
+
+    let mut layout =
+        DeviceLayout::new(name, (0..=KEY_MAX).filter(|c| test_bit(&key_bits, *c)), abs);
+    layout.id = sys_get_input_id(fd).context("EVIOCGID")?;
+    layout.rumble = test_bit(&ff_bits, FF_RUMBLE);
+    // both are missing on some virtual devices
+    layout.phys = sys_get_string(fd, eviocgphys).unwrap_or_default();
+    layout.uniq = sys_get_string(fd, eviocguniq).unwrap_or_default();
+
+    if !layout.is_joystick() {
+        return Ok(None);
+    }
+
+    // kernel timestamps are taken from the realtime clock by default
+    sys_set_clock_id(fd, libc::CLOCK_MONOTONIC).context("EVIOCSCLOCKID")?;
+
+    let decoder = Decoder::new(layout);
+    Ok(Some(DeviceStatus {
+        file,
+        decoder,
+        ds4: None,
+    }))
+}
+
+fn read_device(id: u32, status: &mut DeviceStatus, buf: &mut [u8]) -> io::Result<Vec<Event>> {
+    let mut evts = Vec::new();
+
+    loop {
+        let size = match status.file.read(buf) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        for (ts, diff) in status.decoder.feed_bytes(&buf[..size]) {
+            evts.push(Event::StateDiff {
+                id,
+                is_sink: false,
+                diff,
+                ts,
+            });
+        }
+
+        if status.decoder.needs_resync() {
+            debug!("events dropped, resync");
+            if let Some(diff) = resync_device(status)? {
+                evts.push(Event::StateDiff {
+                    id,
+                    is_sink: false,
+                    diff,
+                    ts: Timestamp::now(),
+                });
+            }
+        }
+    }
+
+    Ok(evts)
+}
+
+fn resync_device(
+    status: &mut DeviceStatus,
+) -> io::Result<Option<crate::driver::StateDiff<super::ButtonBits>>> {
+    let fd = status.file.as_raw_fd();
+
+    let mut key_bits = [0u8; KEY_MAX as usize / 8 + 1];
+    sys_ioctl_buf(fd, eviocgkey(key_bits.len()), &mut key_bits)?;
+
+    let layout = status.decoder.layout();
+    let mut abs_codes: Vec<u16> = layout
+        .axis
+        .iter()
+        .enumerate()
+        .filter(|(_, a)| a.is_some())
+        .filter_map(|(idx, _)| axis_code(AxisIdent::from(idx)))
+        .collect();
+
+    abs_codes.extend(layout.sliders.iter().map(|(code, _)| *code));
+
+    for (idx, hat) in layout.hats.iter().enumerate() {
+        if hat.is_some() {
+            let x = ABS_HAT0X + idx as u16 * 2;
+            abs_codes.extend([x, x + 1]);
+        }
+    }
+
+    let mut abs = Vec::with_capacity(abs_codes.len());
+    for code in abs_codes {
+        abs.push((code, sys_get_abs_info(fd, code)?.value));
+    }
+
+    Ok(status
+        .decoder
+        .sync((0..=KEY_MAX).filter(|c| test_bit(&key_bits, *c)), abs))
+}
+
+#[inline]
+fn test_bit(bits: &[u8], pos: u16) -> bool {
+    bits.get(pos as usize / 8)
+        .map(|b| b & (1 << (pos % 8)) != 0)
+        .unwrap_or(false)
+}
+
+#[inline]
+fn sys_ioctl_buf(fd: c_int, req: c_ulong, buf: &mut [u8]) -> io::Result<usize> {
+    match unsafe { libc::ioctl(fd, req as _, buf.as_mut_ptr()) } {
+        ret if ret < 0 => Err(io::Error::last_os_error()),
+        ret => Ok(ret as usize),
+    }
+}
+
+#[inline]
+fn sys_get_string(fd: c_int, req: fn(usize) -> c_ulong) -> io::Result<String> {
+    let mut buf = [0u8; 256];
+    let len = sys_ioctl_buf(fd, req(buf.len()), &mut buf)?.min(buf.len());
+    Ok(String::from_utf8_lossy(&buf[..len])
+        .trim_end_matches('\0')
+        .to_owned())
+}
+
+fn sys_set_clock_id(fd: c_int, clock_id: c_int) -> io::Result<()> {
+    if unsafe { libc::ioctl(fd, eviocsclockid() as _, &clock_id) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn sys_upload_ff(fd: c_int, effect: &mut libc::ff_effect) -> io::Result<()> {
+    if unsafe { libc::ioctl(fd, eviocsff() as _, effect as *mut libc::ff_effect) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn sys_get_input_id(fd: c_int) -> io::Result<InputId> {
+    let mut raw = libc::input_id {
+        bustype: 0,
+        vendor: 0,
+        product: 0,
+        version: 0,
+    };
+
+    if unsafe { libc::ioctl(fd, eviocgid() as _, &mut raw) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(InputId {
+        bustype: raw.bustype,
+        vendor: raw.vendor,
+        product: raw.product,
+        version: raw.version,
+    })
+}
+
+#[inline]
+fn sys_get_abs_info(fd: c_int, code: u16) -> io::Result<AbsInfo> {
+    let mut raw = libc::input_absinfo {
+        value: 0,
+        minimum: 0,
+        maximum: 0,
+        fuzz: 0,
+        flat: 0,
+        resolution: 0,
+    };
+
+    if unsafe { libc::ioctl(fd, eviocgabs(code) as _, &mut raw) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(AbsInfo {
+        value: raw.value,
+        minimum: raw.minimum,
+        maximum: raw.maximum,
+        fuzz: raw.fuzz,
+        flat: raw.flat,
+        resolution: raw.resolution,
+    })
+}
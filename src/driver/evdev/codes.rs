@@ -0,0 +1,47 @@
+//! event types & codes from linux/input-event-codes.h
+
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_ABS: u16 = 0x03;
+pub const EV_FF: u16 = 0x15;
+pub const EV_MAX: u16 = 0x1f;
+
+pub const SYN_REPORT: u16 = 0;
+pub const SYN_DROPPED: u16 = 3;
+
+pub const BTN_MISC: u16 = 0x100;
+pub const BTN_MOUSE: u16 = 0x110;
+pub const BTN_JOYSTICK: u16 = 0x120;
+pub const BTN_GAMEPAD: u16 = 0x130;
+pub const BTN_DIGI: u16 = 0x140;
+pub const BTN_TRIGGER_HAPPY: u16 = 0x2c0;
+pub const KEY_MAX: u16 = 0x2ff;
+
+pub const ABS_X: u16 = 0x00;
+pub const ABS_Y: u16 = 0x01;
+pub const ABS_Z: u16 = 0x02;
+pub const ABS_RX: u16 = 0x03;
+pub const ABS_RY: u16 = 0x04;
+pub const ABS_RZ: u16 = 0x05;
+pub const ABS_THROTTLE: u16 = 0x06;
+pub const ABS_RUDDER: u16 = 0x07;
+pub const ABS_WHEEL: u16 = 0x08;
+pub const ABS_GAS: u16 = 0x09;
+pub const ABS_BRAKE: u16 = 0x0a;
+pub const ABS_HAT0X: u16 = 0x10;
+pub const ABS_HAT0Y: u16 = 0x11;
+pub const ABS_HAT3Y: u16 = 0x17;
+pub const ABS_MISC: u16 = 0x28;
+pub const ABS_MT_SLOT: u16 = 0x2f;
+pub const ABS_MAX: u16 = 0x3f;
+
+pub const FF_RUMBLE: u16 = 0x50;
+pub const FF_MAX: u16 = 0x7f;
+
+/// whether the key code is treated as a joystick button
+#[inline]
+pub fn is_button(code: u16) -> bool {
+    (BTN_MISC..BTN_MOUSE).contains(&code)
+        || (BTN_JOYSTICK..BTN_DIGI).contains(&code)
+        || (BTN_TRIGGER_HAPPY..=KEY_MAX).contains(&code)
+}
@@ -0,0 +1,763 @@
+use std::{collections::HashMap, io, io::Read, mem::size_of, time::Duration};
+
+use tracing::warn;
+
+use super::{codes::*, ButtonBits};
+use crate::{
+    driver::{
+        Bits, DeviceGuid, DeviceInfo, ObjectStates, SliderInfo, StableId, StateDiff, Timestamp,
+    },
+    AxisIdent, ButtonIdent, DPadState, HatIdent, SliderKind, HAT_LIMIT, SLIDER_LIMIT,
+};
+
+const WORD_SIZE: usize = size_of::<usize>();
+
+/// abs codes of the axis slots
+///
+/// The kernel has no code for the clutch.
+const AXIS_CODES: [(u16, AxisIdent); 11] = [
+    (ABS_X, AxisIdent::X),
+    (ABS_Y, AxisIdent::Y),
+    (ABS_Z, AxisIdent::Z),
+    (ABS_RX, AxisIdent::RX),
+    (ABS_RY, AxisIdent::RY),
+    (ABS_RZ, AxisIdent::RZ),
+    // like the HID throttle usage on windows, the kernel also puts the HID slider usage there
+    (ABS_THROTTLE, AxisIdent::Throttle),
+    (ABS_RUDDER, AxisIdent::Rudder),
+    (ABS_WHEEL, AxisIdent::Steering),
+    (ABS_GAS, AxisIdent::Accelerator),
+    (ABS_BRAKE, AxisIdent::Brake),
+];
+
+/// axis slot of an abs code
+#[inline]
+fn axis_ident(code: u16) -> Option<AxisIdent> {
+    AXIS_CODES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, ident)| *ident)
+}
+
+/// abs code of an axis slot
+#[inline]
+pub fn axis_code(ident: AxisIdent) -> Option<u16> {
+    AXIS_CODES
+        .iter()
+        .find(|(_, i)| *i == ident)
+        .map(|(code, _)| *code)
+}
+
+/// whether the abs code is mapped onto a slider: the codes left undefined after
+/// ABS_BRAKE, and ABS_MISC & the codes after it, where the kernel puts the usages
+/// left once their codes are taken, up to the multi-touch codes
+#[inline]
+fn is_slider(code: u16) -> bool {
+    matches!(code, 0x0b..=0x0f | ABS_MISC..ABS_MT_SLOT)
+}
+
+/// a single `struct input_event` record, as read from an event node
+///
+/// Records are parsed in the layout of the host, as the kernel writes them:
+/// `struct timeval` is two native words, so records are 24 bytes long on
+/// 64 bits hosts and 16 bytes long on 32 bits ones. Recorded event files
+/// are only decoded by hosts of the same word size.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub sec: i64,
+    pub usec: i64,
+    pub typ: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl InputEvent {
+    /// size of the native record, two words of `struct timeval` followed by
+    /// type, code & value; depends on the word size of the host
+    pub const SIZE: usize = WORD_SIZE * 2 + 8;
+
+    /// parse a record from the head of the given bytes
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() < Self::SIZE {
+            return None;
+        }
+
+        let word = |b: &[u8]| -> i64 {
+            if WORD_SIZE == 8 {
+                i64::from_ne_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+            } else {
+                i32::from_ne_bytes([b[0], b[1], b[2], b[3]]) as i64
+            }
+        };
+
+        let rest = &buf[WORD_SIZE * 2..];
+        Some(Self {
+            sec: word(&buf[..WORD_SIZE]),
+            usec: word(&buf[WORD_SIZE..WORD_SIZE * 2]),
+            typ: u16::from_ne_bytes([rest[0], rest[1]]),
+            code: u16::from_ne_bytes([rest[2], rest[3]]),
+            value: i32::from_ne_bytes([rest[4], rest[5], rest[6], rest[7]]),
+        })
+    }
+
+    /// read the next record, `None` on a clean end of stream
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Option<Self>> {
+        let mut buf = [0u8; Self::SIZE];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match r.read(&mut buf[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "truncated input event",
+                    ))
+                }
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(Self::from_bytes(&buf))
+    }
+
+    /// kernel time of the record, monotonic once the clock of the node is set to it
+    pub fn timestamp(&self) -> Timestamp {
+        Timestamp::from_duration(Duration::new(
+            self.sec.max(0) as u64,
+            (self.usec.clamp(0, 999_999) * 1000) as u32,
+        ))
+    }
+}
+
+/// `struct input_absinfo`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AbsInfo {
+    pub value: i32,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    pub resolution: i32,
+}
+
+/// `struct input_id`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InputId {
+    pub bustype: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+}
+
+/// objects of an event node that can be mapped onto joystick objects
+#[derive(Debug, Default, Clone)]
+pub struct DeviceLayout {
+    pub name: String,
+    pub id: InputId,
+    /// key codes of the buttons, in button ident order
+    pub buttons: Vec<u16>,
+    pub axis: [Option<AbsInfo>; AxisIdent::Limit as usize],
+    /// ABS_HAT0X & ABS_HAT0Y up to ABS_HAT3X & ABS_HAT3Y
+    pub hats: [Option<(AbsInfo, AbsInfo)>; HAT_LIMIT],
+    /// abs codes of the sliders in slider ident order, see `is_slider`; dials &
+    /// wheels end up on ABS_RUDDER & ABS_WHEEL, which can't be told apart from
+    /// the simulation controls
+    pub sliders: Vec<(u16, AbsInfo)>,
+    /// whether rumble effects can be uploaded, `FF_RUMBLE`
+    pub rumble: bool,
+    /// physical path of the device, e.g. `usb-0000:00:14.0-2/input0`
+    pub phys: String,
+    /// unique ident of the device, the serial number or the Bluetooth address if any
+    pub uniq: String,
+}
+
+impl DeviceLayout {
+    /// construct a layout from the supported key codes and abs codes of a device
+    pub fn new<K, A>(name: String, keys: K, abs: A) -> Self
+    where
+        K: IntoIterator<Item = u16>,
+        A: IntoIterator<Item = (u16, AbsInfo)>,
+    {
+        let mut buttons: Vec<u16> = keys.into_iter().filter(|c| is_button(*c)).collect();
+        buttons.sort_unstable();
+        buttons.dedup();
+
+        let mut layout = Self {
+            name,
+            buttons,
+            ..Default::default()
+        };
+
+        let mut hat_axis: [[Option<AbsInfo>; 2]; HAT_LIMIT] = Default::default();
+        for (code, info) in abs {
+            match code {
+                _ if is_slider(code) => {
+                    layout.sliders.push((code, info));
+                }
+                ABS_HAT0X..=ABS_HAT3Y => {
+                    let (idx, comp) = hat_position(code);
+                    hat_axis[idx][comp].replace(info);
+                }
+                _ => {
+                    if let Some(ident) = axis_ident(code) {
+                        layout.axis[ident as usize].replace(info);
+                    }
+                }
+            }
+        }
+
+        for (slot, [x, y]) in layout.hats.iter_mut().zip(hat_axis) {
+            if let (Some(x), Some(y)) = (x, y) {
+                slot.replace((x, y));
+            }
+        }
+
+        layout.sliders.sort_unstable_by_key(|(code, _)| *code);
+        layout.sliders.dedup_by_key(|(code, _)| *code);
+        if layout.sliders.len() > SLIDER_LIMIT {
+            warn!(limit = SLIDER_LIMIT, "sliders limit exceeded");
+            layout.sliders.truncate(SLIDER_LIMIT);
+        }
+
+        layout
+    }
+
+    /// whether the device looks like a joystick or a gamepad
+    pub fn is_joystick(&self) -> bool {
+        let has_js_buttons = self.buttons.iter().any(|c| {
+            (BTN_JOYSTICK..BTN_DIGI).contains(c) || (BTN_TRIGGER_HAPPY..=KEY_MAX).contains(c)
+        });
+
+        let has_stick = self.axis[AxisIdent::X as usize].is_some()
+            && self.axis[AxisIdent::Y as usize].is_some();
+
+        has_js_buttons || (has_stick && !self.buttons.is_empty())
+    }
+
+    pub fn info(&self) -> DeviceInfo {
+        let mut info = DeviceInfo {
+            name: self.name.clone(),
+            buttons_num: self.buttons.len(),
+            // hats are numbered by their abs codes
+            hats: self
+                .hats
+                .iter()
+                .rposition(Option::is_some)
+                .map_or(0, |idx| idx + 1),
+            axis: Default::default(),
+            sliders: self
+                .sliders
+                .iter()
+                .map(|(_, abs)| SliderInfo::new(SliderKind::Slider, (abs.minimum, abs.maximum)))
+                .collect(),
+            vendor_id: self.id.vendor,
+            product_id: self.id.product,
+            version: self.id.version,
+            guid: match self.id.vendor {
+                0 => DeviceGuid::from_name(self.id.bustype, &self.name),
+                vendor => {
+                    DeviceGuid::from_ids(self.id.bustype, vendor, self.id.product, self.id.version)
+                }
+            },
+            rumble: self.rumble,
+            stable_id: StableId::new(
+                self.id.vendor,
+                self.id.product,
+                Some(self.uniq.as_str()),
+                &self.phys,
+            ),
+        };
+
+        for (idx, abs) in self.axis.iter().enumerate() {
+            if let Some(abs) = abs {
+                info.axis[idx].replace((abs.minimum, abs.maximum));
+            }
+        }
+
+        info
+    }
+}
+
+/// hat index & component, 0 for X and 1 for Y, of a hat abs code
+#[inline]
+fn hat_position(code: u16) -> (HatIdent, usize) {
+    let offset = (code - ABS_HAT0X) as usize;
+    (offset / 2, offset % 2)
+}
+
+#[inline]
+fn dpad_state(x: i32, y: i32) -> DPadState {
+    match (x.signum(), y.signum()) {
+        (0, -1) => DPadState::Up,
+        (1, -1) => DPadState::UpRight,
+        (1, 0) => DPadState::Right,
+        (1, 1) => DPadState::DownRight,
+        (0, 1) => DPadState::Down,
+        (-1, 1) => DPadState::DownLeft,
+        (-1, 0) => DPadState::Left,
+        (-1, -1) => DPadState::UpLeft,
+        _ => DPadState::Null,
+    }
+}
+
+/// folds `input_event` records of a single device into state diffs, one per `SYN_REPORT`
+pub struct Decoder {
+    layout: DeviceLayout,
+    button_idents: HashMap<u16, ButtonIdent>,
+    hats: [(i32, i32); HAT_LIMIT],
+    pending: ObjectStates<ButtonBits>,
+    reported: ObjectStates<ButtonBits>,
+    dropped: bool,
+    resync: bool,
+    partial: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn new(mut layout: DeviceLayout) -> Self {
+        // buttons beyond the cap are ignored, rather than the whole device
+        if layout.buttons.len() > ButtonBits::CAP {
+            warn!(
+                cap = ButtonBits::CAP,
+                num = layout.buttons.len(),
+                "maximum bits cap exceeded, extra buttons ignored"
+            );
+            layout.buttons.truncate(ButtonBits::CAP);
+        }
+
+        let button_idents = layout
+            .buttons
+            .iter()
+            .enumerate()
+            .map(|(idx, code)| (*code, idx))
+            .collect();
+
+        let mut pending = ObjectStates::<ButtonBits>::default();
+        for (slot, abs) in pending.axis.iter_mut().zip(layout.axis.iter()) {
+            *slot = abs.map(|a| a.value);
+        }
+
+        for (slot, (_, abs)) in pending.sliders.iter_mut().zip(layout.sliders.iter()) {
+            slot.replace(abs.value);
+        }
+
+        let mut hats = [(0, 0); HAT_LIMIT];
+        for (idx, hat) in layout.hats.iter().enumerate() {
+            if let Some((x, y)) = hat {
+                hats[idx] = (x.value, y.value);
+                pending.dpad[idx].replace(dpad_state(x.value, y.value));
+            }
+        }
+
+        Self {
+            layout,
+            button_idents,
+            hats,
+            pending,
+            reported: Default::default(),
+            dropped: false,
+            resync: false,
+            partial: Vec::new(),
+        }
+    }
+
+    pub fn layout(&self) -> &DeviceLayout {
+        &self.layout
+    }
+
+    /// whether events were dropped by the kernel, and the states should be
+    /// re-synchronized with `sync`
+    pub fn needs_resync(&self) -> bool {
+        self.resync
+    }
+
+    /// feed one record, a diff is returned when a report is completed
+    pub fn feed(&mut self, evt: &InputEvent) -> Option<StateDiff<ButtonBits>> {
+        match (evt.typ, evt.code) {
+            (EV_SYN, SYN_DROPPED) => {
+                self.dropped = true;
+                None
+            }
+
+            (EV_SYN, SYN_REPORT) => {
+                if self.dropped {
+                    self.dropped = false;
+                    self.resync = true;
+                    return None;
+                }
+
+                self.report()
+            }
+
+            _ if self.dropped => None,
+
+            (EV_KEY, code) => {
+                self.apply_key(code, evt.value != 0);
+                None
+            }
+
+            (EV_ABS, code) => {
+                self.apply_abs(code, evt.value);
+                None
+            }
+
+            _ => None,
+        }
+    }
+
+    /// feed raw bytes read from an event node, incomplete trailing records are
+    /// kept until the next call; diffs come with the time of their reports
+    pub fn feed_bytes(&mut self, buf: &[u8]) -> Vec<(Timestamp, StateDiff<ButtonBits>)> {
+        self.partial.extend_from_slice(buf);
+
+        let mut diffs = Vec::new();
+        let mut consumed = 0;
+        while let Some(evt) = InputEvent::from_bytes(&self.partial[consumed..]) {
+            consumed += InputEvent::SIZE;
+            if let Some(diff) = self.feed(&evt) {
+                diffs.push((evt.timestamp(), diff));
+            }
+        }
+
+        self.partial.drain(..consumed);
+        diffs
+    }
+
+    /// decode the whole stream, e.g. a recorded event file
+    pub fn decode<R: Read>(
+        &mut self,
+        mut r: R,
+    ) -> io::Result<Vec<(Timestamp, StateDiff<ButtonBits>)>> {
+        let mut diffs = Vec::new();
+        while let Some(evt) = InputEvent::read_from(&mut r)? {
+            if let Some(diff) = self.feed(&evt) {
+                diffs.push((evt.timestamp(), diff));
+            }
+        }
+
+        Ok(diffs)
+    }
+
+    /// replace the states with the given pressed keys & abs values, and report
+    /// the changes
+    pub fn sync<K, A>(&mut self, pressed: K, abs: A) -> Option<StateDiff<ButtonBits>>
+    where
+        K: IntoIterator<Item = u16>,
+        A: IntoIterator<Item = (u16, i32)>,
+    {
+        self.pending.buttons = Default::default();
+        for code in pressed {
+            self.apply_key(code, true);
+        }
+
+        for (code, value) in abs {
+            self.apply_abs(code, value);
+        }
+
+        self.dropped = false;
+        self.resync = false;
+        self.report()
+    }
+
+    fn report(&mut self) -> Option<StateDiff<ButtonBits>> {
+        if self.pending == self.reported {
+            return None;
+        }
+
+        let diff = self.pending.diff(&self.reported);
+        self.reported = self.pending.clone();
+        Some(diff)
+    }
+
+    fn apply_key(&mut self, code: u16, pressed: bool) {
+        if let Some(idx) = self.button_idents.get(&code).cloned() {
+            if pressed {
+                self.pending.buttons.set(idx);
+            } else {
+                self.pending.buttons.clear(idx);
+            }
+        }
+    }
+
+    fn apply_abs(&mut self, code: u16, value: i32) {
+        match code {
+            _ if is_slider(code) => {
+                if let Some(idx) = self.layout.sliders.iter().position(|(c, _)| *c == code) {
+                    self.pending.sliders[idx].replace(value);
+                }
+            }
+
+            ABS_HAT0X..=ABS_HAT3Y => {
+                let (idx, comp) = hat_position(code);
+                if self.pending.dpad[idx].is_none() {
+                    return;
+                }
+
+                let hat = &mut self.hats[idx];
+                if comp == 0 {
+                    hat.0 = value;
+                } else {
+                    hat.1 = value;
+                }
+
+                self.pending.dpad[idx].replace(dpad_state(hat.0, hat.1));
+            }
+
+            _ => {
+                let slot = axis_ident(code).map(|ident| &mut self.pending.axis[ident as usize]);
+                if let Some(slot) = slot.filter(|s| s.is_some()) {
+                    slot.replace(value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn abs(minimum: i32, maximum: i32) -> AbsInfo {
+        AbsInfo {
+            minimum,
+            maximum,
+            ..Default::default()
+        }
+    }
+
+    fn event(typ: u16, code: u16, value: i32) -> InputEvent {
+        InputEvent {
+            typ,
+            code,
+            value,
+            ..Default::default()
+        }
+    }
+
+    fn record(usec: i64, typ: u16, code: u16, value: i32) -> InputEvent {
+        InputEvent {
+            usec,
+            ..event(typ, code, value)
+        }
+    }
+
+    /// native bytes of the records, as read from an event node
+    fn bytes(events: &[InputEvent]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for evt in events {
+            if WORD_SIZE == 8 {
+                buf.extend(evt.sec.to_ne_bytes());
+                buf.extend(evt.usec.to_ne_bytes());
+            } else {
+                buf.extend((evt.sec as i32).to_ne_bytes());
+                buf.extend((evt.usec as i32).to_ne_bytes());
+            }
+            buf.extend(evt.typ.to_ne_bytes());
+            buf.extend(evt.code.to_ne_bytes());
+            buf.extend(evt.value.to_ne_bytes());
+        }
+
+        buf
+    }
+
+    fn gamepad() -> Decoder {
+        Decoder::new(DeviceLayout::new(
+            "gamepad".to_owned(),
+            [BTN_GAMEPAD, BTN_GAMEPAD + 1],
+            [
+                (ABS_X, abs(-128, 127)),
+                (ABS_Y, abs(-128, 127)),
+                (ABS_HAT0X, abs(-1, 1)),
+                (ABS_HAT0X + 1, abs(-1, 1)),
+            ],
+        ))
+    }
+
+    #[test]
+    fn split_records() {
+        let mut decoder = gamepad();
+        let buf = bytes(&[
+            record(100, EV_KEY, BTN_GAMEPAD, 1),
+            record(100, EV_ABS, ABS_X, 50),
+            record(100, EV_SYN, SYN_REPORT, 0),
+            record(200, EV_KEY, BTN_GAMEPAD, 0),
+            record(200, EV_SYN, SYN_REPORT, 0),
+        ]);
+        assert_eq!(buf.len(), InputEvent::SIZE * 5);
+
+        // the report record split across reads
+        let split = InputEvent::SIZE * 2 + 5;
+        assert!(decoder.feed_bytes(&buf[..split]).is_empty());
+
+        let diffs = decoder.feed_bytes(&buf[split..split + 3]);
+        assert!(diffs.is_empty());
+
+        let diffs = decoder.feed_bytes(&buf[split + 3..]);
+        assert_eq!(diffs.len(), 2);
+
+        let (ts, diff) = &diffs[0];
+        assert_eq!(ts.as_duration(), Duration::from_micros(100));
+        assert_eq!(diff.buttons.1.bit(0), Some(true));
+        assert_eq!(diff.axis[AxisIdent::X as usize], Some(50));
+
+        let (ts, diff) = &diffs[1];
+        assert_eq!(ts.as_duration(), Duration::from_micros(200));
+        assert_eq!(diff.buttons.0.bit(0), Some(true));
+        assert_eq!(diff.buttons.1.bit(0), Some(false));
+        assert_eq!(diff.axis[AxisIdent::X as usize], None);
+    }
+
+    #[test]
+    fn truncated_stream() {
+        let buf = bytes(&[
+            record(100, EV_KEY, BTN_GAMEPAD, 1),
+            record(100, EV_SYN, SYN_REPORT, 0),
+        ]);
+
+        assert_eq!(gamepad().decode(&buf[..]).unwrap().len(), 1);
+        assert!(gamepad().decode(&b""[..]).unwrap().is_empty());
+
+        let err = gamepad().decode(&buf[..buf.len() - 3]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut r = &buf[InputEvent::SIZE + 1..];
+        let err = InputEvent::read_from(&mut r).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn hats() {
+        let mut decoder = gamepad();
+        assert_eq!(decoder.layout().info().hats, 1);
+
+        let mut report = |events: &[(u16, i32)]| {
+            for (code, value) in events {
+                decoder.feed(&event(EV_ABS, *code, *value));
+            }
+            decoder
+                .feed(&event(EV_SYN, SYN_REPORT, 0))
+                .map(|diff| diff.dpad)
+        };
+
+        // the initial state is reported with the first change
+        let mut dpad = [None; HAT_LIMIT];
+        dpad[0] = Some(DPadState::Left);
+        assert_eq!(report(&[(ABS_HAT0X, -1)]), Some(dpad));
+
+        dpad[0] = Some(DPadState::UpLeft);
+        assert_eq!(report(&[(ABS_HAT0X + 1, -1)]), Some(dpad));
+
+        dpad[0] = Some(DPadState::Down);
+        assert_eq!(report(&[(ABS_HAT0X, 0), (ABS_HAT0X + 1, 1)]), Some(dpad));
+
+        // hats not present on the device
+        assert_eq!(report(&[(ABS_HAT0X + 2, 1)]), None);
+    }
+
+    #[test]
+    fn dropped_events() {
+        let mut decoder = gamepad();
+        let diffs = decoder.feed_bytes(&bytes(&[
+            record(100, EV_KEY, BTN_GAMEPAD, 1),
+            record(100, EV_SYN, SYN_DROPPED, 0),
+            record(100, EV_KEY, BTN_GAMEPAD + 1, 1),
+            record(100, EV_ABS, ABS_X, 20),
+            record(100, EV_SYN, SYN_REPORT, 0),
+        ]));
+
+        // the report is discarded until the states are read again
+        assert!(diffs.is_empty());
+        assert!(decoder.needs_resync());
+
+        let diff = decoder
+            .sync([BTN_GAMEPAD + 1], [(ABS_X, 30), (ABS_HAT0X, 1)])
+            .unwrap();
+        assert!(!decoder.needs_resync());
+        assert_eq!(diff.buttons.1.bit(0), Some(false));
+        assert_eq!(diff.buttons.1.bit(1), Some(true));
+        assert_eq!(diff.axis[AxisIdent::X as usize], Some(30));
+        assert_eq!(diff.dpad[0], Some(DPadState::Right));
+
+        // back to the reports
+        let diffs = decoder.feed_bytes(&bytes(&[
+            record(300, EV_KEY, BTN_GAMEPAD + 1, 0),
+            record(300, EV_SYN, SYN_REPORT, 0),
+        ]));
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].1.buttons.1.bit(1), Some(false));
+    }
+
+    #[test]
+    fn throttle_axis() {
+        let layout = DeviceLayout::new(
+            "flight stick".to_owned(),
+            [BTN_JOYSTICK],
+            [
+                (ABS_X, abs(0, 1023)),
+                (ABS_Y, abs(0, 1023)),
+                (ABS_THROTTLE, abs(0, 255)),
+                (ABS_MISC, abs(0, 255)),
+            ],
+        );
+
+        let info = layout.info();
+        assert_eq!(info.axis[AxisIdent::Throttle as usize], Some((0, 255)));
+        assert_eq!(
+            info.sliders,
+            [SliderInfo::new(SliderKind::Slider, (0, 255))]
+        );
+
+        let mut decoder = Decoder::new(layout);
+        decoder.feed(&event(EV_ABS, ABS_THROTTLE, 200));
+        decoder.feed(&event(EV_ABS, ABS_MISC, 100));
+        let diff = decoder.feed(&event(EV_SYN, SYN_REPORT, 0)).unwrap();
+
+        assert_eq!(diff.axis[AxisIdent::Throttle as usize], Some(200));
+        assert_eq!(diff.sliders[0], Some(100));
+    }
+
+    #[test]
+    fn sliders() {
+        let layout = DeviceLayout::new(
+            "throttle quadrant".to_owned(),
+            [BTN_JOYSTICK],
+            [
+                (ABS_MISC + 1, abs(0, 1023)),
+                (ABS_MISC, abs(0, 255)),
+                (0x0b, abs(-128, 127)),
+                (ABS_MT_SLOT, abs(0, 9)),
+            ],
+        );
+
+        // in code order, the multi-touch codes left out
+        assert_eq!(
+            layout.info().sliders,
+            [
+                SliderInfo::new(SliderKind::Slider, (-128, 127)),
+                SliderInfo::new(SliderKind::Slider, (0, 255)),
+                SliderInfo::new(SliderKind::Slider, (0, 1023)),
+            ]
+        );
+
+        let mut decoder = Decoder::new(layout);
+        decoder.feed(&event(EV_ABS, ABS_MISC + 1, 1000));
+        decoder.feed(&event(EV_ABS, ABS_MT_SLOT, 1));
+        let diff = decoder.feed(&event(EV_SYN, SYN_REPORT, 0)).unwrap();
+        assert_eq!(diff.sliders[..3], [Some(0), Some(0), Some(1000)]);
+        assert_eq!(diff.sliders[3], None);
+
+        let layout = DeviceLayout::new(
+            "button box".to_owned(),
+            [BTN_JOYSTICK],
+            (0x0b..=0x0f)
+                .chain(ABS_MISC..ABS_MT_SLOT)
+                .map(|code| (code, abs(0, 255))),
+        );
+        assert_eq!(layout.sliders.len(), SLIDER_LIMIT);
+        assert_eq!(
+            layout.sliders.last().map(|(code, _)| *code),
+            Some(ABS_MISC + 2)
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! linux evdev backend, reading `/dev/input/event*` nodes
+//!
+//! The decoding part works on any byte stream of `input_event` records, so
+//! recorded event files can be decoded on every platform.
+
+#[cfg(target_os = "linux")]
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{spawn, JoinHandle},
+};
+
+#[cfg(target_os = "linux")]
+use anyhow::Result;
+#[cfg(target_os = "linux")]
+use crossbeam_channel::{unbounded, Receiver};
+#[cfg(target_os = "linux")]
+use tracing::{debug, warn, warn_span};
+
+#[cfg(target_os = "linux")]
+use crate::driver::{Driver, Event, Rumble, Timestamp};
+
+#[cfg(target_os = "linux")]
+mod api;
+pub mod codes;
+mod decode;
+
+pub use decode::*;
+
+type ButtonBits = crate::driver::B256;
+
+#[cfg(target_os = "linux")]
+pub struct Evdev {
+    ctx: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
+    event_rx: Receiver<Event<u32, ButtonBits>>,
+    effects: Mutex<api::RumbleEffects>,
+}
+
+#[cfg(target_os = "linux")]
+impl Evdev {
+    /// init an Evdev instance with a background thread polling the event nodes
+    pub fn background() -> Result<Self> {
+        let (event_tx, event_rx) = unbounded();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let join = spawn(move || {
+            let _span = warn_span!("event loop").entered();
+            debug!("start");
+            let res = api::start_event_loop(&thread_stop, &event_tx);
+            if let Err(e) = res.as_ref() {
+                warn!("fail: {:?}", e);
+            }
+
+            _ = event_tx.send(Event::Interruption(res, Timestamp::now()));
+            debug!("stop");
+        });
+
+        Ok(Self {
+            ctx: Some((stop, join)),
+            event_rx,
+            effects: Default::default(),
+        })
+    }
+
+    fn cleanup(&mut self) {
+        if let Some((stop, join)) = self.ctx.take() {
+            stop.store(true, Ordering::Release);
+            debug!("cleaned up");
+
+            _ = join.join();
+            debug!("thread joined");
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Evdev {
+    fn drop(&mut self) {
+        self.cleanup();
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Driver for Evdev {
+    type DeviceIdent = u32;
+    type ButtonBits = ButtonBits;
+
+    fn as_event_receiver(&self) -> &Receiver<Event<Self::DeviceIdent, Self::ButtonBits>> {
+        &self.event_rx
+    }
+
+    /// written through the hidraw node of the device, non HID devices have none
+    fn send_report(&self, id: &Self::DeviceIdent, report: &[u8]) -> Result<()> {
+        api::write_report(*id, report)
+    }
+
+    /// uploaded as an `FF_RUMBLE` effect, the kernel stops it after the duration
+    fn rumble(&self, id: &Self::DeviceIdent, rumble: Rumble) -> Result<()> {
+        let mut effects = self.effects.lock().unwrap_or_else(|e| e.into_inner());
+        api::play_rumble(&mut effects, *id, &rumble)
+    }
+
+    fn stop_rumble(&self, id: &Self::DeviceIdent) -> Result<()> {
+        let mut effects = self.effects.lock().unwrap_or_else(|e| e.into_inner());
+        api::stop_rumble(&mut effects, *id)
+    }
+
+    fn close(mut self) {
+        self.cleanup();
+    }
+}
@@ -0,0 +1,144 @@
+//! driver fed by hand & recording the output calls, for tests of applications
+
+use std::{
+    mem::take,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+use super::{Bits, DeviceInfo, Driver, Event, Reconnects, Rumble, Timestamp, B256};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputCall {
+    Report(u64, Vec<u8>),
+    Rumble(u64, Rumble),
+    StopRumble(u64),
+}
+
+/// emits the events it's given, and records the output calls to its attached devices
+///
+/// Output calls to devices not attached fail, as do the rumbles of devices
+/// without `DeviceInfo::rumble`. Clones share the devices, the calls & the
+/// events, e.g. to keep feeding a driver handed over to a wrapper; the events
+/// end once all the clones are closed.
+#[derive(Clone)]
+pub struct Fake<B: Bits = B256> {
+    event_tx: Sender<Event<u64, B>>,
+    event_rx: Receiver<Event<u64, B>>,
+    devices: Arc<Mutex<Vec<(u64, DeviceInfo)>>>,
+    reconnects: Arc<Mutex<Reconnects<u64>>>,
+    calls: Arc<Mutex<Vec<OutputCall>>>,
+}
+
+impl<B: Bits> Default for Fake<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Bits> Fake<B> {
+    pub fn new() -> Self {
+        let (event_tx, event_rx) = unbounded();
+        Self {
+            event_tx,
+            event_rx,
+            devices: Arc::new(Mutex::new(Vec::new())),
+            reconnects: Arc::new(Mutex::new(Reconnects::default())),
+            calls: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// emit the attachment of the device at the current time, a reconnect if
+    /// its stable ident was attached before
+    pub fn attach(&self, id: u64, info: DeviceInfo) {
+        {
+            let mut devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+            devices.retain(|(k, _)| *k != id);
+            devices.push((id, info.clone()));
+        }
+
+        let attachment = self
+            .reconnects
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .attach(&id, &info);
+
+        self.send(Event::Attached(id, info, attachment, Timestamp::now()));
+    }
+
+    /// emit the detachment of the device, at the current time
+    pub fn detach(&self, id: u64) {
+        self.devices
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(k, _)| *k != id);
+        self.reconnects
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .detach(&id);
+
+        self.send(Event::Deattached(id, Timestamp::now()));
+    }
+
+    /// emit any event, e.g. state diffs from `session::DiffRecord::to_diff`;
+    /// attachments emitted this way are not known to the output calls
+    pub fn send(&self, evt: Event<u64, B>) {
+        // the receiver is shared too, the channel can't be broken
+        _ = self.event_tx.send(evt);
+    }
+
+    /// the output calls so far, oldest first
+    pub fn calls(&self) -> Vec<OutputCall> {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// the output calls so far, forgetting them
+    pub fn take_calls(&self) -> Vec<OutputCall> {
+        take(&mut *self.calls.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn record(&self, id: u64, rumble: bool, call: OutputCall) -> Result<()> {
+        let devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        let info = devices
+            .iter()
+            .find(|(k, _)| *k == id)
+            .map(|(_, info)| info)
+            .ok_or_else(|| anyhow!("device {} not attached", id))?;
+
+        if rumble && !info.rumble {
+            return Err(anyhow!("rumble unsupported, device {}", id));
+        }
+
+        self.calls
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(call);
+
+        Ok(())
+    }
+}
+
+impl<B: Bits> Driver for Fake<B> {
+    type DeviceIdent = u64;
+    type ButtonBits = B;
+
+    fn as_event_receiver(&self) -> &Receiver<Event<Self::DeviceIdent, Self::ButtonBits>> {
+        &self.event_rx
+    }
+
+    fn send_report(&self, id: &Self::DeviceIdent, report: &[u8]) -> Result<()> {
+        self.record(*id, false, OutputCall::Report(*id, report.to_vec()))
+    }
+
+    fn rumble(&self, id: &Self::DeviceIdent, rumble: Rumble) -> Result<()> {
+        self.record(*id, true, OutputCall::Rumble(*id, rumble))
+    }
+
+    fn stop_rumble(&self, id: &Self::DeviceIdent) -> Result<()> {
+        self.record(*id, true, OutputCall::StopRumble(*id))
+    }
+
+    fn close(self) {}
+}
@@ -0,0 +1,72 @@
+//! forwarding thread shared by the wrappers observing the events of a driver
+
+use std::thread::{spawn, JoinHandle};
+
+use crossbeam_channel::{unbounded, Receiver};
+use tracing::{debug, Span};
+
+use super::{Driver, Event};
+
+/// forwards the events of the inner driver unchanged, once observed
+///
+/// The events are observed in a thread, in the given span, before being
+/// forwarded; without forwarding, they're only observed and the event
+/// receiver is disconnected right away.
+pub(crate) struct Forwarder<D: Driver> {
+    inner: D,
+    join: JoinHandle<()>,
+    event_rx: Receiver<Event<D::DeviceIdent, D::ButtonBits>>,
+}
+
+impl<D> Forwarder<D>
+where
+    D: Driver,
+    D::DeviceIdent: Send + Sync + 'static,
+    D::ButtonBits: Send + Sync + 'static,
+{
+    pub(crate) fn spawn<F>(inner: D, span: Span, forwarded: bool, mut observe: F) -> Self
+    where
+        F: FnMut(&Event<D::DeviceIdent, D::ButtonBits>) + Send + 'static,
+    {
+        let inner_rx = inner.as_event_receiver().clone();
+        let (event_tx, event_rx) = unbounded();
+        let event_tx = forwarded.then_some(event_tx);
+        let join = spawn(move || {
+            let _span = span.entered();
+            debug!("start");
+            for evt in inner_rx.iter() {
+                observe(&evt);
+
+                if let Some(event_tx) = event_tx.as_ref() {
+                    if event_tx.send(evt).is_err() {
+                        debug!("event chan broken");
+                        break;
+                    }
+                }
+            }
+            debug!("stop");
+        });
+
+        Self {
+            inner,
+            join,
+            event_rx,
+        }
+    }
+}
+
+impl<D: Driver> Forwarder<D> {
+    pub(crate) fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub(crate) fn as_event_receiver(&self) -> &Receiver<Event<D::DeviceIdent, D::ButtonBits>> {
+        &self.event_rx
+    }
+
+    pub(crate) fn close(self) {
+        self.inner.close();
+        _ = self.join.join();
+        debug!("thread joined");
+    }
+}
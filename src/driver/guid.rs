@@ -0,0 +1,113 @@
+use std::{
+    fmt::{self, Debug, Display},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// bus types shared by the linux input subsystem & SDL
+pub const BUS_USB: u16 = 0x03;
+pub const BUS_BLUETOOTH: u16 = 0x05;
+
+/// 16 bytes device GUID in SDL's layout, as used by GameControllerDB
+///
+/// The CRC field is always left empty, like in most of the database entries.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceGuid(pub [u8; 16]);
+
+impl DeviceGuid {
+    /// bus, crc, vendor, 0, product, 0, version, 0, in little endian words
+    pub fn from_ids(bus: u16, vendor: u16, product: u16, version: u16) -> Self {
+        let mut guid = [0u8; 16];
+        for (idx, word) in [bus, 0, vendor, 0, product, 0, version, 0]
+            .into_iter()
+            .enumerate()
+        {
+            guid[idx * 2..idx * 2 + 2].copy_from_slice(&word.to_le_bytes());
+        }
+
+        Self(guid)
+    }
+
+    /// bus & crc followed by the head of the name, for devices without a vendor id
+    pub fn from_name(bus: u16, name: &str) -> Self {
+        let mut guid = [0u8; 16];
+        guid[..2].copy_from_slice(&bus.to_le_bytes());
+        for (dst, src) in guid[4..15].iter_mut().zip(name.bytes()) {
+            *dst = src;
+        }
+
+        Self(guid)
+    }
+
+    #[inline]
+    fn word(&self, idx: usize) -> u16 {
+        u16::from_le_bytes([self.0[idx * 2], self.0[idx * 2 + 1]])
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|b| *b == 0)
+    }
+
+    pub fn bus(&self) -> u16 {
+        self.word(0)
+    }
+
+    /// vendor, product & version, if the guid is in the ids layout
+    pub fn ids(&self) -> Option<(u16, u16, u16)> {
+        let vendor = self.word(2);
+        if vendor == 0 || self.word(3) != 0 || self.word(5) != 0 {
+            return None;
+        }
+
+        Some((vendor, self.word(4), self.word(6)))
+    }
+}
+
+impl Display for DeviceGuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for DeviceGuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DeviceGuid({})", self)
+    }
+}
+
+impl FromStr for DeviceGuid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 32 || !s.is_ascii() {
+            return Err(anyhow!("invalid guid {:?}", s));
+        }
+
+        let mut guid = [0u8; 16];
+        for (idx, b) in guid.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[idx * 2..idx * 2 + 2], 16)
+                .map_err(|_| anyhow!("invalid guid {:?}", s))?;
+        }
+
+        Ok(Self(guid))
+    }
+}
+
+impl Serialize for DeviceGuid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DeviceGuid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    fmt::{self, Debug, Display},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::DeviceInfo;
+
+/// ident of a device that survives reconnects & sessions, zero when unknown
+///
+/// Hashed from the vendor & product ids with the serial number, or with the
+/// interface path for devices without one, which then keep their ident as long
+/// as they are plugged into the same port.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StableId(pub u64);
+
+impl StableId {
+    pub fn new(vendor_id: u16, product_id: u16, serial: Option<&str>, path: &str) -> Self {
+        let (kind, key) = match serial.map(str::trim).filter(|s| !s.is_empty()) {
+            Some(serial) => (b's', serial.to_owned()),
+            // paths differ in case between the APIs of a platform
+            None => (b'p', path.to_lowercase()),
+        };
+
+        // FNV-1a, which unlike the std hashers is the same across releases
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        for b in vendor_id
+            .to_le_bytes()
+            .into_iter()
+            .chain(product_id.to_le_bytes())
+            .chain([kind])
+            .chain(key.bytes())
+        {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+
+        Self(hash)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Display for StableId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl Debug for StableId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "StableId({})", self)
+    }
+}
+
+impl FromStr for StableId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 16 {
+            return Err(anyhow!("invalid stable id {:?}", s));
+        }
+
+        u64::from_str_radix(s, 16)
+            .map(Self)
+            .map_err(|_| anyhow!("invalid stable id {:?}", s))
+    }
+}
+
+// serialized like the GUIDs, which unlike integers can key TOML tables
+impl Serialize for StableId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for StableId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// how an attached device relates to the devices attached before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attachment<DI> {
+    /// not attached before since the driver started
+    New,
+    /// attached before with the same stable ident, under the given device ident
+    Reconnect(DI),
+}
+
+/// remembers the device idents of the stable idents, to tell reconnects apart
+///
+/// Only a stable ident without an attached device is reconnected, devices
+/// sharing one, e.g. with placeholder serial numbers, are new while the others
+/// are attached.
+#[derive(Debug, Clone)]
+pub struct Reconnects<DI> {
+    /// stable ident, last device ident & whether the device is attached
+    known: Vec<(StableId, DI, bool)>,
+}
+
+impl<DI> Default for Reconnects<DI> {
+    fn default() -> Self {
+        Self { known: Vec::new() }
+    }
+}
+
+impl<DI: Clone + PartialEq> Reconnects<DI> {
+    /// record the attachment, devices without a stable ident are always new
+    pub fn attach(&mut self, id: &DI, info: &DeviceInfo) -> Attachment<DI> {
+        // an attachment under the same ident replaces the previous device
+        self.detach(id);
+
+        if info.stable_id.is_zero() {
+            return Attachment::New;
+        }
+
+        let detached = self
+            .known
+            .iter_mut()
+            .find(|(k, _, attached)| *k == info.stable_id && !attached);
+
+        match detached {
+            Some((_, prev, attached)) => {
+                *attached = true;
+                Attachment::Reconnect(std::mem::replace(prev, id.clone()))
+            }
+            None => {
+                self.known.push((info.stable_id, id.clone(), true));
+                Attachment::New
+            }
+        }
+    }
+
+    /// record the detachment, the stable ident of the device can be reconnected
+    pub fn detach(&mut self, id: &DI) {
+        if let Some((_, _, attached)) = self
+            .known
+            .iter_mut()
+            .find(|(_, k, attached)| *attached && k == id)
+        {
+            *attached = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(stable_id: u64) -> DeviceInfo {
+        DeviceInfo {
+            stable_id: StableId(stable_id),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reconnects() {
+        let mut reconnects = Reconnects::default();
+        assert_eq!(reconnects.attach(&1, &info(7)), Attachment::New);
+
+        // another device with the same stable ident while the first is attached
+        assert_eq!(reconnects.attach(&2, &info(7)), Attachment::New);
+
+        reconnects.detach(&1);
+        assert_eq!(reconnects.attach(&3, &info(7)), Attachment::Reconnect(1));
+        assert_eq!(reconnects.attach(&4, &info(7)), Attachment::New);
+
+        reconnects.detach(&2);
+        reconnects.detach(&4);
+        assert_eq!(reconnects.attach(&5, &info(7)), Attachment::Reconnect(2));
+        assert_eq!(reconnects.attach(&6, &info(7)), Attachment::Reconnect(4));
+    }
+
+    #[test]
+    fn reconnects_without_stable_id() {
+        let mut reconnects = Reconnects::default();
+        assert_eq!(reconnects.attach(&1, &info(0)), Attachment::New);
+        reconnects.detach(&1);
+        assert_eq!(reconnects.attach(&1, &info(0)), Attachment::New);
+
+        // attached again without a detachment
+        assert_eq!(reconnects.attach(&2, &info(9)), Attachment::New);
+        assert_eq!(reconnects.attach(&2, &info(9)), Attachment::Reconnect(2));
+    }
+
+    #[test]
+    fn stable_id_str() {
+        let id = StableId::new(0x054c, 0x09cc, Some("a4:ae:12:00:00:01"), "");
+        assert_eq!(id.to_string().parse::<StableId>().unwrap(), id);
+        assert_eq!(
+            serde_json::to_string(&StableId(0xab)).unwrap(),
+            "\"00000000000000ab\""
+        );
+        assert!("ab".parse::<StableId>().is_err());
+    }
+}
@@ -1,85 +1,237 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, time::Duration};
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 use crossbeam_channel::Receiver;
+use serde::{Deserialize, Serialize};
 
-use crate::{AxisIdent, AxisState, DPadState, Joystick, ObjectDiff, SliderState};
+use crate::{
+    hid::ds4::Ds4Event, AxisIdent, AxisState, DPadState, ObjectDiff, Profile, SliderKind,
+    SliderState, HAT_LIMIT, SLIDER_LIMIT,
+};
 
 mod bits;
+pub mod evdev;
+pub mod fake;
+mod forward;
+mod guid;
+mod identity;
+pub mod multi;
+#[cfg(windows)]
 pub mod rawinput;
+pub mod recorder;
+pub mod replay;
+pub mod session;
+#[cfg(feature = "stream")]
+pub mod stream;
+mod time;
+pub mod tracker;
 
 pub use bits::*;
+pub use guid::*;
+pub use identity::*;
+pub use time::*;
 
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct StateDiff<B: Bits> {
-    dpad: Option<DPadState>,
-    buttons: (B, B),
-    axis: [Option<AxisState>; AxisIdent::Limit as usize],
-    slider: Option<SliderState>,
+    pub(crate) dpad: [Option<DPadState>; HAT_LIMIT],
+    /// changed buttons & the current states of all the buttons
+    pub(crate) buttons: (B, B),
+    pub(crate) axis: [Option<AxisState>; AxisIdent::Limit as usize],
+    pub(crate) sliders: [Option<SliderState>; SLIDER_LIMIT],
 }
 
 impl<B: Bits> StateDiff<B> {
-    pub fn diffs<J, const N: usize>(&self, _joy: &J) -> Vec<ObjectDiff>
-    where
-        J: Joystick<N>,
-    {
+    /// changed objects with their raw values, the axis definitions which aren't
+    /// `AxisDef::is_raw` are skipped, use `normalize::Normalizer::diffs` for those
+    pub fn diffs<P: Profile + ?Sized>(&self, profile: &P) -> Vec<ObjectDiff> {
         let axis_count = self.axis.iter().filter(|x| x.is_some()).count();
         let obj_count = self.buttons.0.count_ones() as usize
             + axis_count
-            + self.dpad.as_ref().into_iter().count()
-            + self.slider.as_ref().into_iter().count();
+            + self.dpad.iter().flatten().count()
+            + self.sliders.iter().flatten().count();
 
         let mut obj_diffs = Vec::with_capacity(obj_count);
 
-        if let Some(st) = self.dpad.as_ref().cloned() {
-            obj_diffs.push(ObjectDiff::DPad(st));
+        for (idx, st) in self.dpad.iter().enumerate() {
+            if let Some(st) = st {
+                obj_diffs.push(ObjectDiff::DPad(profile.hat(idx), *st));
+            }
         }
 
-        for (idx, ident) in J::BUTTONS.iter().enumerate() {
-            if let Some(true) = self.buttons.0.bit(idx) {
+        for idx in (0..B::CAP).filter(|i| self.buttons.0.bit(*i).unwrap_or(false)) {
+            if let Some(ident) = profile.button(idx) {
                 obj_diffs.push(ObjectDiff::Button(
-                    *ident,
+                    ident,
                     self.buttons.1.bit(idx).unwrap_or(false).into(),
                 ));
             }
         }
 
-        for (idx, ax) in J::AXIS
+        for (idx, st) in self
+            .axis
             .iter()
             .enumerate()
-            .filter_map(|(i, x)| x.map(|prof| (i, prof)))
+            .filter_map(|(i, x)| x.map(|st| (i, st)))
         {
-            if let Some(st) = self.axis.get(idx).cloned().and_then(|x| x) {
+            for ax in profile.axis(idx.into()).iter().filter(|ax| ax.is_raw()) {
                 obj_diffs.push(ObjectDiff::Axis(ax.typ, st));
             }
         }
 
-        if let Some(st) = self.slider.as_ref().cloned() {
-            obj_diffs.push(ObjectDiff::Slider(st));
+        for (idx, st) in self.sliders.iter().enumerate() {
+            if let Some(st) = st {
+                obj_diffs.push(ObjectDiff::Slider(idx, *st));
+            }
         }
 
         obj_diffs
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct ObjectStates<B> {
+    pub(crate) dpad: [Option<DPadState>; HAT_LIMIT],
+    pub(crate) buttons: B,
+    pub(crate) axis: [Option<AxisState>; AxisIdent::Limit as usize],
+    pub(crate) sliders: [Option<SliderState>; SLIDER_LIMIT],
+}
+
+impl<B: Bits> ObjectStates<B> {
+    /// compare with the previous states and collect the changed objects
+    pub(crate) fn diff(&self, prev: &Self) -> StateDiff<B> {
+        let btns_diff = self.buttons.clone() ^ prev.buttons.clone();
+
+        let mut st_diff = StateDiff {
+            dpad: [None; HAT_LIMIT],
+            buttons: (btns_diff, self.buttons.clone()),
+            axis: [None; AxisIdent::Limit as usize],
+            sliders: [None; SLIDER_LIMIT],
+        };
+
+        for (hidx, hst) in st_diff.dpad.iter_mut().enumerate() {
+            if self.dpad[hidx] != prev.dpad[hidx] {
+                *hst = self.dpad[hidx];
+            }
+        }
+
+        for (sidx, sst) in st_diff.sliders.iter_mut().enumerate() {
+            if self.sliders[sidx] != prev.sliders[sidx] {
+                *sst = self.sliders[sidx];
+            }
+        }
+
+        for (aidx, ast) in st_diff.axis.iter_mut().enumerate() {
+            if self.axis[aidx].is_some() && self.axis[aidx] != prev.axis[aidx] {
+                *ast = self.axis[aidx]
+            }
+        }
+
+        st_diff
+    }
+
+    /// fold a diff into the states
+    pub(crate) fn apply(&mut self, diff: &StateDiff<B>) {
+        for (st, hst) in self.dpad.iter_mut().zip(diff.dpad.iter()) {
+            if hst.is_some() {
+                *st = *hst;
+            }
+        }
+
+        // diffs carry the states of all the buttons
+        self.buttons = diff.buttons.1.clone();
+
+        for (st, ast) in self.axis.iter_mut().zip(diff.axis.iter()) {
+            if ast.is_some() {
+                *st = *ast;
+            }
+        }
+
+        for (st, sst) in self.sliders.iter_mut().zip(diff.sliders.iter()) {
+            if sst.is_some() {
+                *st = *sst;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct DeviceInfo {
     pub name: String,
     pub buttons_num: usize,
-    pub dpad: bool,
+    /// number of hat switches, the first one being the dpad
+    pub hats: usize,
     pub axis: [Option<(i32, i32)>; AxisIdent::Limit as usize],
-    pub slider: Option<(i32, i32)>,
+    /// sliders, dials & wheels, in slider ident order
+    pub sliders: Vec<SliderInfo>,
+    /// zero when unknown
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub version: u16,
+    pub guid: DeviceGuid,
+    /// whether the device can rumble, see `Driver::rumble`
+    pub rumble: bool,
+    pub stable_id: StableId,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SliderInfo {
+    pub kind: SliderKind,
+    pub range: (i32, i32),
+}
+
+impl SliderInfo {
+    pub fn new(kind: SliderKind, range: (i32, i32)) -> Self {
+        Self { kind, range }
+    }
+}
+
+/// events of a driver, each one carrying the time it was captured at
 pub enum Event<DI: Debug + PartialEq, B: Bits> {
-    Attached(DI, DeviceInfo),
-    Deattached(DI),
+    Attached(DI, DeviceInfo, Attachment<DI>, Timestamp),
+    Deattached(DI, Timestamp),
     StateDiff {
         id: DI,
         is_sink: bool,
         diff: StateDiff<B>,
+        ts: Timestamp,
     },
-    Warn(Error),
-    Interruption(Result<()>),
+    /// touchpad, motion & battery changes of a DualShock 4, decoded from its
+    /// full reports by the backends able to read them
+    Ds4(DI, Ds4Event, Timestamp),
+    Warn(Error, Timestamp),
+    Interruption(Result<()>, Timestamp),
+}
+
+impl<DI: Debug + PartialEq, B: Bits> Event<DI, B> {
+    pub fn timestamp(&self) -> Timestamp {
+        match self {
+            Self::Attached(_, _, _, ts)
+            | Self::Deattached(_, ts)
+            | Self::StateDiff { ts, .. }
+            | Self::Ds4(_, _, ts)
+            | Self::Warn(_, ts)
+            | Self::Interruption(_, ts) => *ts,
+        }
+    }
+}
+
+/// strengths of the two rumble motors, the low frequency one being the heavy one
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rumble {
+    pub low: u16,
+    pub high: u16,
+    /// zero to rumble until stopped
+    pub duration: Duration,
+}
+
+impl Rumble {
+    pub fn new(low: u16, high: u16, duration: Duration) -> Self {
+        Self {
+            low,
+            high,
+            duration,
+        }
+    }
 }
 
 pub trait Driver {
@@ -88,5 +240,22 @@ pub trait Driver {
 
     fn as_event_receiver(&self) -> &Receiver<Event<Self::DeviceIdent, Self::ButtonBits>>;
 
+    /// write an output report to an attached device, starting with its report id,
+    /// e.g. one built by `hid::ds4::Ds4Output`
+    fn send_report(&self, id: &Self::DeviceIdent, report: &[u8]) -> Result<()> {
+        _ = report;
+        Err(anyhow!("output reports unsupported, device {:?}", id))
+    }
+
+    /// start rumbling, replacing the ongoing rumble of the device
+    fn rumble(&self, id: &Self::DeviceIdent, rumble: Rumble) -> Result<()> {
+        _ = rumble;
+        Err(anyhow!("rumble unsupported, device {:?}", id))
+    }
+
+    fn stop_rumble(&self, id: &Self::DeviceIdent) -> Result<()> {
+        self.rumble(id, Rumble::default())
+    }
+
     fn close(self);
 }
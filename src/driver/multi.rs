@@ -0,0 +1,431 @@
+//! aggregator merging the events of several drivers, with backend tagged device idents
+
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{spawn, JoinHandle},
+};
+
+use anyhow::{anyhow, Error, Result};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use tracing::{debug, warn_span};
+
+use super::{Attachment, Bits, Driver, Event, Rumble, Timestamp, B256};
+
+/// index of a backend, in the order they were added
+pub type BackendIdent = usize;
+
+/// device idents of the backends, losslessly converted into a common form
+pub trait RawDeviceIdent: Sized {
+    fn to_raw(&self) -> u64;
+
+    fn from_raw(raw: u64) -> Option<Self>;
+}
+
+macro_rules! impl_raw_device_ident {
+    ($($t:ty),*) => {
+        $(
+            impl RawDeviceIdent for $t {
+                #[inline]
+                fn to_raw(&self) -> u64 {
+                    *self as u64
+                }
+
+                #[inline]
+                fn from_raw(raw: u64) -> Option<Self> {
+                    // the round trip of signed idents goes through the bit pattern
+                    let id = raw as $t;
+                    (id.to_raw() == raw).then_some(id)
+                }
+            }
+        )*
+    };
+}
+
+impl_raw_device_ident!(u32, u64, isize);
+
+/// device ident unique across the backends of a `Multi`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceId {
+    pub backend: BackendIdent,
+    /// device ident in the backend, see `RawDeviceIdent`
+    pub raw: u64,
+}
+
+impl DeviceId {
+    /// device ident in the backend, `None` if it doesn't fit the ident type
+    pub fn ident<DI: RawDeviceIdent>(&self) -> Option<DI> {
+        DI::from_raw(self.raw)
+    }
+}
+
+/// a driver behind its raw device idents
+trait Backend {
+    fn send_report(&self, raw: u64, report: &[u8]) -> Result<()>;
+
+    fn rumble(&self, raw: u64, rumble: Rumble) -> Result<()>;
+
+    fn stop_rumble(&self, raw: u64) -> Result<()>;
+
+    fn close(self: Box<Self>);
+}
+
+fn ident<DI: RawDeviceIdent>(raw: u64) -> Result<DI> {
+    DI::from_raw(raw).ok_or_else(|| anyhow!("invalid raw device ident {}", raw))
+}
+
+impl<D> Backend for D
+where
+    D: Driver,
+    D::DeviceIdent: RawDeviceIdent,
+{
+    fn send_report(&self, raw: u64, report: &[u8]) -> Result<()> {
+        Driver::send_report(self, &ident(raw)?, report)
+    }
+
+    fn rumble(&self, raw: u64, rumble: Rumble) -> Result<()> {
+        Driver::rumble(self, &ident(raw)?, rumble)
+    }
+
+    fn stop_rumble(&self, raw: u64) -> Result<()> {
+        Driver::stop_rumble(self, &ident(raw)?)
+    }
+
+    fn close(self: Box<Self>) {
+        Driver::close(*self);
+    }
+}
+
+struct Member {
+    name: String,
+    driver: Option<Box<dyn Backend>>,
+    join: Option<JoinHandle<()>>,
+}
+
+/// merges the events of the added drivers, which share the button bits
+///
+/// Interruptions of a backend are reported as warnings, and a single
+/// interruption is reported once all the backends stopped, with the error of
+/// the last backend interrupted by one if any. Backends are added until the
+/// events are first received, backends stopping before are only counted out
+/// from then on.
+pub struct Multi<B: Bits = B256> {
+    members: Vec<Member>,
+    /// running backends, plus one while they're being added
+    running: Arc<AtomicUsize>,
+    adding: AtomicBool,
+    last_error: Arc<Mutex<Option<Error>>>,
+    event_tx: Sender<Event<DeviceId, B>>,
+    event_rx: Receiver<Event<DeviceId, B>>,
+}
+
+impl<B: Bits + Send + Sync + 'static> Default for Multi<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Bits + Send + Sync + 'static> Multi<B> {
+    pub fn new() -> Self {
+        let (event_tx, event_rx) = unbounded();
+        Self {
+            members: Vec::new(),
+            running: Arc::new(AtomicUsize::new(1)),
+            adding: AtomicBool::new(true),
+            last_error: Arc::new(Mutex::new(None)),
+            event_tx,
+            event_rx,
+        }
+    }
+
+    /// take the driver over, its devices are tagged with the returned backend ident
+    pub fn add<D>(&mut self, name: &str, driver: D) -> BackendIdent
+    where
+        D: Driver<ButtonBits = B> + 'static,
+        D::DeviceIdent: RawDeviceIdent + Send + 'static,
+    {
+        let backend = self.members.len();
+
+        let inner_rx = driver.as_event_receiver().clone();
+        let event_tx = self.event_tx.clone();
+        let running = self.running.clone();
+        running.fetch_add(1, Ordering::SeqCst);
+        let last_error = self.last_error.clone();
+
+        let thread_name = name.to_owned();
+        let join = spawn(move || {
+            let _span = warn_span!("multi", backend, name = %thread_name).entered();
+            debug!("start");
+            if let Err(e) = forward(backend, &thread_name, &inner_rx, &event_tx) {
+                last_error
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .replace(e);
+            }
+
+            stopped(&running, &last_error, &event_tx);
+            debug!("stop");
+        });
+
+        self.members.push(Member {
+            name: name.to_owned(),
+            driver: Some(Box::new(driver)),
+            join: Some(join),
+        });
+
+        backend
+    }
+
+    pub fn backend_name(&self, backend: BackendIdent) -> Option<&str> {
+        self.members.get(backend).map(|m| m.name.as_str())
+    }
+}
+
+impl<B: Bits> Multi<B> {
+    /// stop counting the backends being added, once the events are received
+    fn added(&self) {
+        if self.adding.swap(false, Ordering::SeqCst) {
+            stopped(&self.running, &self.last_error, &self.event_tx);
+        }
+    }
+
+    /// the driver of the backend of the device
+    fn backend(&self, id: &DeviceId) -> Result<&dyn Backend> {
+        let member = self
+            .members
+            .get(id.backend)
+            .ok_or_else(|| anyhow!("unknown backend {}", id.backend))?;
+
+        member
+            .driver
+            .as_deref()
+            .ok_or_else(|| anyhow!("backend {} closed", member.name))
+    }
+
+    fn cleanup(&mut self) {
+        for member in self.members.iter_mut() {
+            if let Some(driver) = member.driver.take() {
+                driver.close();
+                debug!("{} closed", member.name);
+            }
+
+            if let Some(join) = member.join.take() {
+                _ = join.join();
+                debug!("{} thread joined", member.name);
+            }
+        }
+    }
+}
+
+impl<B: Bits> Drop for Multi<B> {
+    fn drop(&mut self) {
+        self.cleanup();
+    }
+}
+
+impl<B: Bits + Send + Sync + 'static> Driver for Multi<B> {
+    type DeviceIdent = DeviceId;
+    type ButtonBits = B;
+
+    fn as_event_receiver(&self) -> &Receiver<Event<Self::DeviceIdent, Self::ButtonBits>> {
+        self.added();
+        &self.event_rx
+    }
+
+    fn send_report(&self, id: &Self::DeviceIdent, report: &[u8]) -> Result<()> {
+        self.backend(id)?.send_report(id.raw, report)
+    }
+
+    fn rumble(&self, id: &Self::DeviceIdent, rumble: Rumble) -> Result<()> {
+        self.backend(id)?.rumble(id.raw, rumble)
+    }
+
+    fn stop_rumble(&self, id: &Self::DeviceIdent) -> Result<()> {
+        self.backend(id)?.stop_rumble(id.raw)
+    }
+
+    fn close(mut self) {
+        self.cleanup();
+    }
+}
+
+/// count a backend out, the interruption is reported once none is left
+fn stopped<B: Bits>(
+    running: &AtomicUsize,
+    last_error: &Mutex<Option<Error>>,
+    event_tx: &Sender<Event<DeviceId, B>>,
+) {
+    if running.fetch_sub(1, Ordering::SeqCst) == 1 {
+        debug!("last backend stopped");
+        let res = match last_error.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        };
+        _ = event_tx.send(Event::Interruption(res, Timestamp::now()));
+    }
+}
+
+/// forward the events of the backend until its interruption, with its error
+fn forward<DI, B>(
+    backend: BackendIdent,
+    name: &str,
+    inner_rx: &Receiver<Event<DI, B>>,
+    event_tx: &Sender<Event<DeviceId, B>>,
+) -> Result<()>
+where
+    DI: Debug + PartialEq + RawDeviceIdent,
+    B: Bits,
+{
+    let tag = |id: &DI| DeviceId {
+        backend,
+        raw: id.to_raw(),
+    };
+
+    for evt in inner_rx.iter() {
+        let evt = match evt {
+            Event::Attached(id, info, attachment, ts) => {
+                let attachment = match attachment {
+                    Attachment::New => Attachment::New,
+                    Attachment::Reconnect(prev) => Attachment::Reconnect(tag(&prev)),
+                };
+
+                Event::Attached(tag(&id), info, attachment, ts)
+            }
+
+            Event::Deattached(id, ts) => Event::Deattached(tag(&id), ts),
+
+            Event::StateDiff {
+                id,
+                is_sink,
+                diff,
+                ts,
+            } => Event::StateDiff {
+                id: tag(&id),
+                is_sink,
+                diff,
+                ts,
+            },
+
+            Event::Ds4(id, evt, ts) => Event::Ds4(tag(&id), evt, ts),
+
+            Event::Warn(e, ts) => Event::Warn(e.context(format!("backend {}", name)), ts),
+
+            Event::Interruption(Ok(()), _) => {
+                debug!("interrupted");
+                return Ok(());
+            }
+
+            Event::Interruption(Err(e), ts) => {
+                let e = e.context(format!("backend {} interrupted", name));
+                // the error itself is kept for the interruption of all the backends
+                _ = event_tx.send(Event::Warn(anyhow!("{:#}", e), ts));
+                return Err(e);
+            }
+        };
+
+        if event_tx.send(evt).is_err() {
+            debug!("event chan broken");
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::driver::fake::Fake;
+
+    fn interrupt(fake: &Fake, res: Result<()>) {
+        fake.send(Event::Interruption(res, Timestamp::now()));
+    }
+
+    fn next(multi: &Multi) -> Event<DeviceId, B256> {
+        multi
+            .as_event_receiver()
+            .recv_timeout(Duration::from_secs(5))
+            .expect("event")
+    }
+
+    #[test]
+    fn interruption_ok() {
+        let (a, b) = (Fake::new(), Fake::new());
+        interrupt(&a, Ok(()));
+        interrupt(&b, Ok(()));
+
+        let mut multi = Multi::new();
+        multi.add("a", a);
+        multi.add("b", b);
+
+        assert!(matches!(next(&multi), Event::Interruption(Ok(()), _)));
+        multi.close();
+    }
+
+    #[test]
+    fn interruption_error() {
+        let (a, b) = (Fake::new(), Fake::new());
+        interrupt(&a, Err(anyhow!("unplugged")));
+        interrupt(&b, Ok(()));
+
+        let mut multi = Multi::new();
+        multi.add("a", a);
+        multi.add("b", b);
+
+        match next(&multi) {
+            Event::Warn(e, _) => assert_eq!(format!("{:#}", e), "backend a interrupted: unplugged"),
+            _ => panic!("warning expected"),
+        }
+
+        // whichever backend stops last
+        match next(&multi) {
+            Event::Interruption(Err(e), _) => {
+                assert_eq!(format!("{:#}", e), "backend a interrupted: unplugged")
+            }
+            _ => panic!("failed interruption expected"),
+        }
+        multi.close();
+    }
+
+    #[test]
+    fn stopped_before_added() {
+        let (a, b) = (Fake::new(), Fake::new());
+        interrupt(&a, Ok(()));
+
+        let mut multi = Multi::new();
+        multi.add("a", a);
+
+        // the first backend stopped before the second one is added
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while multi.running.load(Ordering::SeqCst) > 1 {
+            assert!(Instant::now() < deadline, "backend not stopped");
+            sleep(Duration::from_millis(1));
+        }
+
+        multi.add("b", b.clone());
+        b.attach(7, Default::default());
+        assert!(matches!(
+            next(&multi),
+            Event::Attached(DeviceId { backend: 1, raw: 7 }, ..)
+        ));
+
+        interrupt(&b, Ok(()));
+        assert!(matches!(next(&multi), Event::Interruption(Ok(()), _)));
+        drop(b);
+        multi.close();
+    }
+
+    #[test]
+    fn no_backend() {
+        let multi = Multi::<B256>::new();
+        assert!(matches!(next(&multi), Event::Interruption(Ok(()), _)));
+    }
+}
@@ -1,9 +1,10 @@
 use std::{
     collections::HashMap,
     ffi::c_void,
+    iter::once,
     mem::{replace, size_of},
     slice::from_raw_parts_mut,
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
 use anyhow::{anyhow, Context, Result};
@@ -14,15 +15,17 @@ use windows::{
     core::{Error as wError, HSTRING, PCWSTR},
     Win32::{
         Devices::HumanInterfaceDevice::{
-            HidP_GetButtonCaps, HidP_GetCaps, HidP_GetData, HidP_GetValueCaps, HidP_Input,
-            HidP_MaxDataListLength, HIDP_BUTTON_CAPS, HIDP_CAPS, HIDP_DATA, HIDP_VALUE_CAPS,
-            HID_USAGE_GENERIC_GAMEPAD, HID_USAGE_GENERIC_HATSWITCH, HID_USAGE_GENERIC_JOYSTICK,
-            HID_USAGE_GENERIC_RX, HID_USAGE_GENERIC_RY, HID_USAGE_GENERIC_RZ,
-            HID_USAGE_GENERIC_SLIDER, HID_USAGE_GENERIC_X, HID_USAGE_GENERIC_Y,
-            HID_USAGE_GENERIC_Z, HID_USAGE_PAGE_GENERIC,
+            HidD_GetSerialNumberString, HidP_GetButtonCaps, HidP_GetCaps, HidP_GetData,
+            HidP_GetValueCaps, HidP_Input, HidP_MaxDataListLength, HIDP_BUTTON_CAPS, HIDP_CAPS,
+            HIDP_DATA, HIDP_VALUE_CAPS, HID_USAGE_GENERIC_GAMEPAD, HID_USAGE_GENERIC_HATSWITCH,
+            HID_USAGE_GENERIC_JOYSTICK, HID_USAGE_PAGE_GENERIC,
         },
-        Foundation::{HANDLE, HWND, LPARAM, LRESULT, SUCCESS, WPARAM},
-        System::LibraryLoader::GetModuleHandleW,
+        Foundation::{CloseHandle, HANDLE, HWND, LPARAM, LRESULT, SUCCESS, WPARAM},
+        Storage::FileSystem::{
+            CreateFileW, WriteFile, FILE_ACCESS_FLAGS, FILE_FLAGS_AND_ATTRIBUTES,
+            FILE_GENERIC_WRITE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+        },
+        System::{LibraryLoader::GetModuleHandleW, SystemInformation::GetTickCount64},
         UI::{
             Input::{
                 GetRawInputData, GetRawInputDeviceInfoW, RegisterRawInputDevices, HRAWINPUT,
@@ -42,22 +45,24 @@ use windows::{
 
 use super::ButtonBits;
 use crate::{
-    driver::{Bits, DeviceInfo, StateDiff},
-    AxisIdent, ButtonIdent, DPadState,
+    driver::{
+        Bits, DeviceGuid, DeviceInfo, ObjectStates, Reconnects, SliderInfo, StableId, Timestamp,
+        BUS_BLUETOOTH, BUS_USB,
+    },
+    hid::{
+        axis_ident,
+        ds4::{is_ds4, Ds4Decoder, Ds4Output},
+        dualsense::{is_dualsense, DualSenseOutput},
+        slider_kind, DeviceObjectIndex, HatDecoder,
+    },
+    AxisIdent, SliderKind, HAT_LIMIT, SLIDER_LIMIT,
 };
 
-type Event = crate::driver::Event<isize, u32>;
+type Event = crate::driver::Event<isize, ButtonBits>;
 
 const FAIL: u32 = -1i32 as u32;
 
-const HID_AXIS_USAGES: [u16; 6] = [
-    HID_USAGE_GENERIC_X,
-    HID_USAGE_GENERIC_Y,
-    HID_USAGE_GENERIC_Z,
-    HID_USAGE_GENERIC_RX,
-    HID_USAGE_GENERIC_RY,
-    HID_USAGE_GENERIC_RZ,
-];
+const BT_HID_SERVICE: &str = "00001124-0000-1000-8000-00805f9b34fb";
 
 #[inline]
 unsafe fn get_last_err() -> wError {
@@ -137,36 +142,24 @@ pub(super) unsafe fn close_message_window(hwnd: HWND) -> Result<()> {
 
 #[derive(Default)]
 struct DeviceCap {
-    dpad: Option<HIDP_VALUE_CAPS>,
+    hats: [Option<HIDP_VALUE_CAPS>; HAT_LIMIT],
+    hat_decoders: [Option<HatDecoder>; HAT_LIMIT],
     button_caps: Option<Vec<HIDP_BUTTON_CAPS>>,
     buttons_num: usize,
     axis_caps: [Option<HIDP_VALUE_CAPS>; AxisIdent::Limit as usize],
-    slider: Option<HIDP_VALUE_CAPS>,
+    sliders: [Option<HIDP_VALUE_CAPS>; SLIDER_LIMIT],
+    slider_kinds: [SliderKind; SLIDER_LIMIT],
     mapping: HashMap<u16, DeviceObjectIndex>,
 }
 
-#[derive(Debug)]
-enum DeviceObjectIndex {
-    DPad,
-    Button(ButtonIdent),
-    Axis(AxisIdent),
-    Slider,
-}
-
-#[derive(Debug, Default, PartialEq, Eq)]
-struct DeviceObjectStates {
-    dpad: Option<DPadState>,
-    buttons: ButtonBits,
-    axis: [Option<i32>; AxisIdent::Limit as usize],
-    slider: Option<i32>,
-}
-
 struct DeviceStatus {
     _name: HSTRING,
     max_data_count: u32,
     pre_parsed_data: Vec<u8>,
     cap: DeviceCap,
-    obj_states: DeviceObjectStates,
+    obj_states: ObjectStates<ButtonBits>,
+    /// decoder of the vendor defined data of a DualShock 4
+    ds4: Option<Ds4Decoder>,
 }
 
 pub(super) unsafe fn start_message_loop(hwnd: HWND, event_tx: &Sender<Event>) -> Result<()> {
@@ -174,6 +167,7 @@ pub(super) unsafe fn start_message_loop(hwnd: HWND, event_tx: &Sender<Event>) ->
     debug!("register rawinput events");
 
     let mut devices = HashMap::new();
+    let mut reconnects = Reconnects::default();
 
     loop {
         trace!("waiting for message");
@@ -188,6 +182,8 @@ pub(super) unsafe fn start_message_loop(hwnd: HWND, event_tx: &Sender<Event>) ->
         let _span =
             warn_span!("message", code = msg.message, hwnd = ?msg.hwnd, wparam = ?msg.wParam, lparam = ?msg.lParam).entered();
 
+        let ts = message_timestamp(msg.time);
+
         let event_res = match msg.message {
             WM_CLOSE => {
                 if !DestroyWindow(hwnd).as_bool() {
@@ -196,25 +192,39 @@ pub(super) unsafe fn start_message_loop(hwnd: HWND, event_tx: &Sender<Event>) ->
                 return Ok(());
             }
 
-            WM_INPUT => process_input_message(&mut devices, msg.wParam, msg.lParam)
+            WM_INPUT => process_input_message(&mut devices, msg.wParam, msg.lParam, ts)
                 .context("process input event"),
 
-            WM_INPUT_DEVICE_CHANGE => {
-                process_input_change_message(&mut devices, msg.wParam, msg.lParam)
-                    .context("process input change event")
-            }
-
-            _ => Ok(None),
+            WM_INPUT_DEVICE_CHANGE => process_input_change_message(
+                &mut devices,
+                &mut reconnects,
+                msg.wParam,
+                msg.lParam,
+                ts,
+            )
+            .map(Vec::from_iter)
+            .context("process input change event"),
+
+            _ => Ok(Vec::new()),
         };
 
         DispatchMessageW(&msg);
 
-        if let Some(evt) = event_res.unwrap_or_else(|e| Some(Event::Warn(e))) {
+        for evt in event_res.unwrap_or_else(|e| vec![Event::Warn(e, ts)]) {
             event_tx.send(evt).context("event chan broken")?;
         }
     }
 }
 
+/// extend the 32-bit message time, in milliseconds since the boot like `GetTickCount64`
+#[inline]
+unsafe fn message_timestamp(time: u32) -> Timestamp {
+    let now = GetTickCount64();
+    // messages are never newer than the tick count, even across a wrap of the 32-bit time
+    let age = (now as u32).wrapping_sub(time) as u64;
+    Timestamp::from_duration(Duration::from_millis(now.saturating_sub(age)))
+}
+
 unsafe fn register_events(hwnd: HWND) -> Result<()> {
     let devices_opts = [
         RAWINPUTDEVICE {
@@ -238,10 +248,61 @@ unsafe fn register_events(hwnd: HWND) -> Result<()> {
     Ok(())
 }
 
+/// write an output report into the device file, padded to the output report length of the device
+pub(super) unsafe fn write_report(hdl: HANDLE, report: &[u8]) -> Result<()> {
+    let hname = get_device_name(hdl)?;
+
+    let pre_parsed_data_size =
+        sys_get_device_info_size(hdl, RIDI_PREPARSEDDATA).context("get pre parsed data size")?;
+
+    let mut pre_parsed_data = allocate_buffer::<u8>(pre_parsed_data_size);
+    sys_get_device_info(
+        hdl,
+        RIDI_PREPARSEDDATA,
+        pre_parsed_data.as_mut_ptr(),
+        Some(pre_parsed_data_size),
+    )
+    .context("get pre parsed data")?;
+
+    let mut hidp_caps = HIDP_CAPS::default();
+    HidP_GetCaps(pre_parsed_data.as_ptr() as isize, &mut hidp_caps).context("HidP_GetCaps")?;
+
+    let mut buf = report.to_vec();
+    if buf.len() < hidp_caps.OutputReportByteLength as usize {
+        buf.resize(hidp_caps.OutputReportByteLength as usize, 0);
+    }
+
+    let file = CreateFileW(
+        &hname,
+        FILE_GENERIC_WRITE,
+        FILE_SHARE_READ | FILE_SHARE_WRITE,
+        None,
+        OPEN_EXISTING,
+        FILE_FLAGS_AND_ATTRIBUTES(0),
+        HANDLE(0),
+    )
+    .context("open device file")?;
+
+    let res = WriteFile(
+        file,
+        Some(buf.as_ptr() as *const c_void),
+        buf.len() as u32,
+        None,
+        None,
+    )
+    .ok()
+    .context("WriteFile");
+
+    CloseHandle(file);
+    res
+}
+
 unsafe fn process_input_change_message(
     deivces: &mut HashMap<isize, DeviceStatus>,
+    reconnects: &mut Reconnects<isize>,
     wparam: WPARAM,
     lparam: LPARAM,
+    ts: Timestamp,
 ) -> Result<Option<Event>> {
     let _span = warn_span!("input change").entered();
     match wparam.0 as u32 {
@@ -251,8 +312,9 @@ unsafe fn process_input_change_message(
                 if deivces.remove(&lparam.0).is_none() {
                     warn!("no device found on removal");
                 }
+                reconnects.detach(&lparam.0);
 
-                Ok(Some(Event::Deattached(lparam.0)))
+                Ok(Some(Event::Deattached(lparam.0, ts)))
             }
         }
 
@@ -268,12 +330,66 @@ unsafe fn process_input_change_message(
     };
 
     deivces.insert(lparam.0, profile);
+    let attachment = reconnects.attach(&lparam.0, &pub_info);
 
-    Ok(Some(Event::Attached(lparam.0, pub_info)))
+    Ok(Some(Event::Attached(lparam.0, pub_info, attachment, ts)))
 }
 
-unsafe fn get_device(hdl: HANDLE) -> Result<Option<(DeviceInfo, DeviceStatus)>> {
-    // device name
+/// output report of the rumble of the devices known to rumble, see `rumble_capable`;
+/// the sequence number is only used by DualSense reports over Bluetooth
+pub(super) unsafe fn rumble_report(hdl: HANDLE, strong: u8, weak: u8, seq: u8) -> Result<Vec<u8>> {
+    let name = get_device_name(hdl)?.to_string_lossy();
+
+    let mut dev_info = RID_DEVICE_INFO {
+        cbSize: size_of::<RID_DEVICE_INFO>() as u32,
+        ..Default::default()
+    };
+
+    sys_get_device_info(hdl, RIDI_DEVICEINFO, &mut dev_info, None).context("get device info")?;
+    let hid_info = dev_info.Anonymous.hid;
+    let (vendor_id, product_id) = (hid_info.dwVendorId as u16, hid_info.dwProductId as u16);
+    let bluetooth = name.to_ascii_lowercase().contains(BT_HID_SERVICE);
+
+    if is_ds4(vendor_id, product_id) {
+        let output = Ds4Output {
+            rumble: Some((strong, weak)),
+            ..Default::default()
+        };
+
+        return Ok(if bluetooth {
+            output.bt_report().to_vec()
+        } else {
+            output.usb_report().to_vec()
+        });
+    }
+
+    if is_dualsense(vendor_id, product_id) {
+        let output = DualSenseOutput {
+            rumble: Some((strong, weak)),
+            seq,
+            ..Default::default()
+        };
+
+        return Ok(if bluetooth {
+            output.bt_report().to_vec()
+        } else {
+            output.usb_report().to_vec()
+        });
+    }
+
+    Err(anyhow!(
+        "rumble unsupported, device {:04x}:{:04x}",
+        vendor_id,
+        product_id
+    ))
+}
+
+#[inline]
+fn rumble_capable(vendor_id: u16, product_id: u16) -> bool {
+    is_ds4(vendor_id, product_id) || is_dualsense(vendor_id, product_id)
+}
+
+unsafe fn get_device_name(hdl: HANDLE) -> Result<HSTRING> {
     let mut name_buf = [0u16; 1024];
     let name_buf_size = name_buf.len();
     let name_buf_used = sys_get_device_info(
@@ -284,8 +400,44 @@ unsafe fn get_device(hdl: HANDLE) -> Result<Option<(DeviceInfo, DeviceStatus)>>
     )
     .context("get device name")?;
 
-    let hname = HSTRING::from_wide(&name_buf[..name_buf_used.min(name_buf_size)])
-        .context("construct device name string")?;
+    HSTRING::from_wide(&name_buf[..name_buf_used.min(name_buf_size)])
+        .context("construct device name string")
+}
+
+/// serial number of the HID device, none if it has no serial number or it can't be read
+unsafe fn get_serial_number(hname: &HSTRING) -> Option<String> {
+    // no access is needed to query the attributes, even of devices opened exclusively
+    let file = CreateFileW(
+        hname,
+        FILE_ACCESS_FLAGS(0),
+        FILE_SHARE_READ | FILE_SHARE_WRITE,
+        None,
+        OPEN_EXISTING,
+        FILE_FLAGS_AND_ATTRIBUTES(0),
+        HANDLE(0),
+    )
+    .ok()?;
+
+    // the limit of the string is 126 wide chars
+    let mut buf = [0u16; 128];
+    let ok = HidD_GetSerialNumberString(
+        file,
+        buf.as_mut_ptr() as *mut c_void,
+        (buf.len() * size_of::<u16>()) as u32,
+    )
+    .as_bool();
+
+    CloseHandle(file);
+    if !ok {
+        return None;
+    }
+
+    let len = buf.iter().position(|c| *c == 0).unwrap_or(buf.len());
+    Some(String::from_utf16_lossy(&buf[..len]))
+}
+
+unsafe fn get_device(hdl: HANDLE) -> Result<Option<(DeviceInfo, DeviceStatus)>> {
+    let hname = get_device_name(hdl)?;
 
     // device info
     let mut dev_info = RID_DEVICE_INFO {
@@ -330,12 +482,38 @@ unsafe fn get_device(hdl: HANDLE) -> Result<Option<(DeviceInfo, DeviceStatus)>>
         None => return Ok(None),
     };
 
+    let name = hname.to_string_lossy();
+    let hid_info = dev_info.Anonymous.hid;
+    let (vendor_id, product_id, version) = (
+        hid_info.dwVendorId as u16,
+        hid_info.dwProductId as u16,
+        hid_info.dwVersionNumber as u16,
+    );
+
+    // devices over bluetooth are exposed with the HID service class in their paths
+    let bus = if name.to_ascii_lowercase().contains(BT_HID_SERVICE) {
+        BUS_BLUETOOTH
+    } else {
+        BUS_USB
+    };
+
     let mut info = DeviceInfo {
-        name: hname.to_string_lossy(),
         buttons_num: cap.buttons_num,
-        dpad: cap.dpad.is_some(),
+        hats: cap.hats.iter().flatten().count(),
         axis: Default::default(),
-        slider: None,
+        sliders: Vec::new(),
+        vendor_id,
+        product_id,
+        version,
+        guid: DeviceGuid::from_ids(bus, vendor_id, product_id, version),
+        rumble: rumble_capable(vendor_id, product_id),
+        stable_id: StableId::new(
+            vendor_id,
+            product_id,
+            get_serial_number(&hname).as_deref(),
+            &name,
+        ),
+        name,
     };
 
     for (idx, value) in cap.axis_caps.iter().enumerate() {
@@ -350,9 +528,13 @@ unsafe fn get_device(hdl: HANDLE) -> Result<Option<(DeviceInfo, DeviceStatus)>>
         }
     }
 
-    if let Some(val_caps) = cap.slider.as_ref() {
-        info.slider
-            .replace((val_caps.LogicalMin, val_caps.LogicalMax));
+    for (val_caps, kind) in cap.sliders.iter().zip(cap.slider_kinds.iter()) {
+        if let Some(val_caps) = val_caps {
+            info.sliders.push(SliderInfo::new(
+                *kind,
+                (val_caps.LogicalMin, val_caps.LogicalMax),
+            ));
+        }
     }
 
     let status = DeviceStatus {
@@ -361,6 +543,7 @@ unsafe fn get_device(hdl: HANDLE) -> Result<Option<(DeviceInfo, DeviceStatus)>>
         pre_parsed_data,
         cap,
         obj_states: Default::default(),
+        ds4: is_ds4(vendor_id, product_id).then(Ds4Decoder::new),
     };
 
     Ok(Some((info, status)))
@@ -463,15 +646,19 @@ unsafe fn get_device_cap(pre_parsed_data_ptr: isize) -> Result<Option<DeviceCap>
             }
         }
 
-        let buttons_num = dev_cap.mapping.len();
+        let mut buttons_num = dev_cap.mapping.len();
 
+        // buttons beyond the cap are ignored, rather than the whole device
         if buttons_num > ButtonBits::CAP {
             warn!(
                 cap = ButtonBits::CAP,
                 num = buttons_num,
-                "input button caps: maximum bits cap exceeded",
+                "input button caps: maximum bits cap exceeded, extra buttons ignored",
             );
-            return Ok(None);
+            dev_cap.mapping.retain(
+                |_, obj| !matches!(obj, DeviceObjectIndex::Button(idx) if *idx >= ButtonBits::CAP),
+            );
+            buttons_num = ButtonBits::CAP;
         }
 
         dev_cap.button_caps.replace(button_caps);
@@ -505,41 +692,69 @@ unsafe fn get_device_cap(pre_parsed_data_ptr: isize) -> Result<Option<DeviceCap>
             };
 
             let object = match (cap.UsagePage, usage) {
-                (HID_USAGE_PAGE_GENERIC, HID_USAGE_GENERIC_SLIDER) => {
-                    Some((&mut dev_cap.slider, DeviceObjectIndex::Slider))
-                }
-
                 (HID_USAGE_PAGE_GENERIC, HID_USAGE_GENERIC_HATSWITCH) => {
-                    if !(cap.LogicalMin == 0 && cap.LogicalMax == 7) {
-                        warn!(
-                            min = cap.LogicalMin,
-                            max = cap.LogicalMax,
-                            "unexpected value range for hat"
-                        );
-                        None
-                    } else {
-                        Some((&mut dev_cap.dpad, DeviceObjectIndex::DPad))
+                    let decoder = HatDecoder::new(
+                        (cap.LogicalMin, cap.LogicalMax),
+                        (cap.PhysicalMin, cap.PhysicalMax),
+                        cap.Units,
+                    );
+
+                    let slot = dev_cap
+                        .hats
+                        .iter_mut()
+                        .enumerate()
+                        .find(|(_, s)| s.is_none());
+
+                    match (decoder, slot) {
+                        (None, _) => {
+                            warn!(
+                                min = cap.LogicalMin,
+                                max = cap.LogicalMax,
+                                unit = cap.Units,
+                                "unexpected value range for hat"
+                            );
+                            None
+                        }
+
+                        (Some(decoder), Some((idx, slot))) => {
+                            dev_cap.hat_decoders[idx].replace(decoder);
+                            Some((slot, DeviceObjectIndex::DPad(idx)))
+                        }
+
+                        (Some(_), None) => {
+                            warn!(limit = HAT_LIMIT, "hats limit exceeded");
+                            None
+                        }
                     }
                 }
 
-                (HID_USAGE_PAGE_GENERIC, usage) if HID_AXIS_USAGES.contains(&usage) => {
-                    let idx = match usage {
-                        HID_USAGE_GENERIC_X => AxisIdent::X,
-                        HID_USAGE_GENERIC_Y => AxisIdent::Y,
-                        HID_USAGE_GENERIC_Z => AxisIdent::Z,
-                        HID_USAGE_GENERIC_RX => AxisIdent::RX,
-                        HID_USAGE_GENERIC_RY => AxisIdent::RY,
-                        HID_USAGE_GENERIC_RZ => AxisIdent::RZ,
-                        _ => unreachable!("unexpected usage id {} for axis", usage),
-                    };
-
-                    dev_cap
+                (upage, uid) => match (axis_ident(upage, uid), slider_kind(upage, uid)) {
+                    (Some(idx), _) => dev_cap
                         .axis_caps
                         .get_mut(idx as usize)
-                        .map(|slot| (slot, DeviceObjectIndex::Axis(idx)))
-                }
+                        .map(|slot| (slot, DeviceObjectIndex::Axis(idx))),
+
+                    (None, Some(kind)) => {
+                        let slot = dev_cap
+                            .sliders
+                            .iter_mut()
+                            .enumerate()
+                            .find(|(_, s)| s.is_none());
+
+                        match slot {
+                            Some((idx, slot)) => {
+                                dev_cap.slider_kinds[idx] = kind;
+                                Some((slot, DeviceObjectIndex::Slider(idx)))
+                            }
+                            None => {
+                                warn!(limit = SLIDER_LIMIT, "sliders limit exceeded");
+                                None
+                            }
+                        }
+                    }
 
-                (_upage, _uid) => None,
+                    (None, None) => None,
+                },
             };
 
             let _span = warn_span!("value caps", page = cap.UsagePage, usage, di);
@@ -574,7 +789,8 @@ unsafe fn process_input_message(
     devices: &mut HashMap<isize, DeviceStatus>,
     wparam: WPARAM,
     lparam: LPARAM,
-) -> Result<Option<Event>> {
+    ts: Timestamp,
+) -> Result<Vec<Event>> {
     let is_sink = match wparam.0 as u32 {
         RIM_INPUT => false,
 
@@ -582,11 +798,11 @@ unsafe fn process_input_message(
 
         _other => {
             warn!("unexpected wparam code");
-            return Ok(None);
+            return Ok(Vec::new());
         }
     };
 
-    get_input_event(devices, is_sink, lparam)
+    get_input_event(devices, is_sink, lparam, ts)
 }
 
 #[inline]
@@ -641,13 +857,14 @@ unsafe fn get_input_event(
     devices: &mut HashMap<isize, DeviceStatus>,
     is_sink: bool,
     hdl: LPARAM,
-) -> Result<Option<Event>> {
+    ts: Timestamp,
+) -> Result<Vec<Event>> {
     let mut raw_data_bytes = get_raw_input_data(hdl.0)?;
     let raw_data_ptr = raw_data_bytes.as_mut_ptr() as *mut RAWINPUT;
     let raw_data = &mut *raw_data_ptr;
 
     if raw_data.header.dwType != RIM_TYPEHID.0 {
-        return Ok(None);
+        return Ok(Vec::new());
     }
 
     let hdev = raw_data.header.hDevice.0;
@@ -655,17 +872,26 @@ unsafe fn get_input_event(
         .get_mut(&hdev)
         .ok_or_else(|| anyhow!("device info for {} not found", hdev))?;
 
-    let mut new_states = DeviceObjectStates::default();
+    let mut new_states = ObjectStates::<ButtonBits>::default();
 
     let report_size = (raw_data.data.hid.dwCount * raw_data.data.hid.dwSizeHid) as usize;
     let reports = from_raw_parts_mut(raw_data.data.hid.bRawData.as_mut_ptr(), report_size);
 
     let mut data_buf = allocate_buffer::<HIDP_DATA>(dev_status.max_data_count as usize);
+    let mut ds4_evts = Vec::new();
 
     for (chunk_idx, chunk) in reports
         .chunks_mut(raw_data.data.hid.dwSizeHid as usize)
         .enumerate()
     {
+        if let Some(decoder) = dev_status.ds4.as_mut() {
+            match decoder.feed(chunk) {
+                Ok(evts) => ds4_evts.extend(evts.into_iter().map(|e| Event::Ds4(hdev, e, ts))),
+                // e.g. the reduced reports sent over Bluetooth until the full ones are requested
+                Err(e) => trace!("report chunk {} skipped: {:?}", chunk_idx, e),
+            }
+        }
+
         let data_count =
             sys_hidp_get_data(dev_status, chunk, &mut data_buf).with_context(|| {
                 format!(
@@ -687,20 +913,11 @@ unsafe fn get_input_event(
                 warn_span!("data value", data_idx = data.DataIndex, ?obj_idx).entered();
 
             match obj_idx {
-                DeviceObjectIndex::DPad => {
-                    let st = match data.Anonymous.RawValue {
-                        0 => DPadState::Up,
-                        1 => DPadState::UpRight,
-                        2 => DPadState::Right,
-                        3 => DPadState::DownRight,
-                        4 => DPadState::Down,
-                        5 => DPadState::DownLeft,
-                        6 => DPadState::Left,
-                        7 => DPadState::UpLeft,
-                        _other => DPadState::Null,
-                    };
-
-                    new_states.dpad.replace(st);
+                DeviceObjectIndex::DPad(idx) => {
+                    if let Some(decoder) = dev_status.cap.hat_decoders[*idx].as_ref() {
+                        new_states.dpad[*idx]
+                            .replace(decoder.decode(data.Anonymous.RawValue as i32));
+                    }
                 }
 
                 DeviceObjectIndex::Button(idx) => {
@@ -715,46 +932,24 @@ unsafe fn get_input_event(
                     }
                 }
 
-                DeviceObjectIndex::Slider => {
-                    new_states.slider.replace(data.Anonymous.RawValue as i32);
+                DeviceObjectIndex::Slider(idx) => {
+                    new_states.sliders[*idx].replace(data.Anonymous.RawValue as i32);
                 }
             }
         }
     }
 
     let prev_state = replace(&mut dev_status.obj_states, new_states);
-    let btns_diff = dev_status.obj_states.buttons ^ prev_state.buttons;
-
-    let mut st_diff = StateDiff {
-        dpad: None,
-        buttons: (btns_diff, dev_status.obj_states.buttons),
-        axis: [None; AxisIdent::Limit as usize],
-        slider: None,
-    };
-
-    if dev_status.obj_states.dpad != prev_state.dpad {
-        st_diff.dpad = dev_status.obj_states.dpad;
-    }
-
-    if dev_status.obj_states.slider != prev_state.slider {
-        st_diff.slider = dev_status.obj_states.slider;
-    }
-
-    for (aidx, ast) in st_diff.axis.iter_mut().enumerate() {
-        if dev_status.obj_states.axis[aidx].is_some()
-            && dev_status.obj_states.axis[aidx] != prev_state.axis[aidx]
-        {
-            *ast = dev_status.obj_states.axis[aidx]
-        }
-    }
+    let st_diff = dev_status.obj_states.diff(&prev_state);
 
     let evt = Event::StateDiff {
         id: hdev,
         is_sink,
         diff: st_diff,
+        ts,
     };
 
-    Ok(Some(evt))
+    Ok(once(evt).chain(ds4_evts).collect())
 }
 
 #[inline]
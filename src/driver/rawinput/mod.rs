@@ -1,19 +1,34 @@
-use std::thread::{spawn, JoinHandle};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread::{sleep, spawn, JoinHandle},
+};
 
 use anyhow::{Context, Result};
 use crossbeam_channel::{bounded, unbounded, Receiver};
 use tracing::{debug, warn, warn_span};
-use windows::Win32::Foundation::HWND;
+use windows::Win32::Foundation::{HANDLE, HWND};
 
-use crate::driver::{Driver, Event};
+use crate::driver::{Driver, Event, Rumble, Timestamp, B256};
 
 mod api;
 
-type ButtonBits = u32;
+type ButtonBits = B256;
+
+type Rumbles = Arc<Mutex<HashMap<isize, RumbleState>>>;
+
+#[derive(Debug, Default)]
+struct RumbleState {
+    /// number of the latest rumble, for the timers to stop only their own
+    num: u64,
+    /// sequence number of the next DualSense report over Bluetooth
+    seq: u8,
+}
 
 pub struct RawInput {
     ctx: Option<(HWND, JoinHandle<()>)>,
-    event_rx: Receiver<Event<isize, u32>>,
+    event_rx: Receiver<Event<isize, ButtonBits>>,
+    rumbles: Rumbles,
 }
 
 impl RawInput {
@@ -40,7 +55,7 @@ impl RawInput {
                 warn!("fail: {:?}", e);
             }
 
-            _ = event_tx.send(Event::Interruption(res));
+            _ = event_tx.send(Event::Interruption(res, Timestamp::now()));
             debug!("stop");
         });
 
@@ -52,6 +67,7 @@ impl RawInput {
         Ok(Self {
             ctx: Some((hwnd, join)),
             event_rx,
+            rumbles: Default::default(),
         })
     }
 
@@ -82,7 +98,59 @@ impl Driver for RawInput {
         &self.event_rx
     }
 
+    fn send_report(&self, id: &Self::DeviceIdent, report: &[u8]) -> Result<()> {
+        unsafe { api::write_report(HANDLE(*id), report) }
+    }
+
+    /// through the output reports of the devices known to rumble, stopped by a timer
+    fn rumble(&self, id: &Self::DeviceIdent, rumble: Rumble) -> Result<()> {
+        let num = {
+            let mut rumbles = self.rumbles.lock().unwrap_or_else(|e| e.into_inner());
+            let st = rumbles.entry(*id).or_default();
+            st.num += 1;
+            st.num
+        };
+
+        set_rumble(&self.rumbles, *id, rumble.low, rumble.high)?;
+        if rumble.duration.is_zero() {
+            return Ok(());
+        }
+
+        let (id, rumbles) = (*id, self.rumbles.clone());
+        spawn(move || {
+            sleep(rumble.duration);
+            let latest = rumbles
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&id)
+                .map(|st| st.num);
+
+            if latest == Some(num) {
+                if let Err(e) = set_rumble(&rumbles, id, 0, 0) {
+                    warn!("stop rumble of {:#x}: {:?}", id, e);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
     fn close(mut self) {
         self.cleanup();
     }
 }
+
+fn set_rumble(rumbles: &Rumbles, id: isize, low: u16, high: u16) -> Result<()> {
+    let seq = {
+        let mut rumbles = rumbles.lock().unwrap_or_else(|e| e.into_inner());
+        let st = rumbles.entry(id).or_default();
+        let seq = st.seq;
+        st.seq = (seq + 1) & 0x0f;
+        seq
+    };
+
+    unsafe {
+        let report = api::rumble_report(HANDLE(id), (low >> 8) as u8, (high >> 8) as u8, seq)?;
+        api::write_report(HANDLE(id), &report)
+    }
+}
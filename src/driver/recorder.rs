@@ -0,0 +1,292 @@
+//! recorder wrapping any driver, writing its events into a session file
+
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use crossbeam_channel::Receiver;
+use tracing::{warn, warn_span};
+
+use super::{
+    forward::Forwarder,
+    session::{DiffRecord, Record, RecordKind, SessionWriter},
+    Bits, Driver, Event, Rumble, Timestamp,
+};
+
+/// records the events of the inner driver into a session
+///
+/// The recording stops at the first write failure, with a warning, while the
+/// events keep being forwarded.
+pub struct Recorder<D: Driver> {
+    fwd: Forwarder<D>,
+}
+
+impl<D> Recorder<D>
+where
+    D: Driver,
+    D::DeviceIdent: Clone + Send + Sync + 'static,
+    D::ButtonBits: Send + Sync + 'static,
+{
+    /// record into the session file at the given path, the file is truncated
+    pub fn create<P: AsRef<Path>>(inner: D, path: P) -> Result<Self> {
+        let file = File::create(path.as_ref())
+            .with_context(|| format!("create session file {:?}", path.as_ref()))?;
+
+        Self::new(inner, BufWriter::new(file))
+    }
+
+    pub fn new<W: Write + Send + 'static>(inner: D, w: W) -> Result<Self> {
+        let mut recording = Recording {
+            writer: Some(SessionWriter::new(w).context("init session writer")?),
+            origin: None,
+            numbers: DeviceNumbers {
+                next: 0,
+                known: Vec::new(),
+            },
+        };
+
+        let fwd = Forwarder::spawn(inner, warn_span!("recorder"), true, move |evt| {
+            recording.record(evt)
+        });
+
+        Ok(Self { fwd })
+    }
+}
+
+impl<D> Driver for Recorder<D>
+where
+    D: Driver,
+    D::DeviceIdent: Clone + Send + Sync + 'static,
+    D::ButtonBits: Send + Sync + 'static,
+{
+    type DeviceIdent = D::DeviceIdent;
+    type ButtonBits = D::ButtonBits;
+
+    fn as_event_receiver(&self) -> &Receiver<Event<Self::DeviceIdent, Self::ButtonBits>> {
+        self.fwd.as_event_receiver()
+    }
+
+    fn send_report(&self, id: &Self::DeviceIdent, report: &[u8]) -> Result<()> {
+        self.fwd.inner().send_report(id, report)
+    }
+
+    fn rumble(&self, id: &Self::DeviceIdent, rumble: Rumble) -> Result<()> {
+        self.fwd.inner().rumble(id, rumble)
+    }
+
+    fn stop_rumble(&self, id: &Self::DeviceIdent) -> Result<()> {
+        self.fwd.inner().stop_rumble(id)
+    }
+
+    fn close(self) {
+        self.fwd.close();
+    }
+}
+
+/// assigns session local numbers to device idents
+struct DeviceNumbers<DI> {
+    next: u64,
+    known: Vec<(DI, u64)>,
+}
+
+impl<DI: PartialEq + Clone> DeviceNumbers<DI> {
+    fn get(&mut self, id: &DI, attached: bool) -> u64 {
+        if !attached {
+            if let Some((_, num)) = self.known.iter().find(|(k, _)| k == id) {
+                return *num;
+            }
+        }
+
+        let num = self.next;
+        self.next += 1;
+        self.known.retain(|(k, _)| k != id);
+        self.known.push((id.clone(), num));
+        num
+    }
+}
+
+struct Recording<W: Write, DI> {
+    /// `None` once a write failed
+    writer: Option<SessionWriter<W>>,
+    /// timestamps are recorded relative to the one of the first event
+    origin: Option<Timestamp>,
+    numbers: DeviceNumbers<DI>,
+}
+
+impl<W: Write, DI: Debug + PartialEq + Clone> Recording<W, DI> {
+    fn record<B: Bits>(&mut self, evt: &Event<DI, B>) {
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+
+        let numbers = &mut self.numbers;
+        let ts = evt.timestamp();
+        let ts_us = ts
+            .saturating_duration_since(*self.origin.get_or_insert(ts))
+            .as_micros() as u64;
+        let (device, kind) = match evt {
+            Event::Attached(id, info, ..) => (
+                Some((numbers.get(id, true), id)),
+                RecordKind::Attached { info: info.clone() },
+            ),
+
+            Event::Deattached(id, _) => {
+                (Some((numbers.get(id, false), id)), RecordKind::Deattached)
+            }
+
+            Event::StateDiff {
+                id, is_sink, diff, ..
+            } => (
+                Some((numbers.get(id, false), id)),
+                RecordKind::StateDiff {
+                    is_sink: *is_sink,
+                    diff: DiffRecord::from_diff(diff),
+                },
+            ),
+
+            Event::Ds4(id, event, _) => (
+                Some((numbers.get(id, false), id)),
+                RecordKind::Ds4 { data: *event },
+            ),
+
+            Event::Warn(e, _) => (
+                None,
+                RecordKind::Warn {
+                    message: format!("{:?}", e),
+                },
+            ),
+
+            Event::Interruption(res, _) => (
+                None,
+                RecordKind::Interruption {
+                    error: res.as_ref().err().map(|e| format!("{:?}", e)),
+                },
+            ),
+        };
+
+        let record = Record {
+            ts_us,
+            device: device.map(|(num, _)| num),
+            ident: device
+                .filter(|_| matches!(kind, RecordKind::Attached { .. }))
+                .map(|(_, id)| format!("{:?}", id)),
+            kind,
+        };
+
+        if let Err(e) = writer.write(&record) {
+            warn!("recording stopped: {:?}", e);
+            self.writer = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::driver::{
+        fake::Fake,
+        replay::{Pacing, Replay},
+        session::SessionReader,
+        Attachment, DeviceInfo, StableId, StateDiff, B256,
+    };
+
+    /// session buffer still readable once handed over to the recorder
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap_or_else(|e| e.into_inner()).write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn ts(ms: u64) -> Timestamp {
+        Timestamp::from_duration(Duration::from_millis(ms))
+    }
+
+    fn info() -> DeviceInfo {
+        DeviceInfo {
+            name: "pad".to_owned(),
+            stable_id: StableId(7),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn record() {
+        let buf = Buffer::default();
+        let fake = Fake::<B256>::new();
+        let recorder = Recorder::new(fake.clone(), buf.clone()).unwrap();
+
+        let mut diff = StateDiff::<B256>::default();
+        diff.buttons.0.set(3);
+        diff.buttons.1.set(3);
+
+        fake.send(Event::Attached(10, info(), Attachment::New, ts(1000)));
+        fake.send(Event::StateDiff {
+            id: 10,
+            is_sink: false,
+            diff,
+            ts: ts(1250),
+        });
+        fake.send(Event::Deattached(10, ts(1500)));
+        fake.send(Event::Attached(10, info(), Attachment::New, ts(2000)));
+        fake.send(Event::Deattached(10, ts(2001)));
+
+        // forwarded unchanged
+        let events: Vec<_> = recorder.as_event_receiver().iter().take(5).collect();
+        assert!(matches!(events[0], Event::Attached(10, ..)));
+        assert!(matches!(events[4], Event::Deattached(10, at) if at == ts(2001)));
+
+        drop(fake);
+        recorder.close();
+
+        let buf = buf.0.lock().unwrap().clone();
+        let records: Vec<_> = SessionReader::new(&buf[..])
+            .unwrap()
+            .map(|r| r.map(|r| (r.ts_us, r.device, r.ident)).unwrap())
+            .collect();
+        assert_eq!(
+            records,
+            [
+                (0, Some(0), Some("10".to_owned())),
+                (250_000, Some(0), None),
+                (500_000, Some(0), None),
+                // renumbered once attached again
+                (1_000_000, Some(1), Some("10".to_owned())),
+                (1_001_000, Some(1), None),
+            ]
+        );
+
+        let replay = Replay::<B256>::from_reader(Cursor::new(buf), Pacing::Immediate).unwrap();
+        let events: Vec<_> = replay.as_event_receiver().iter().collect();
+        assert_eq!(events.len(), 6);
+        assert!(
+            matches!(&events[0], Event::Attached(0, info, Attachment::New, _) if info.name == "pad")
+        );
+        assert!(
+            matches!(&events[1], Event::StateDiff { id: 0, diff, .. } if diff.buttons.1.bit(3) == Some(true))
+        );
+        assert!(matches!(events[2], Event::Deattached(0, _)));
+        assert!(matches!(
+            events[3],
+            Event::Attached(1, _, Attachment::Reconnect(0), _)
+        ));
+        assert!(matches!(events[4], Event::Deattached(1, _)));
+        assert!(matches!(events[5], Event::Interruption(Ok(()), _)));
+    }
+}
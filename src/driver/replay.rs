@@ -0,0 +1,323 @@
+//! replay backend, playing back a recorded session
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    thread::{spawn, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use crossbeam_channel::{bounded, unbounded, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use tracing::{debug, warn, warn_span};
+
+use super::{
+    session::{RecordKind, SessionReader},
+    Bits, Driver, Event, Reconnects, Timestamp, B256,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pacing {
+    /// keep the recorded intervals between events
+    RealTime,
+    /// emit events as fast as possible
+    Immediate,
+}
+
+pub struct Replay<B: Bits = B256> {
+    ctx: Option<(Sender<()>, JoinHandle<()>)>,
+    event_rx: Receiver<Event<u64, B>>,
+}
+
+impl<B: Bits + Send + Sync + 'static> Replay<B> {
+    /// init a Replay instance playing back the session file in background
+    pub fn open<P: AsRef<Path>>(path: P, pacing: Pacing) -> Result<Self> {
+        let file = File::open(path.as_ref())
+            .with_context(|| format!("open session file {:?}", path.as_ref()))?;
+
+        Self::from_reader(BufReader::new(file), pacing)
+    }
+
+    pub fn from_reader<R: BufRead + Send + 'static>(r: R, pacing: Pacing) -> Result<Self> {
+        let reader = SessionReader::new(r).context("read session")?;
+
+        let (event_tx, event_rx) = unbounded();
+        let (stop_tx, stop_rx) = bounded(0);
+        let join = spawn(move || {
+            let _span = warn_span!("replay", ?pacing).entered();
+            debug!("start");
+            let res = play(reader, pacing, &stop_rx, &event_tx);
+            if let Err(e) = res.as_ref() {
+                warn!("fail: {:?}", e);
+            }
+
+            _ = event_tx.send(Event::Interruption(res, Timestamp::now()));
+            debug!("stop");
+        });
+
+        Ok(Self {
+            ctx: Some((stop_tx, join)),
+            event_rx,
+        })
+    }
+}
+
+impl<B: Bits> Replay<B> {
+    fn cleanup(&mut self) {
+        if let Some((stop_tx, join)) = self.ctx.take() {
+            drop(stop_tx);
+            debug!("cleaned up");
+
+            _ = join.join();
+            debug!("thread joined");
+        }
+    }
+}
+
+impl<B: Bits> Drop for Replay<B> {
+    fn drop(&mut self) {
+        self.cleanup();
+    }
+}
+
+impl<B: Bits + Send + Sync + 'static> Driver for Replay<B> {
+    type DeviceIdent = u64;
+    type ButtonBits = B;
+
+    fn as_event_receiver(&self) -> &Receiver<Event<Self::DeviceIdent, Self::ButtonBits>> {
+        &self.event_rx
+    }
+
+    fn close(mut self) {
+        self.cleanup();
+    }
+}
+
+fn play<R: BufRead, B: Bits + Send + Sync + 'static>(
+    reader: SessionReader<R>,
+    pacing: Pacing,
+    stop_rx: &Receiver<()>,
+    event_tx: &Sender<Event<u64, B>>,
+) -> Result<()> {
+    let start = Instant::now();
+    // the recorded intervals are kept between the timestamps too
+    let origin = Timestamp::now();
+    let mut reconnects = Reconnects::default();
+
+    for record in reader {
+        let record = record?;
+        let ts = origin + Duration::from_micros(record.ts_us);
+
+        let stopped = match pacing {
+            Pacing::RealTime => {
+                let due = start + Duration::from_micros(record.ts_us);
+                let wait = due.saturating_duration_since(Instant::now());
+                !matches!(stop_rx.recv_timeout(wait), Err(RecvTimeoutError::Timeout))
+            }
+
+            Pacing::Immediate => !matches!(stop_rx.try_recv(), Err(TryRecvError::Empty)),
+        };
+
+        if stopped {
+            debug!("stopped");
+            return Ok(());
+        }
+
+        let device = || {
+            record
+                .device
+                .ok_or_else(|| anyhow!("device absent at {}us", record.ts_us))
+        };
+
+        let evt = match record.kind {
+            RecordKind::Attached { info } => {
+                let id = device()?;
+                let attachment = reconnects.attach(&id, &info);
+                Event::Attached(id, info, attachment, ts)
+            }
+
+            RecordKind::Deattached => {
+                let id = device()?;
+                reconnects.detach(&id);
+                Event::Deattached(id, ts)
+            }
+
+            RecordKind::StateDiff { is_sink, diff } => Event::StateDiff {
+                id: device()?,
+                is_sink,
+                diff: diff
+                    .to_diff()
+                    .with_context(|| format!("convert diff at {}us", record.ts_us))?,
+                ts,
+            },
+
+            RecordKind::Ds4 { data } => Event::Ds4(device()?, data, ts),
+
+            RecordKind::Warn { message } => Event::Warn(anyhow!(message), ts),
+
+            RecordKind::Interruption { error } => {
+                return match error {
+                    Some(e) => Err(anyhow!(e)).context("recorded interruption"),
+                    None => Ok(()),
+                };
+            }
+        };
+
+        event_tx.send(evt).context("event chan broken")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::driver::{
+        session::{DiffRecord, Record, SessionWriter},
+        Attachment, DeviceInfo, StableId,
+    };
+
+    fn record(ts_us: u64, device: Option<u64>, kind: RecordKind) -> Record {
+        Record {
+            ts_us,
+            device,
+            ident: None,
+            kind,
+        }
+    }
+
+    fn attached(ts_us: u64, device: u64) -> Record {
+        let info = DeviceInfo {
+            name: "pad".to_owned(),
+            stable_id: StableId(7),
+            ..Default::default()
+        };
+
+        record(ts_us, Some(device), RecordKind::Attached { info })
+    }
+
+    fn start(records: &[Record]) -> Replay<u32> {
+        let mut buf = Vec::new();
+        let mut session = SessionWriter::new(&mut buf).unwrap();
+        for record in records {
+            session.write(record).unwrap();
+        }
+
+        Replay::from_reader(Cursor::new(buf), Pacing::Immediate).unwrap()
+    }
+
+    fn recv(replay: &Replay<u32>) -> Event<u64, u32> {
+        replay
+            .as_event_receiver()
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+    }
+
+    #[test]
+    fn play() {
+        let diff = DiffRecord {
+            changed: vec![1],
+            pressed: vec![1],
+            ..Default::default()
+        };
+
+        let replay = start(&[
+            attached(0, 0),
+            record(
+                1500,
+                Some(0),
+                RecordKind::StateDiff {
+                    is_sink: false,
+                    diff,
+                },
+            ),
+            record(2000, Some(0), RecordKind::Deattached),
+            attached(3000, 1),
+            record(
+                4000,
+                None,
+                RecordKind::Warn {
+                    message: "lost".to_owned(),
+                },
+            ),
+        ]);
+
+        let first = match recv(&replay) {
+            Event::Attached(0, info, Attachment::New, ts) => {
+                assert_eq!(info.name, "pad");
+                ts
+            }
+            _ => panic!("unexpected event"),
+        };
+
+        match recv(&replay) {
+            Event::StateDiff {
+                id: 0,
+                is_sink: false,
+                diff,
+                ts,
+            } => {
+                assert_eq!(diff.buttons, (0b10, 0b10));
+                assert_eq!(
+                    ts.saturating_duration_since(first),
+                    Duration::from_micros(1500)
+                );
+            }
+            _ => panic!("unexpected event"),
+        }
+
+        assert!(matches!(recv(&replay), Event::Deattached(0, _)));
+        assert!(matches!(
+            recv(&replay),
+            Event::Attached(1, _, Attachment::Reconnect(0), _)
+        ));
+        assert!(matches!(recv(&replay), Event::Warn(e, _) if e.to_string() == "lost"));
+
+        // the end of the session
+        assert!(matches!(recv(&replay), Event::Interruption(Ok(()), _)));
+        assert!(replay.as_event_receiver().recv().is_err());
+    }
+
+    #[test]
+    fn device_absent() {
+        let replay = start(&[attached(0, 0), record(10, None, RecordKind::Deattached)]);
+
+        assert!(matches!(recv(&replay), Event::Attached(0, ..)));
+        match recv(&replay) {
+            Event::Interruption(Err(e), _) => {
+                assert_eq!(e.to_string(), "device absent at 10us")
+            }
+            _ => panic!("unexpected event"),
+        }
+    }
+
+    #[test]
+    fn interruption() {
+        let interruption = |error: Option<&str>| RecordKind::Interruption {
+            error: error.map(str::to_owned),
+        };
+
+        // the records after the recorded interruption are ignored
+        let replay = start(&[
+            attached(0, 0),
+            record(10, None, interruption(Some("device lost"))),
+            attached(20, 1),
+        ]);
+
+        assert!(matches!(recv(&replay), Event::Attached(0, ..)));
+        match recv(&replay) {
+            Event::Interruption(Err(e), _) => {
+                assert_eq!(format!("{:#}", e), "recorded interruption: device lost")
+            }
+            _ => panic!("unexpected event"),
+        }
+        assert!(replay.as_event_receiver().recv().is_err());
+
+        let replay = start(&[record(0, None, interruption(None)), attached(20, 1)]);
+        assert!(matches!(recv(&replay), Event::Interruption(Ok(()), _)));
+        assert!(replay.as_event_receiver().recv().is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! on-disk format of recorded event sessions
+//!
+//! A session file is made of JSON lines, the first one is a header carrying the
+//! format version, and each of the following lines is a single record.
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Bits, DeviceInfo, StateDiff};
+use crate::hid::ds4::Ds4Event;
+use crate::{
+    AxisIdent, AxisState, DPadState, HatIdent, SliderIdent, SliderState, HAT_LIMIT, SLIDER_LIMIT,
+};
+
+pub const SESSION_FORMAT: &str = "joystick-rs-session";
+pub const SESSION_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionHeader {
+    pub format: String,
+    pub version: u32,
+}
+
+impl Default for SessionHeader {
+    fn default() -> Self {
+        Self {
+            format: SESSION_FORMAT.to_owned(),
+            version: SESSION_VERSION,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// offset of the event timestamp since the one of the first record, in microseconds
+    pub ts_us: u64,
+    /// session local number of the device, absent for events not bound to a device
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<u64>,
+    /// debug representation of the device ident in the recorded driver, on attachment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ident: Option<String>,
+    #[serde(flatten)]
+    pub kind: RecordKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RecordKind {
+    Attached {
+        info: DeviceInfo,
+    },
+    Deattached,
+    StateDiff {
+        is_sink: bool,
+        diff: DiffRecord,
+    },
+    Ds4 {
+        data: Ds4Event,
+    },
+    Warn {
+        message: String,
+    },
+    Interruption {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// serializable form of `StateDiff`, buttons are listed by their idents
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffRecord {
+    /// changed hats by their idents
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hats: BTreeMap<HatIdent, DPadState>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pressed: Vec<usize>,
+    #[serde(default)]
+    pub axis: [Option<AxisState>; AxisIdent::Limit as usize],
+    /// changed sliders, dials & wheels by their idents
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sliders: BTreeMap<SliderIdent, SliderState>,
+}
+
+impl DiffRecord {
+    pub fn from_diff<B: Bits>(diff: &StateDiff<B>) -> Self {
+        let ones = |bits: &B| {
+            (0..B::CAP)
+                .filter(|pos| bits.bit(*pos).unwrap_or(false))
+                .collect()
+        };
+
+        Self {
+            hats: diff
+                .dpad
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, st)| st.map(|st| (idx, st)))
+                .collect(),
+            changed: ones(&diff.buttons.0),
+            pressed: ones(&diff.buttons.1),
+            axis: diff.axis,
+            sliders: diff
+                .sliders
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, st)| st.map(|st| (idx, st)))
+                .collect(),
+        }
+    }
+
+    pub fn to_diff<B: Bits>(&self) -> Result<StateDiff<B>> {
+        let bits = |idents: &[usize]| {
+            let mut bits = B::default();
+            for pos in idents {
+                if !bits.set(*pos) {
+                    return Err(anyhow!(
+                        "button ident {} exceeded the maximum bits cap {}",
+                        pos,
+                        B::CAP
+                    ));
+                }
+            }
+
+            Ok(bits)
+        };
+
+        let mut dpad = [None; HAT_LIMIT];
+        for (idx, st) in self.hats.iter() {
+            match dpad.get_mut(*idx) {
+                Some(slot) => {
+                    slot.replace(*st);
+                }
+                None => {
+                    return Err(anyhow!(
+                        "hat ident {} exceeded the limit {}",
+                        idx,
+                        HAT_LIMIT
+                    ))
+                }
+            }
+        }
+
+        let mut sliders = [None; SLIDER_LIMIT];
+        for (idx, st) in self.sliders.iter() {
+            match sliders.get_mut(*idx) {
+                Some(slot) => {
+                    slot.replace(*st);
+                }
+                None => {
+                    return Err(anyhow!(
+                        "slider ident {} exceeded the limit {}",
+                        idx,
+                        SLIDER_LIMIT
+                    ))
+                }
+            }
+        }
+
+        Ok(StateDiff {
+            dpad,
+            buttons: (bits(&self.changed)?, bits(&self.pressed)?),
+            axis: self.axis,
+            sliders,
+        })
+    }
+}
+
+/// reads records from a session file, the header is checked on construction
+pub struct SessionReader<R> {
+    inner: R,
+    line: String,
+    line_no: usize,
+}
+
+impl<R: BufRead> SessionReader<R> {
+    pub fn new(inner: R) -> Result<Self> {
+        let mut reader = Self {
+            inner,
+            line: String::new(),
+            line_no: 0,
+        };
+
+        if !reader.next_line().context("read header")? {
+            return Err(anyhow!("empty session"));
+        }
+
+        let header: SessionHeader =
+            serde_json::from_str(reader.line.trim()).context("parse header")?;
+
+        if header.format != SESSION_FORMAT {
+            return Err(anyhow!("unexpected session format {}", header.format));
+        }
+
+        if header.version != SESSION_VERSION {
+            return Err(anyhow!("unsupported session version {}", header.version));
+        }
+
+        Ok(reader)
+    }
+
+    /// read the next non-empty line into the line buffer
+    fn next_line(&mut self) -> Result<bool> {
+        loop {
+            self.line.clear();
+            self.line_no += 1;
+            if self.inner.read_line(&mut self.line)? == 0 {
+                return Ok(false);
+            }
+
+            if !self.line.trim().is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for SessionReader<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_line() {
+            Ok(true) => Some(
+                serde_json::from_str(self.line.trim())
+                    .with_context(|| format!("parse record at line {}", self.line_no)),
+            ),
+            Ok(false) => None,
+            Err(e) => Some(Err(e).with_context(|| format!("read line {}", self.line_no))),
+        }
+    }
+}
+
+/// writes records to a session file, the header is written on construction
+pub struct SessionWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> SessionWriter<W> {
+    pub fn new(mut inner: W) -> Result<Self> {
+        serde_json::to_writer(&mut inner, &SessionHeader::default()).context("write header")?;
+        inner.write_all(b"\n").context("write header")?;
+        inner.flush().context("flush header")?;
+
+        Ok(Self { inner })
+    }
+
+    /// write a single record, and flush it so that the file is always readable
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        serde_json::to_writer(&mut self.inner, record).context("write record")?;
+        self.inner.write_all(b"\n").context("write record")?;
+        self.inner.flush().context("flush record")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_round_trip() {
+        let mut diff = StateDiff::<u32> {
+            dpad: [None; HAT_LIMIT],
+            buttons: (0b101, 0b100),
+            axis: [None; AxisIdent::Limit as usize],
+            sliders: [None; SLIDER_LIMIT],
+        };
+        diff.dpad[1] = Some(DPadState::Left);
+        diff.axis[AxisIdent::Throttle as usize] = Some(-7);
+        diff.sliders[2] = Some(300);
+
+        let record = DiffRecord::from_diff(&diff);
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["hats"], serde_json::json!({ "1": "Left" }));
+        assert_eq!(json["sliders"], serde_json::json!({ "2": 300 }));
+        assert_eq!(json["axis"].as_array().map(Vec::len), Some(12));
+
+        let record: DiffRecord = serde_json::from_value(json).unwrap();
+        assert_eq!(record.to_diff::<u32>().unwrap(), diff);
+    }
+
+    #[test]
+    fn header_version() {
+        let header =
+            |version: u32| format!(r#"{{"format":"{}","version":{}}}"#, SESSION_FORMAT, version);
+
+        assert!(SessionReader::new(header(SESSION_VERSION).as_bytes()).is_ok());
+        assert!(SessionReader::new(header(SESSION_VERSION + 1).as_bytes()).is_err());
+        assert!(SessionReader::new(&b""[..]).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! futures streams over the events of any driver, behind the `stream` feature
+
+use std::{
+    fmt::Debug,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    thread::{spawn, JoinHandle},
+};
+
+use crossbeam_channel::Receiver;
+use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures_core::Stream;
+use tracing::{debug, warn_span};
+
+use super::{Bits, Driver, Event, Timestamp};
+use crate::{
+    profile::{ProfileRegistry, SharedProfile},
+    ObjectDiff,
+};
+
+/// device streams being fed, none once the forwarding stopped
+type Subscribers<DI> = Arc<Mutex<Option<Vec<(DI, UnboundedSender<(Timestamp, ObjectDiff)>)>>>>;
+
+/// stream of the events of the inner driver, ending once the driver is closed
+///
+/// Events are forwarded by a background thread, and queued from construction
+/// until polled; use `devices_only` when only the streams of devices are used.
+/// Dropping the stream closes the inner driver.
+pub struct EventStream<D: Driver> {
+    /// taken on close
+    inner: Option<D>,
+    join: Option<JoinHandle<()>>,
+    event_rx: UnboundedReceiver<Event<D::DeviceIdent, D::ButtonBits>>,
+    subscribers: Subscribers<D::DeviceIdent>,
+}
+
+impl<D> EventStream<D>
+where
+    D: Driver,
+    D::DeviceIdent: Clone + Send + Sync + 'static,
+    D::ButtonBits: Send + Sync + 'static,
+{
+    /// profiles of the device streams are picked from the registry
+    pub fn new(inner: D, registry: ProfileRegistry) -> Self {
+        Self::spawn(inner, registry, true)
+    }
+
+    /// only feed the streams of devices, the events aren't queued and the
+    /// stream itself ends right away
+    pub fn devices_only(inner: D, registry: ProfileRegistry) -> Self {
+        Self::spawn(inner, registry, false)
+    }
+
+    fn spawn(inner: D, registry: ProfileRegistry, queue: bool) -> Self {
+        let subscribers: Subscribers<_> = Arc::new(Mutex::new(Some(Vec::new())));
+
+        let inner_rx = inner.as_event_receiver().clone();
+        let (event_tx, event_rx) = unbounded();
+        let event_tx = queue.then_some(event_tx);
+        let thread_subscribers = subscribers.clone();
+        let join = spawn(move || {
+            let _span = warn_span!("event stream").entered();
+            debug!("start");
+            forward(&registry, &thread_subscribers, &inner_rx, event_tx.as_ref());
+            // dropping the senders ends the device streams
+            thread_subscribers
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take();
+            debug!("stop");
+        });
+
+        Self {
+            inner: Some(inner),
+            join: Some(join),
+            event_rx,
+            subscribers,
+        }
+    }
+
+    /// stream of the object diffs of the device, with its profile applied
+    ///
+    /// The device may be attached later, and the stream ends on its detachment.
+    pub fn device(&self, id: D::DeviceIdent) -> DeviceStream {
+        let (tx, rx) = unbounded();
+        if let Some(subscribers) = self
+            .subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_mut()
+        {
+            subscribers.push((id, tx));
+        }
+
+        DeviceStream { rx }
+    }
+
+    /// close the inner driver, ending all the streams
+    pub fn close(mut self) {
+        self.shutdown();
+    }
+}
+
+impl<D: Driver> EventStream<D> {
+    fn shutdown(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            inner.close();
+        }
+
+        if let Some(join) = self.join.take() {
+            _ = join.join();
+            debug!("thread joined");
+        }
+    }
+}
+
+impl<D: Driver> Drop for EventStream<D> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// nothing is pinned structurally, the inner driver is only moved out on close
+impl<D: Driver> Unpin for EventStream<D> {}
+
+impl<D: Driver> Stream for EventStream<D> {
+    type Item = Event<D::DeviceIdent, D::ButtonBits>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.event_rx).poll_next(cx)
+    }
+}
+
+/// timestamped object diffs of a single device
+pub struct DeviceStream {
+    rx: UnboundedReceiver<(Timestamp, ObjectDiff)>,
+}
+
+impl Stream for DeviceStream {
+    type Item = (Timestamp, ObjectDiff);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+fn forward<DI, B>(
+    registry: &ProfileRegistry,
+    subscribers: &Subscribers<DI>,
+    inner_rx: &Receiver<Event<DI, B>>,
+    event_tx: Option<&UnboundedSender<Event<DI, B>>>,
+) where
+    DI: Debug + PartialEq + Clone,
+    B: Bits,
+{
+    let mut profiles: Vec<(DI, SharedProfile)> = Vec::new();
+
+    for evt in inner_rx.iter() {
+        match &evt {
+            Event::Attached(id, info, ..) => {
+                profiles.retain(|(k, _)| k != id);
+                profiles.push((id.clone(), registry.select(info)));
+            }
+
+            Event::Deattached(id, _) => {
+                profiles.retain(|(k, _)| k != id);
+                // dropping the senders ends the streams
+                if let Some(subscribers) = subscribers
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .as_mut()
+                {
+                    subscribers.retain(|(k, _)| k != id);
+                }
+            }
+
+            Event::StateDiff { id, diff, ts, .. } => {
+                if let Some((_, profile)) = profiles.iter().find(|(k, _)| k == id) {
+                    let mut subscribers = subscribers.lock().unwrap_or_else(|e| e.into_inner());
+                    if let Some(subscribers) = subscribers.as_mut() {
+                        subscribers.retain(|(k, tx)| {
+                            k != id
+                                || diff
+                                    .diffs(profile.as_ref())
+                                    .into_iter()
+                                    .all(|d| tx.unbounded_send((*ts, d)).is_ok())
+                        });
+                    }
+                }
+            }
+
+            Event::Ds4(..) | Event::Warn(..) | Event::Interruption(..) => {}
+        }
+
+        if let Some(event_tx) = event_tx {
+            if event_tx.unbounded_send(evt).is_err() {
+                debug!("event stream dropped");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        task::Waker,
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::{
+        driver::{fake::Fake, DeviceInfo, StateDiff, B256},
+        ButtonState,
+    };
+
+    /// poll until ready, the forwarding happens on another thread
+    fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Poll::Ready(item) = Pin::new(&mut *stream).poll_next(&mut cx) {
+                return item;
+            }
+
+            assert!(Instant::now() < deadline, "stream stalled");
+            sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn info() -> DeviceInfo {
+        DeviceInfo {
+            name: "pad".to_owned(),
+            buttons_num: 2,
+            ..Default::default()
+        }
+    }
+
+    fn press(idx: usize) -> Event<u64, B256> {
+        let mut diff = StateDiff::<B256>::default();
+        diff.buttons.0.set(idx);
+        diff.buttons.1.set(idx);
+
+        Event::StateDiff {
+            id: 1,
+            is_sink: false,
+            diff,
+            ts: Timestamp::now(),
+        }
+    }
+
+    #[test]
+    fn event_order() {
+        // the events before the first poll are queued
+        let fake = Fake::<B256>::new();
+        fake.attach(1, info());
+        fake.send(press(0));
+        fake.detach(1);
+
+        let mut stream = EventStream::new(fake, ProfileRegistry::new());
+        assert!(matches!(next(&mut stream), Some(Event::Attached(1, ..))));
+        assert!(matches!(
+            next(&mut stream),
+            Some(Event::StateDiff { id: 1, .. })
+        ));
+        assert!(matches!(next(&mut stream), Some(Event::Deattached(1, _))));
+        stream.close();
+    }
+
+    #[test]
+    fn device_stream() {
+        let fake = Fake::<B256>::new();
+        let stream = EventStream::devices_only(fake.clone(), ProfileRegistry::new());
+        let mut device = stream.device(1);
+        let mut other = stream.device(2);
+
+        fake.attach(1, info());
+        fake.send(press(1));
+        fake.detach(1);
+
+        assert!(matches!(
+            next(&mut device),
+            Some((_, ObjectDiff::Button(_, ButtonState::Pressed)))
+        ));
+        assert!(next(&mut device).is_none());
+
+        // the streams of the other devices end with the driver
+        drop(fake);
+        stream.close();
+        assert!(next(&mut other).is_none());
+    }
+
+    #[test]
+    fn drop_ends_streams() {
+        let fake = Fake::<B256>::new();
+        let mut stream = EventStream::devices_only(fake.clone(), ProfileRegistry::new());
+        let mut device = stream.device(1);
+        assert!(next(&mut stream).is_none());
+
+        drop(fake);
+        drop(stream);
+        assert!(next(&mut device).is_none());
+    }
+}
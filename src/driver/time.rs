@@ -0,0 +1,69 @@
+use std::{fmt, ops::Add, time::Duration};
+
+/// monotonic time of an event, since an unspecified origin of the platform
+///
+/// The origin is the boot on linux & windows, so timestamps taken by the
+/// kernel and the ones taken by `now` can be compared with each other.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(Duration);
+
+impl Timestamp {
+    pub const fn from_duration(since_origin: Duration) -> Self {
+        Self(since_origin)
+    }
+
+    pub const fn as_duration(&self) -> Duration {
+        self.0
+    }
+
+    /// current time of the monotonic clock
+    pub fn now() -> Self {
+        Self(sys_now())
+    }
+
+    /// elapsed time since the earlier timestamp, zero if it's actually later
+    pub fn saturating_duration_since(&self, earlier: Self) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Self;
+
+    fn add(self, rhs: Duration) -> Self {
+        Self(self.0 + rhs)
+    }
+}
+
+impl fmt::Debug for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.6}s", self.0.as_secs_f64())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn sys_now() -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    // never fails with a valid clock id & pointer
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+#[cfg(windows)]
+fn sys_now() -> Duration {
+    use windows::Win32::System::SystemInformation::GetTickCount64;
+
+    Duration::from_millis(unsafe { GetTickCount64() })
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn sys_now() -> Duration {
+    use std::{sync::OnceLock, time::Instant};
+
+    static ORIGIN: OnceLock<Instant> = OnceLock::new();
+    ORIGIN.get_or_init(Instant::now).elapsed()
+}
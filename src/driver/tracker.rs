@@ -0,0 +1,247 @@
+//! wrapper keeping the full states of the devices of any driver
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use crossbeam_channel::Receiver;
+use tracing::warn_span;
+
+use super::{forward::Forwarder, Bits, Driver, Event, Rumble};
+use crate::{normalize::CalibrationStore, profile::ProfileRegistry, state::DeviceState};
+
+type States<DI, B> = Arc<RwLock<Vec<(DI, DeviceState<B>)>>>;
+
+/// keeps the states of the devices of the inner driver, by folding its events
+///
+/// The states are updated before an event is forwarded, and the forwarded
+/// events still have to be drained, e.g. with `try_iter` once per frame; use
+/// `snapshots_only` when only the states are queried.
+pub struct StateTracker<D: Driver> {
+    fwd: Forwarder<D>,
+    states: States<D::DeviceIdent, D::ButtonBits>,
+}
+
+impl<D> StateTracker<D>
+where
+    D: Driver,
+    D::DeviceIdent: Clone + Send + Sync + 'static,
+    D::ButtonBits: Send + Sync + 'static,
+{
+    /// profiles are picked from the registry, and axis normalized with the stored calibrations
+    pub fn new(inner: D, registry: ProfileRegistry, calibrations: CalibrationStore) -> Self {
+        Self::spawn(inner, registry, calibrations, true)
+    }
+
+    /// only keep the states, no event is forwarded and the event receiver is
+    /// disconnected right away
+    pub fn snapshots_only(
+        inner: D,
+        registry: ProfileRegistry,
+        calibrations: CalibrationStore,
+    ) -> Self {
+        Self::spawn(inner, registry, calibrations, false)
+    }
+
+    fn spawn(
+        inner: D,
+        registry: ProfileRegistry,
+        calibrations: CalibrationStore,
+        forwarded: bool,
+    ) -> Self {
+        let states: States<_, _> = Default::default();
+
+        let thread_states = states.clone();
+        let fwd = Forwarder::spawn(inner, warn_span!("state tracker"), forwarded, move |evt| {
+            fold(&registry, &calibrations, &thread_states, evt)
+        });
+
+        Self { fwd, states }
+    }
+
+    /// snapshot of the states of the device, `None` if it's not attached
+    pub fn state(&self, id: &D::DeviceIdent) -> Option<DeviceState<D::ButtonBits>> {
+        let states = self.states.read().unwrap_or_else(|e| e.into_inner());
+        states
+            .iter()
+            .find(|(k, _)| k == id)
+            .map(|(_, st)| st.clone())
+    }
+
+    /// snapshots of all the attached devices
+    pub fn states(&self) -> Vec<(D::DeviceIdent, DeviceState<D::ButtonBits>)> {
+        self.states
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl<D> Driver for StateTracker<D>
+where
+    D: Driver,
+    D::DeviceIdent: Clone + Send + Sync + 'static,
+    D::ButtonBits: Send + Sync + 'static,
+{
+    type DeviceIdent = D::DeviceIdent;
+    type ButtonBits = D::ButtonBits;
+
+    fn as_event_receiver(&self) -> &Receiver<Event<Self::DeviceIdent, Self::ButtonBits>> {
+        self.fwd.as_event_receiver()
+    }
+
+    fn send_report(&self, id: &Self::DeviceIdent, report: &[u8]) -> Result<()> {
+        self.fwd.inner().send_report(id, report)
+    }
+
+    fn rumble(&self, id: &Self::DeviceIdent, rumble: Rumble) -> Result<()> {
+        self.fwd.inner().rumble(id, rumble)
+    }
+
+    fn stop_rumble(&self, id: &Self::DeviceIdent) -> Result<()> {
+        self.fwd.inner().stop_rumble(id)
+    }
+
+    fn close(self) {
+        self.fwd.close();
+    }
+}
+
+fn fold<DI, B>(
+    registry: &ProfileRegistry,
+    calibrations: &CalibrationStore,
+    states: &States<DI, B>,
+    evt: &Event<DI, B>,
+) where
+    DI: Debug + PartialEq + Clone,
+    B: Bits,
+{
+    let mut states = states.write().unwrap_or_else(|e| e.into_inner());
+    match evt {
+        Event::Attached(id, info, ..) => {
+            let st = DeviceState::new(
+                info.clone(),
+                registry.select(info),
+                calibrations.normalizer(info),
+            );
+
+            states.retain(|(k, _)| k != id);
+            states.push((id.clone(), st));
+        }
+
+        Event::Deattached(id, _) => {
+            states.retain(|(k, _)| k != id);
+        }
+
+        Event::StateDiff { id, diff, .. } => {
+            if let Some((_, st)) = states.iter_mut().find(|(k, _)| k == id) {
+                st.apply(diff);
+            }
+        }
+
+        Event::Ds4(..) | Event::Warn(..) | Event::Interruption(..) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::{
+        driver::{fake::Fake, DeviceInfo, StateDiff, Timestamp, B256},
+        Axis, AxisIdent, Button, DPadState,
+    };
+
+    fn info() -> DeviceInfo {
+        let mut info = DeviceInfo {
+            name: "pad".to_owned(),
+            buttons_num: 2,
+            hats: 1,
+            ..Default::default()
+        };
+        info.axis[AxisIdent::X as usize] = Some((0, 255));
+
+        info
+    }
+
+    fn diff(pressed: bool, x: i32, dpad: DPadState) -> Event<u64, B256> {
+        let mut diff = StateDiff::<B256>::default();
+        diff.buttons.0.set(1);
+        if pressed {
+            diff.buttons.1.set(1);
+        }
+        diff.axis[AxisIdent::X as usize] = Some(x);
+        diff.dpad[0] = Some(dpad);
+
+        Event::StateDiff {
+            id: 1,
+            is_sink: false,
+            diff,
+            ts: Timestamp::now(),
+        }
+    }
+
+    fn recv<D: Driver>(driver: &D) -> Event<D::DeviceIdent, D::ButtonBits> {
+        driver
+            .as_event_receiver()
+            .recv_timeout(Duration::from_secs(5))
+            .expect("event")
+    }
+
+    #[test]
+    fn fold_events() {
+        let fake = Fake::<B256>::new();
+        let tracker = StateTracker::new(fake.clone(), ProfileRegistry::new(), Default::default());
+
+        fake.attach(1, info());
+        fake.send(diff(true, 255, DPadState::Left));
+        assert!(matches!(recv(&tracker), Event::Attached(1, ..)));
+        assert!(matches!(recv(&tracker), Event::StateDiff { .. }));
+
+        let st = tracker.state(&1).unwrap();
+        assert!(st.is_pressed(Button::Other("b1")));
+        assert!(!st.is_pressed(Button::Other("b0")));
+        assert_eq!(st.axis(Axis::LThumbX), Some(1.0));
+        assert_eq!(st.dpad(), DPadState::Left);
+
+        fake.send(diff(false, 0, DPadState::Null));
+        recv(&tracker);
+        let st = tracker.state(&1).unwrap();
+        assert!(!st.is_pressed(Button::Other("b1")));
+        assert_eq!(st.axis(Axis::LThumbX), Some(-1.0));
+        assert_eq!(st.dpad(), DPadState::Null);
+
+        fake.detach(1);
+        recv(&tracker);
+        assert!(tracker.state(&1).is_none());
+        assert!(tracker.states().is_empty());
+    }
+
+    #[test]
+    fn snapshots_only() {
+        let fake = Fake::<B256>::new();
+        let tracker =
+            StateTracker::snapshots_only(fake.clone(), ProfileRegistry::new(), Default::default());
+
+        fake.attach(1, info());
+        fake.send(diff(true, 128, DPadState::Up));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !tracker
+            .state(&1)
+            .is_some_and(|st| st.dpad() == DPadState::Up)
+        {
+            assert!(Instant::now() < deadline, "states not updated");
+            sleep(Duration::from_millis(1));
+        }
+
+        assert!(tracker.state(&1).unwrap().is_pressed(Button::Other("b1")));
+        assert!(tracker.as_event_receiver().try_recv().is_err());
+    }
+}
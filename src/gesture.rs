@@ -0,0 +1,317 @@
+//! long presses, double taps & chords, recognized from the button diffs of a single device
+//!
+//! Recognition only depends on the timestamps it's fed with, e.g. the ones of
+//! the driver events, so it behaves the same when driven by a fake clock.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{driver::Timestamp, normalize::NormalizedDiff, Button, ButtonState, ObjectDiff};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Gesture {
+    /// held for the long press duration, reported once per press
+    LongPress(Button),
+    /// pressed again shortly after a tap
+    DoubleTap(Button),
+    /// all the buttons of a configured chord held together, in the configured order
+    Chord(Vec<Button>),
+}
+
+/// timing thresholds in milliseconds, and the chords to recognize
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GestureConfig {
+    pub long_press_ms: u64,
+    /// from the release of a tap to the next press
+    pub double_tap_ms: u64,
+    /// from the first to the last press of a chord
+    pub chord_ms: u64,
+    pub chords: Vec<Vec<Button>>,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            long_press_ms: 500,
+            double_tap_ms: 250,
+            chord_ms: 150,
+            chords: Vec::new(),
+        }
+    }
+}
+
+impl GestureConfig {
+    fn long_press(&self) -> Duration {
+        Duration::from_millis(self.long_press_ms)
+    }
+
+    fn double_tap(&self) -> Duration {
+        Duration::from_millis(self.double_tap_ms)
+    }
+
+    fn chord(&self) -> Duration {
+        Duration::from_millis(self.chord_ms)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Held {
+    button: Button,
+    since: Timestamp,
+    /// long press reported
+    long: bool,
+    /// second press of a double tap, not counted as a tap on release
+    double: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    held: Vec<Held>,
+    /// buttons & release times of the last taps
+    taps: Vec<(Button, Timestamp)>,
+    /// whether each chord is reported, until one of its buttons is released
+    chords: Vec<bool>,
+}
+
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> Self {
+        Self {
+            chords: vec![false; config.chords.len()],
+            config,
+            held: Vec::new(),
+            taps: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &GestureConfig {
+        &self.config
+    }
+
+    /// report the long presses reached by `now`, to be called when no events
+    /// come in, e.g. once per frame
+    pub fn poll(&mut self, now: Timestamp) -> Vec<Gesture> {
+        let long_press = self.config.long_press();
+        let double_tap = self.config.double_tap();
+
+        self.taps
+            .retain(|(_, released)| now.saturating_duration_since(*released) <= double_tap);
+
+        self.held
+            .iter_mut()
+            .filter(|h| !h.long && now.saturating_duration_since(h.since) >= long_press)
+            .map(|h| {
+                h.long = true;
+                Gesture::LongPress(h.button)
+            })
+            .collect()
+    }
+
+    /// feed a button change at the given time, long presses reached before it
+    /// are reported first
+    pub fn button(&mut self, ts: Timestamp, button: Button, state: ButtonState) -> Vec<Gesture> {
+        let mut gestures = self.poll(ts);
+
+        match state {
+            ButtonState::Pressed => self.press(ts, button, &mut gestures),
+            ButtonState::Released => self.release(ts, button),
+        }
+
+        gestures
+    }
+
+    /// feed the button diffs of a driver event
+    pub fn diffs(&mut self, ts: Timestamp, diffs: &[ObjectDiff]) -> Vec<Gesture> {
+        let mut gestures = self.poll(ts);
+        for diff in diffs {
+            if let ObjectDiff::Button(button, state) = diff {
+                gestures.extend(self.button(ts, *button, *state));
+            }
+        }
+
+        gestures
+    }
+
+    /// same as `diffs`, over normalized diffs
+    pub fn normalized_diffs(&mut self, ts: Timestamp, diffs: &[NormalizedDiff]) -> Vec<Gesture> {
+        let mut gestures = self.poll(ts);
+        for diff in diffs {
+            if let NormalizedDiff::Button(button, state) = diff {
+                gestures.extend(self.button(ts, *button, *state));
+            }
+        }
+
+        gestures
+    }
+
+    fn press(&mut self, ts: Timestamp, button: Button, gestures: &mut Vec<Gesture>) {
+        if self.held.iter().any(|h| h.button == button) {
+            return;
+        }
+
+        // taps older than the window are dropped by the poll
+        let double = match self.taps.iter().position(|(b, _)| *b == button) {
+            Some(idx) => {
+                self.taps.swap_remove(idx);
+                gestures.push(Gesture::DoubleTap(button));
+                true
+            }
+            None => false,
+        };
+
+        self.held.push(Held {
+            button,
+            since: ts,
+            long: false,
+            double,
+        });
+
+        let chord_window = self.config.chord();
+        for (chord, reported) in self.config.chords.iter().zip(self.chords.iter_mut()) {
+            if *reported || !chord.contains(&button) {
+                continue;
+            }
+
+            // press times of the buttons, if all of them are held
+            let times: Option<Vec<Timestamp>> = chord
+                .iter()
+                .map(|b| self.held.iter().find(|h| h.button == *b).map(|h| h.since))
+                .collect();
+
+            let first = match times.as_ref().and_then(|t| t.iter().min()) {
+                Some(first) => *first,
+                None => continue,
+            };
+
+            // the button just pressed is the last one
+            if ts.saturating_duration_since(first) <= chord_window {
+                *reported = true;
+                gestures.push(Gesture::Chord(chord.clone()));
+            }
+        }
+    }
+
+    fn release(&mut self, ts: Timestamp, button: Button) {
+        let held = match self.held.iter().position(|h| h.button == button) {
+            Some(idx) => self.held.swap_remove(idx),
+            None => return,
+        };
+
+        for (chord, reported) in self.config.chords.iter().zip(self.chords.iter_mut()) {
+            if chord.contains(&button) {
+                *reported = false;
+            }
+        }
+
+        // long presses reached by the release are reported by the poll before
+        if !held.long && !held.double {
+            self.taps.retain(|(b, _)| *b != button);
+            self.taps.push((button, ts));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ButtonState::{Pressed, Released};
+
+    fn ms(ms: u64) -> Timestamp {
+        Timestamp::from_duration(Duration::from_millis(ms))
+    }
+
+    fn recognizer() -> GestureRecognizer {
+        GestureRecognizer::new(GestureConfig {
+            chords: vec![vec![Button::LShoulder, Button::RShoulder]],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn long_press() {
+        let mut rec = recognizer();
+        assert!(rec.button(ms(0), Button::South, Pressed).is_empty());
+        assert!(rec.poll(ms(499)).is_empty());
+        assert_eq!(rec.poll(ms(500)), vec![Gesture::LongPress(Button::South)]);
+
+        // reported once per press, and not counted as a tap
+        assert!(rec.poll(ms(900)).is_empty());
+        assert!(rec.button(ms(1000), Button::South, Released).is_empty());
+        assert!(rec.button(ms(1100), Button::South, Pressed).is_empty());
+
+        // reached before a later event
+        assert_eq!(
+            rec.button(ms(1700), Button::East, Pressed),
+            vec![Gesture::LongPress(Button::South)]
+        );
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut rec = recognizer();
+        rec.button(ms(0), Button::South, Pressed);
+        rec.button(ms(50), Button::South, Released);
+        assert_eq!(
+            rec.button(ms(300), Button::South, Pressed),
+            vec![Gesture::DoubleTap(Button::South)]
+        );
+
+        // the second press isn't the first tap of another double tap
+        rec.button(ms(350), Button::South, Released);
+        assert!(rec.button(ms(400), Button::South, Pressed).is_empty());
+        rec.button(ms(450), Button::South, Released);
+
+        // outside of the window
+        assert!(rec.button(ms(701), Button::South, Pressed).is_empty());
+        rec.button(ms(750), Button::South, Released);
+
+        // dropped by a poll in between
+        rec.poll(ms(1001));
+        assert!(rec.button(ms(1001), Button::South, Pressed).is_empty());
+    }
+
+    #[test]
+    fn chord() {
+        let mut rec = recognizer();
+        assert!(rec.button(ms(0), Button::RShoulder, Pressed).is_empty());
+        assert_eq!(
+            rec.button(ms(150), Button::LShoulder, Pressed),
+            vec![Gesture::Chord(vec![Button::LShoulder, Button::RShoulder])]
+        );
+
+        // reported once while held
+        assert!(rec.button(ms(160), Button::South, Pressed).is_empty());
+        rec.button(ms(200), Button::RShoulder, Released);
+        rec.button(ms(200), Button::LShoulder, Released);
+
+        // outside of the window
+        rec.button(ms(1000), Button::LShoulder, Pressed);
+        assert!(rec.button(ms(1151), Button::RShoulder, Pressed).is_empty());
+    }
+
+    #[test]
+    fn chord_rearm() {
+        let mut rec = recognizer();
+        let chord = vec![Gesture::Chord(vec![Button::LShoulder, Button::RShoulder])];
+        rec.button(ms(0), Button::LShoulder, Pressed);
+        assert_eq!(rec.button(ms(10), Button::RShoulder, Pressed), chord);
+
+        // re-armed by the release of any of its buttons
+        assert!(rec.button(ms(50), Button::RShoulder, Released).is_empty());
+        assert_eq!(
+            rec.button(ms(100), Button::RShoulder, Pressed),
+            [vec![Gesture::DoubleTap(Button::RShoulder)], chord.clone()].concat()
+        );
+
+        // still within the window of the first press only
+        rec.button(ms(120), Button::RShoulder, Released);
+        assert!(rec.button(ms(200), Button::RShoulder, Pressed).is_empty());
+
+        rec.button(ms(250), Button::LShoulder, Released);
+        rec.button(ms(250), Button::RShoulder, Released);
+        rec.button(ms(1000), Button::LShoulder, Pressed);
+        assert_eq!(rec.button(ms(1100), Button::RShoulder, Pressed), chord);
+    }
+}
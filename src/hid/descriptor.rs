@@ -0,0 +1,1042 @@
+//! HID report descriptor parser, and an input report decoder built on top of it
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use tracing::{trace, warn, warn_span};
+
+use super::*;
+use crate::{
+    driver::{Bits, DeviceInfo, ObjectStates, SliderInfo, StateDiff},
+    AxisIdent, ButtonIdent, SliderKind, HAT_LIMIT, SLIDER_LIMIT,
+};
+
+const ITEM_TYPE_MAIN: u8 = 0;
+const ITEM_TYPE_GLOBAL: u8 = 1;
+const ITEM_TYPE_LOCAL: u8 = 2;
+const ITEM_LONG: u8 = 0xfe;
+
+const MAIN_INPUT: u8 = 0x8;
+const MAIN_OUTPUT: u8 = 0x9;
+const MAIN_COLLECTION: u8 = 0xa;
+const MAIN_FEATURE: u8 = 0xb;
+const MAIN_END_COLLECTION: u8 = 0xc;
+
+const GLOBAL_USAGE_PAGE: u8 = 0x0;
+const GLOBAL_LOGICAL_MIN: u8 = 0x1;
+const GLOBAL_LOGICAL_MAX: u8 = 0x2;
+const GLOBAL_PHYSICAL_MIN: u8 = 0x3;
+const GLOBAL_PHYSICAL_MAX: u8 = 0x4;
+const GLOBAL_UNIT_EXPONENT: u8 = 0x5;
+const GLOBAL_UNIT: u8 = 0x6;
+const GLOBAL_REPORT_SIZE: u8 = 0x7;
+const GLOBAL_REPORT_ID: u8 = 0x8;
+const GLOBAL_REPORT_COUNT: u8 = 0x9;
+const GLOBAL_PUSH: u8 = 0xa;
+const GLOBAL_POP: u8 = 0xb;
+
+const LOCAL_USAGE: u8 = 0x0;
+const LOCAL_USAGE_MIN: u8 = 0x1;
+const LOCAL_USAGE_MAX: u8 = 0x2;
+
+const COLLECTION_APPLICATION: u32 = 0x01;
+
+const FLAG_CONSTANT: u32 = 1 << 0;
+const FLAG_VARIABLE: u32 = 1 << 1;
+const FLAG_RELATIVE: u32 = 1 << 2;
+const FLAG_NULL_STATE: u32 = 1 << 6;
+
+/// upper bound of usages expanded from a single usage range
+const USAGE_RANGE_LIMIT: u32 = 1024;
+
+/// upper bound of elements mapped from a single field
+const REPORT_COUNT_LIMIT: u32 = 1024;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct GlobalState {
+    usage_page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    physical_min: i32,
+    physical_max: i32,
+    unit_exponent: i32,
+    unit: u32,
+    report_size: u32,
+    report_id: u8,
+    report_count: u32,
+}
+
+#[derive(Debug, Default)]
+struct LocalState {
+    /// usages, with the usage page in the high 16 bits for extended usages
+    usages: Vec<(bool, u32)>,
+    usage_min: Option<(bool, u32)>,
+    usage_max: Option<(bool, u32)>,
+}
+
+impl LocalState {
+    fn resolve(&self, page: u16) -> Vec<(u16, u16)> {
+        let full = |(extended, usage): (bool, u32)| {
+            if extended {
+                ((usage >> 16) as u16, usage as u16)
+            } else {
+                (page, usage as u16)
+            }
+        };
+
+        let mut usages: Vec<(u16, u16)> = self.usages.iter().cloned().map(full).collect();
+
+        if let (Some(min), Some(max)) = (self.usage_min, self.usage_max) {
+            let (min_page, min_id) = full(min);
+            let (_, max_id) = full(max);
+            let max_id = max_id.min(min_id.saturating_add(USAGE_RANGE_LIMIT as u16));
+            usages.extend((min_id..=max_id).map(|id| (min_page, id)));
+        }
+
+        usages
+    }
+}
+
+/// a single main item field in an input report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportField {
+    pub report_id: u8,
+    /// offset in bits, not including the report id byte
+    pub bit_offset: u32,
+    pub bit_size: u32,
+    pub count: u32,
+    pub flags: u32,
+    /// `(usage page, usage id)` pairs, the last one repeats for the remaining elements
+    pub usages: Vec<(u16, u16)>,
+    pub logical_min: i32,
+    pub logical_max: i32,
+    pub physical_min: i32,
+    pub physical_max: i32,
+    pub unit: u32,
+    pub unit_exponent: i32,
+}
+
+impl ReportField {
+    #[inline]
+    pub fn is_constant(&self) -> bool {
+        self.flags & FLAG_CONSTANT != 0
+    }
+
+    #[inline]
+    pub fn is_variable(&self) -> bool {
+        self.flags & FLAG_VARIABLE != 0
+    }
+
+    #[inline]
+    pub fn is_relative(&self) -> bool {
+        self.flags & FLAG_RELATIVE != 0
+    }
+
+    #[inline]
+    pub fn has_null_state(&self) -> bool {
+        self.flags & FLAG_NULL_STATE != 0
+    }
+
+    /// usage of the element at the given index of a variable field
+    pub fn usage(&self, idx: usize) -> Option<(u16, u16)> {
+        self.usages.get(idx).or_else(|| self.usages.last()).cloned()
+    }
+
+    #[inline]
+    fn is_signed(&self) -> bool {
+        self.logical_min < 0
+    }
+}
+
+/// parsed report descriptor, only input reports are kept
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportDescriptor {
+    /// usage of the first top level application collection
+    pub application: Option<(u16, u16)>,
+    /// whether reports are prefixed with a report id byte
+    pub report_ids: bool,
+    pub inputs: Vec<ReportField>,
+}
+
+impl ReportDescriptor {
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let mut desc = ReportDescriptor::default();
+
+        let mut global = GlobalState::default();
+        let mut global_stack = Vec::new();
+        let mut local = LocalState::default();
+        let mut depth = 0usize;
+        let mut offsets: HashMap<u8, u32> = HashMap::new();
+
+        let mut pos = 0;
+        while pos < raw.len() {
+            let prefix = raw[pos];
+            if prefix == ITEM_LONG {
+                let size = *raw
+                    .get(pos + 1)
+                    .ok_or_else(|| anyhow!("truncated long item at {}", pos))?
+                    as usize;
+                pos += 3 + size;
+                continue;
+            }
+
+            let size = match prefix & 0x3 {
+                3 => 4,
+                s => s as usize,
+            };
+            let typ = (prefix >> 2) & 0x3;
+            let tag = prefix >> 4;
+
+            let data = raw
+                .get(pos + 1..pos + 1 + size)
+                .ok_or_else(|| anyhow!("truncated item {:#04x} at {}", prefix, pos))?;
+            pos += 1 + size;
+
+            let udata = data
+                .iter()
+                .rev()
+                .fold(0u32, |acc, b| (acc << 8) | *b as u32);
+            let sdata = match size {
+                1 => udata as u8 as i8 as i32,
+                2 => udata as u16 as i16 as i32,
+                _ => udata as i32,
+            };
+
+            match typ {
+                ITEM_TYPE_MAIN => {
+                    match tag {
+                        MAIN_INPUT => {
+                            if global.report_size > 32 {
+                                return Err(anyhow!(
+                                    "unsupported report size {} at {}",
+                                    global.report_size,
+                                    pos
+                                ));
+                            }
+
+                            let offset = offsets.entry(global.report_id).or_default();
+                            let bit_offset = *offset;
+                            *offset = global
+                                .report_size
+                                .checked_mul(global.report_count)
+                                .and_then(|bits| bit_offset.checked_add(bits))
+                                .ok_or_else(|| {
+                                    anyhow!("report {} too long at {}", global.report_id, pos)
+                                })?;
+
+                            let field = ReportField {
+                                report_id: global.report_id,
+                                bit_offset,
+                                bit_size: global.report_size,
+                                count: global.report_count,
+                                flags: udata,
+                                usages: local.resolve(global.usage_page),
+                                logical_min: global.logical_min,
+                                logical_max: global.logical_max,
+                                physical_min: global.physical_min,
+                                physical_max: global.physical_max,
+                                unit: global.unit,
+                                unit_exponent: global.unit_exponent,
+                            };
+
+                            desc.inputs.push(field);
+                        }
+
+                        MAIN_OUTPUT | MAIN_FEATURE => {}
+
+                        MAIN_COLLECTION => {
+                            if depth == 0
+                                && udata == COLLECTION_APPLICATION
+                                && desc.application.is_none()
+                            {
+                                desc.application =
+                                    local.resolve(global.usage_page).first().cloned();
+                            }
+                            depth += 1;
+                        }
+
+                        MAIN_END_COLLECTION => {
+                            depth = depth
+                                .checked_sub(1)
+                                .ok_or_else(|| anyhow!("unbalanced end collection at {}", pos))?;
+                        }
+
+                        other => {
+                            trace!("unknown main item tag {:#x}", other);
+                        }
+                    };
+
+                    local = LocalState::default();
+                }
+
+                ITEM_TYPE_GLOBAL => match tag {
+                    GLOBAL_USAGE_PAGE => global.usage_page = udata as u16,
+                    GLOBAL_LOGICAL_MIN => global.logical_min = sdata,
+                    GLOBAL_LOGICAL_MAX => {
+                        // a lot of devices declare e.g. 0..255 in a single byte
+                        global.logical_max = if global.logical_min >= 0 && sdata < 0 {
+                            udata as i32
+                        } else {
+                            sdata
+                        };
+                    }
+                    GLOBAL_PHYSICAL_MIN => global.physical_min = sdata,
+                    GLOBAL_PHYSICAL_MAX => {
+                        global.physical_max = if global.physical_min >= 0 && sdata < 0 {
+                            udata as i32
+                        } else {
+                            sdata
+                        };
+                    }
+                    GLOBAL_UNIT_EXPONENT => {
+                        // 4 bits two's complement
+                        global.unit_exponent = ((udata as i32) << 28) >> 28;
+                    }
+                    GLOBAL_UNIT => global.unit = udata,
+                    GLOBAL_REPORT_SIZE => global.report_size = udata,
+                    GLOBAL_REPORT_ID => {
+                        if udata == 0 || udata > u8::MAX as u32 {
+                            return Err(anyhow!("invalid report id {}", udata));
+                        }
+                        global.report_id = udata as u8;
+                        desc.report_ids = true;
+                    }
+                    GLOBAL_REPORT_COUNT => global.report_count = udata,
+                    GLOBAL_PUSH => global_stack.push(global),
+                    GLOBAL_POP => {
+                        global = global_stack
+                            .pop()
+                            .ok_or_else(|| anyhow!("pop without push at {}", pos))?;
+                    }
+                    other => {
+                        trace!("unknown global item tag {:#x}", other);
+                    }
+                },
+
+                ITEM_TYPE_LOCAL => {
+                    let usage = (size == 4, udata);
+                    match tag {
+                        LOCAL_USAGE => local.usages.push(usage),
+                        LOCAL_USAGE_MIN => {
+                            local.usage_min.replace(usage);
+                        }
+                        LOCAL_USAGE_MAX => {
+                            local.usage_max.replace(usage);
+                        }
+                        _ => {}
+                    }
+                }
+
+                _reserved => {
+                    trace!("reserved item {:#04x}", prefix);
+                }
+            }
+        }
+
+        Ok(desc)
+    }
+
+    #[inline]
+    pub fn is_joystick(&self) -> bool {
+        matches!(
+            self.application,
+            Some((USAGE_PAGE_GENERIC, USAGE_GENERIC_JOYSTICK))
+                | Some((USAGE_PAGE_GENERIC, USAGE_GENERIC_GAMEPAD))
+        )
+    }
+}
+
+/// logical & physical range of a value object
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValueCap {
+    pub logical_min: i32,
+    pub logical_max: i32,
+    pub physical_min: i32,
+    pub physical_max: i32,
+    pub unit: u32,
+    pub unit_exponent: i32,
+}
+
+impl ValueCap {
+    pub fn hat_decoder(&self) -> Option<HatDecoder> {
+        HatDecoder::new(
+            (self.logical_min, self.logical_max),
+            (self.physical_min, self.physical_max),
+            self.unit,
+        )
+    }
+}
+
+impl From<&ReportField> for ValueCap {
+    fn from(f: &ReportField) -> Self {
+        Self {
+            logical_min: f.logical_min,
+            logical_max: f.logical_max,
+            physical_min: f.physical_min,
+            physical_max: f.physical_max,
+            unit: f.unit,
+            unit_exponent: f.unit_exponent,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Location {
+    Value(DeviceObjectIndex),
+    /// array of button usages, the element value selects the pressed button
+    Buttons {
+        logical_min: i32,
+        first: ButtonIdent,
+        num: usize,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct ObjectLocation {
+    report_id: u8,
+    bit_offset: u32,
+    bit_size: u32,
+    signed: bool,
+    location: Location,
+}
+
+/// joystick objects found in a report descriptor, and where to read them
+#[derive(Debug, Clone, Default)]
+pub struct DeviceCap {
+    pub buttons_num: usize,
+    pub hats: [Option<ValueCap>; HAT_LIMIT],
+    pub axis: [Option<ValueCap>; AxisIdent::Limit as usize],
+    /// sliders, dials & wheels, in slider ident order
+    pub sliders: [Option<(SliderKind, ValueCap)>; SLIDER_LIMIT],
+    report_ids: bool,
+    locations: Vec<ObjectLocation>,
+}
+
+impl DeviceCap {
+    /// construct caps from the input fields, `None` if no objects available
+    pub fn new(desc: &ReportDescriptor) -> Option<Self> {
+        let mut cap = DeviceCap {
+            report_ids: desc.report_ids,
+            ..Default::default()
+        };
+
+        for field in desc.inputs.iter().filter(|f| !f.is_constant()) {
+            let _span = warn_span!(
+                "input field",
+                id = field.report_id,
+                offset = field.bit_offset
+            )
+            .entered();
+
+            if !field.is_variable() {
+                cap.add_array(field);
+                continue;
+            }
+
+            for elem in 0..field.count.min(REPORT_COUNT_LIMIT) {
+                let (page, usage) = match field.usage(elem as usize) {
+                    Some(u) => u,
+                    None => break,
+                };
+
+                let object = match (page, usage) {
+                    (USAGE_PAGE_BUTTON, _) => {
+                        let idx = cap.buttons_num;
+                        cap.buttons_num += 1;
+                        Some(DeviceObjectIndex::Button(idx))
+                    }
+
+                    (USAGE_PAGE_GENERIC, USAGE_GENERIC_HATSWITCH) => {
+                        if ValueCap::from(field).hat_decoder().is_none() {
+                            warn!(
+                                min = field.logical_min,
+                                max = field.logical_max,
+                                unit = field.unit,
+                                "unexpected value range for hat"
+                            );
+                            None
+                        } else {
+                            cap.add_hat(field)
+                        }
+                    }
+
+                    (upage, uid) => match (axis_ident(upage, uid), slider_kind(upage, uid)) {
+                        (Some(idx), _) => cap.fill_slot(
+                            |c| &mut c.axis[idx as usize],
+                            field,
+                            DeviceObjectIndex::Axis(idx),
+                        ),
+                        (None, Some(kind)) => cap.add_slider(kind, field),
+                        (None, None) => None,
+                    },
+                };
+
+                if let Some(object) = object {
+                    cap.locations.push(ObjectLocation {
+                        report_id: field.report_id,
+                        bit_offset: field.bit_offset + elem * field.bit_size,
+                        bit_size: field.bit_size,
+                        signed: field.is_signed(),
+                        location: Location::Value(object),
+                    });
+                }
+            }
+        }
+
+        if cap.locations.is_empty() {
+            warn!("no buttons & values available");
+            return None;
+        }
+
+        Some(cap)
+    }
+
+    fn add_array(&mut self, field: &ReportField) {
+        let num = field
+            .usages
+            .iter()
+            .take_while(|(page, _)| *page == USAGE_PAGE_BUTTON)
+            .count();
+
+        if num == 0 {
+            trace!("non-button array ignored");
+            return;
+        }
+
+        let first = self.buttons_num;
+        self.buttons_num += num;
+
+        for elem in 0..field.count.min(REPORT_COUNT_LIMIT) {
+            self.locations.push(ObjectLocation {
+                report_id: field.report_id,
+                bit_offset: field.bit_offset + elem * field.bit_size,
+                bit_size: field.bit_size,
+                signed: field.is_signed(),
+                location: Location::Buttons {
+                    logical_min: field.logical_min,
+                    first,
+                    num,
+                },
+            });
+        }
+    }
+
+    fn fill_slot<F>(
+        &mut self,
+        slot: F,
+        field: &ReportField,
+        object: DeviceObjectIndex,
+    ) -> Option<DeviceObjectIndex>
+    where
+        F: FnOnce(&mut Self) -> &mut Option<ValueCap>,
+    {
+        let slot = slot(self);
+        if slot.is_some() {
+            warn!(?object, "duplicate typed object");
+            return None;
+        }
+
+        slot.replace(field.into());
+        Some(object)
+    }
+
+    fn add_hat(&mut self, field: &ReportField) -> Option<DeviceObjectIndex> {
+        match self.hats.iter().position(Option::is_none) {
+            Some(idx) => self.fill_slot(|c| &mut c.hats[idx], field, DeviceObjectIndex::DPad(idx)),
+            None => {
+                warn!(limit = HAT_LIMIT, "hats limit exceeded");
+                None
+            }
+        }
+    }
+
+    fn add_slider(&mut self, kind: SliderKind, field: &ReportField) -> Option<DeviceObjectIndex> {
+        match self.sliders.iter().position(Option::is_none) {
+            Some(idx) => {
+                self.sliders[idx].replace((kind, field.into()));
+                Some(DeviceObjectIndex::Slider(idx))
+            }
+            None => {
+                warn!(limit = SLIDER_LIMIT, "sliders limit exceeded");
+                None
+            }
+        }
+    }
+
+    /// ids are not part of the descriptor, and are left empty
+    pub fn info(&self, name: String) -> DeviceInfo {
+        let mut info = DeviceInfo {
+            name,
+            buttons_num: self.buttons_num,
+            hats: self.hats.iter().flatten().count(),
+            axis: Default::default(),
+            sliders: self
+                .sliders
+                .iter()
+                .flatten()
+                .map(|(kind, v)| SliderInfo::new(*kind, (v.logical_min, v.logical_max)))
+                .collect(),
+            ..Default::default()
+        };
+
+        for (slot, value) in info.axis.iter_mut().zip(self.axis.iter()) {
+            *slot = value.map(|v| (v.logical_min, v.logical_max));
+        }
+
+        info
+    }
+
+    /// decode the values of the objects in an input report; buttons are
+    /// decoded as 0 or 1, and every button carried by the report is included
+    pub fn decode(&self, report: &[u8]) -> Result<Vec<(DeviceObjectIndex, i32)>> {
+        let (report_id, data) = if self.report_ids {
+            match report.split_first() {
+                Some((id, data)) => (*id, data),
+                None => return Err(anyhow!("empty report")),
+            }
+        } else {
+            (0, report)
+        };
+
+        let mut values = Vec::new();
+        for loc in self.locations.iter().filter(|l| l.report_id == report_id) {
+            let value = extract_bits(data, loc.bit_offset, loc.bit_size, loc.signed).with_context(
+                || {
+                    format!(
+                        "read {} bits at {} from report {} of {} bytes",
+                        loc.bit_size,
+                        loc.bit_offset,
+                        report_id,
+                        report.len()
+                    )
+                },
+            )?;
+
+            match loc.location {
+                Location::Value(DeviceObjectIndex::Button(idx)) => {
+                    values.push((DeviceObjectIndex::Button(idx), (value != 0) as i32));
+                }
+
+                Location::Value(object) => {
+                    values.push((object, value));
+                }
+
+                Location::Buttons {
+                    logical_min,
+                    first,
+                    num,
+                } => {
+                    let pressed = value
+                        .checked_sub(logical_min)
+                        .filter(|v| *v >= 0 && (*v as usize) < num)
+                        .map(|v| first + v as usize);
+
+                    for idx in first..first + num {
+                        let on = Some(idx) == pressed;
+                        match values
+                            .iter_mut()
+                            .find(|(o, _)| *o == DeviceObjectIndex::Button(idx))
+                        {
+                            Some((_, v)) => *v |= on as i32,
+                            None => values.push((DeviceObjectIndex::Button(idx), on as i32)),
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+#[inline]
+fn extract_bits(data: &[u8], offset: u32, size: u32, signed: bool) -> Result<i32> {
+    if size == 0 || size > 32 {
+        return Err(anyhow!("unsupported field size {}", size));
+    }
+
+    let (offset, size) = (offset as usize, size as usize);
+    if offset + size > data.len() * 8 {
+        return Err(anyhow!("report too short"));
+    }
+
+    let mut value = 0u64;
+    for (i, byte) in data[offset / 8..(offset + size).div_ceil(8)]
+        .iter()
+        .enumerate()
+    {
+        value |= (*byte as u64) << (i * 8);
+    }
+
+    let value = ((value >> (offset % 8)) & ((1u64 << size) - 1)) as u32;
+    Ok(if signed && size < 32 {
+        ((value << (32 - size)) as i32) >> (32 - size)
+    } else {
+        value as i32
+    })
+}
+
+/// folds decoded input reports into state diffs
+pub struct ReportDecoder<B> {
+    cap: DeviceCap,
+    states: ObjectStates<B>,
+}
+
+impl<B: Bits> ReportDecoder<B> {
+    pub fn new(mut cap: DeviceCap) -> Result<Self> {
+        // buttons beyond the cap are ignored, rather than the whole device
+        if cap.buttons_num > B::CAP {
+            warn!(
+                cap = B::CAP,
+                num = cap.buttons_num,
+                "maximum bits cap exceeded, extra buttons ignored"
+            );
+            cap.buttons_num = B::CAP;
+        }
+
+        Ok(Self {
+            cap,
+            states: Default::default(),
+        })
+    }
+
+    pub fn cap(&self) -> &DeviceCap {
+        &self.cap
+    }
+
+    pub fn feed(&mut self, report: &[u8]) -> Result<StateDiff<B>> {
+        let mut new_states = self.states.clone();
+        for (object, value) in self.cap.decode(report)? {
+            match object {
+                DeviceObjectIndex::DPad(idx) => {
+                    let decoder = self.cap.hats[idx].and_then(|v| v.hat_decoder());
+                    if let Some(decoder) = decoder {
+                        new_states.dpad[idx].replace(decoder.decode(value));
+                    }
+                }
+
+                DeviceObjectIndex::Button(idx) => {
+                    if value != 0 {
+                        new_states.buttons.set(idx);
+                    } else {
+                        new_states.buttons.clear(idx);
+                    }
+                }
+
+                DeviceObjectIndex::Axis(idx) => {
+                    new_states.axis[idx as usize].replace(value);
+                }
+
+                DeviceObjectIndex::Slider(idx) => {
+                    new_states.sliders[idx].replace(value);
+                }
+            }
+        }
+
+        let diff = new_states.diff(&self.states);
+        self.states = new_states;
+        Ok(diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{driver::SliderInfo, DPadState};
+
+    /// gamepad with two reports: the sticks, a hat in degrees, 12 buttons & a
+    /// signed slider in report 1, a 10 bits throttle & a button array in report 2
+    #[rustfmt::skip]
+    const GAMEPAD: &[u8] = &[
+        0x05, 0x01,       // usage page (generic desktop)
+        0x09, 0x05,       // usage (gamepad)
+        0xa1, 0x01,       // collection (application)
+        0x85, 0x01,       //   report id (1)
+        0x09, 0x30,       //   usage (x)
+        0x09, 0x31,       //   usage (y)
+        0x09, 0x32,       //   usage (z)
+        0x09, 0x35,       //   usage (rz)
+        0x15, 0x00,       //   logical min (0)
+        0x26, 0xff, 0x00, //   logical max (255)
+        0x75, 0x08,       //   report size (8)
+        0x95, 0x04,       //   report count (4)
+        0x81, 0x02,       //   input (data, var, abs)
+        0x09, 0x39,       //   usage (hat switch)
+        0x25, 0x07,       //   logical max (7)
+        0x35, 0x00,       //   physical min (0)
+        0x46, 0x3b, 0x01, //   physical max (315)
+        0x65, 0x14,       //   unit (degrees)
+        0x75, 0x04,       //   report size (4)
+        0x95, 0x01,       //   report count (1)
+        0x81, 0x42,       //   input (data, var, abs, null)
+        0x65, 0x00,       //   unit (none)
+        0x45, 0x00,       //   physical max (0)
+        0x05, 0x09,       //   usage page (button)
+        0x19, 0x01,       //   usage min (1)
+        0x29, 0x0c,       //   usage max (12)
+        0x25, 0x01,       //   logical max (1)
+        0x75, 0x01,       //   report size (1)
+        0x95, 0x0c,       //   report count (12)
+        0x81, 0x02,       //   input (data, var, abs)
+        0xa4,             //   push
+        0x05, 0x01,       //   usage page (generic desktop)
+        0x09, 0x36,       //   usage (slider)
+        0x16, 0x00, 0x80, //   logical min (-32768)
+        0x26, 0xff, 0x7f, //   logical max (32767)
+        0x75, 0x10,       //   report size (16)
+        0x95, 0x01,       //   report count (1)
+        0x81, 0x02,       //   input (data, var, abs)
+        0xb4,             //   pop
+        0x75, 0x08,       //   report size (8)
+        0x95, 0x01,       //   report count (1)
+        0x81, 0x03,       //   input (const, var, abs)
+        0x85, 0x02,       //   report id (2)
+        0x05, 0x02,       //   usage page (simulation)
+        0x09, 0xbb,       //   usage (throttle)
+        0x26, 0xff, 0x03, //   logical max (1023)
+        0x75, 0x0a,       //   report size (10)
+        0x81, 0x02,       //   input (data, var, abs)
+        0x75, 0x06,       //   report size (6)
+        0x81, 0x03,       //   input (const, var, abs)
+        0x05, 0x09,       //   usage page (button)
+        0x19, 0x0d,       //   usage min (13)
+        0x29, 0x10,       //   usage max (16)
+        0x15, 0x01,       //   logical min (1)
+        0x25, 0x04,       //   logical max (4)
+        0x75, 0x08,       //   report size (8)
+        0x95, 0x02,       //   report count (2)
+        0x81, 0x00,       //   input (data, array, abs)
+        0xc0,             // end collection
+    ];
+
+    /// joystick without report ids, 2 buttons & a 4-way hat in positions
+    #[rustfmt::skip]
+    const JOYSTICK: &[u8] = &[
+        0x05, 0x01,       // usage page (generic desktop)
+        0x09, 0x04,       // usage (joystick)
+        0xa1, 0x01,       // collection (application)
+        0x05, 0x09,       //   usage page (button)
+        0x09, 0x01,       //   usage (1)
+        0x09, 0x02,       //   usage (2)
+        0x15, 0x00,       //   logical min (0)
+        0x25, 0x01,       //   logical max (1)
+        0x75, 0x01,       //   report size (1)
+        0x95, 0x02,       //   report count (2)
+        0x81, 0x02,       //   input (data, var, abs)
+        0x05, 0x01,       //   usage page (generic desktop)
+        0x09, 0x39,       //   usage (hat switch)
+        0x25, 0x03,       //   logical max (3)
+        0x75, 0x02,       //   report size (2)
+        0x95, 0x01,       //   report count (1)
+        0x81, 0x42,       //   input (data, var, abs, null)
+        0x75, 0x04,       //   report size (4)
+        0x81, 0x03,       //   input (const, var, abs)
+        0xc0,             // end collection
+    ];
+
+    fn decoder(raw: &[u8]) -> ReportDecoder<u32> {
+        let desc = ReportDescriptor::parse(raw).unwrap();
+        ReportDecoder::new(DeviceCap::new(&desc).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_fields() {
+        let desc = ReportDescriptor::parse(GAMEPAD).unwrap();
+        assert_eq!(
+            desc.application,
+            Some((USAGE_PAGE_GENERIC, USAGE_GENERIC_GAMEPAD))
+        );
+        assert!(desc.is_joystick());
+        assert!(desc.report_ids);
+        assert_eq!(desc.inputs.len(), 8);
+
+        let axis = &desc.inputs[0];
+        assert_eq!(
+            (axis.report_id, axis.bit_offset, axis.bit_size, axis.count),
+            (1, 0, 8, 4)
+        );
+        assert_eq!(axis.usage(3), Some((USAGE_PAGE_GENERIC, USAGE_GENERIC_RZ)));
+        assert_eq!((axis.logical_min, axis.logical_max), (0, 255));
+
+        let hat = &desc.inputs[1];
+        assert_eq!((hat.bit_offset, hat.bit_size), (32, 4));
+        assert_eq!(hat.usages, [(USAGE_PAGE_GENERIC, USAGE_GENERIC_HATSWITCH)]);
+        assert_eq!((hat.logical_min, hat.logical_max), (0, 7));
+        assert_eq!((hat.physical_min, hat.physical_max), (0, 315));
+        assert_eq!(hat.unit, UNIT_DEGREES);
+        assert!(hat.is_variable() && hat.has_null_state());
+
+        let buttons = &desc.inputs[2];
+        assert_eq!((buttons.bit_offset, buttons.count), (36, 12));
+        assert_eq!(buttons.usages.len(), 12);
+        assert_eq!(buttons.usage(11), Some((USAGE_PAGE_BUTTON, 12)));
+        assert_eq!(buttons.unit, 0);
+
+        // the globals of the slider are popped after it
+        let slider = &desc.inputs[3];
+        assert_eq!((slider.bit_offset, slider.bit_size), (48, 16));
+        assert_eq!((slider.logical_min, slider.logical_max), (-32768, 32767));
+        assert_eq!(slider.usages, [(USAGE_PAGE_GENERIC, USAGE_GENERIC_SLIDER)]);
+
+        let padding = &desc.inputs[4];
+        assert!(padding.is_constant());
+        assert_eq!((padding.bit_offset, padding.logical_max), (64, 1));
+
+        // offsets are counted per report
+        let throttle = &desc.inputs[5];
+        assert_eq!(
+            (throttle.report_id, throttle.bit_offset, throttle.bit_size),
+            (2, 0, 10)
+        );
+        assert_eq!(
+            throttle.usages,
+            [(USAGE_PAGE_SIMULATION, USAGE_SIMULATION_THROTTLE)]
+        );
+        assert_eq!((throttle.logical_min, throttle.logical_max), (0, 1023));
+
+        let array = &desc.inputs[7];
+        assert!(!array.is_variable());
+        assert_eq!((array.bit_offset, array.count), (16, 2));
+        assert_eq!(array.usages.first(), Some(&(USAGE_PAGE_BUTTON, 13)));
+        assert_eq!((array.logical_min, array.logical_max), (1, 4));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(ReportDescriptor::parse(&[0x05]).is_err());
+        assert!(ReportDescriptor::parse(&[0xc0]).is_err());
+        assert!(ReportDescriptor::parse(&[0xb4]).is_err());
+        assert!(ReportDescriptor::parse(&[0x85, 0x00]).is_err());
+
+        // report size beyond 32 bits
+        let size = [0x77, 0xff, 0xff, 0xff, 0xff, 0x95, 0x01, 0x81, 0x02];
+        assert!(ReportDescriptor::parse(&size).is_err());
+
+        // report longer than the bit offsets
+        let count = [0x75, 0x20, 0x97, 0xff, 0xff, 0xff, 0xff, 0x81, 0x02];
+        assert!(ReportDescriptor::parse(&count).is_err());
+    }
+
+    #[test]
+    fn report_count_limit() {
+        #[rustfmt::skip]
+        let raw = [
+            0x05, 0x09,                   // usage page (button)
+            0x09, 0x01,                   // usage (1)
+            0x25, 0x01,                   // logical max (1)
+            0x75, 0x01,                   // report size (1)
+            0x97, 0xff, 0xff, 0xff, 0x7f, // report count (2^31 - 1)
+            0x81, 0x02,                   // input (data, var, abs)
+        ];
+
+        let desc = ReportDescriptor::parse(&raw).unwrap();
+        let cap = DeviceCap::new(&desc).unwrap();
+        assert_eq!(cap.buttons_num, REPORT_COUNT_LIMIT as usize);
+    }
+
+    #[test]
+    fn buttons_cap() {
+        #[rustfmt::skip]
+        let raw = [
+            0x05, 0x09, // usage page (button)
+            0x09, 0x01, // usage (1)
+            0x25, 0x01, // logical max (1)
+            0x75, 0x01, // report size (1)
+            0x95, 0x28, // report count (40)
+            0x81, 0x02, // input (data, var, abs)
+        ];
+
+        let mut decoder = decoder(&raw);
+        assert_eq!(decoder.cap().buttons_num, 32);
+        assert_eq!(decoder.cap().info("pad".to_owned()).buttons_num, 32);
+
+        let diff = decoder.feed(&[0xff, 0xff, 0xff, 0xff, 0xff]).unwrap();
+        assert_eq!(diff.buttons, (u32::MAX, u32::MAX));
+    }
+
+    #[test]
+    fn device_cap() {
+        let desc = ReportDescriptor::parse(GAMEPAD).unwrap();
+        let cap = DeviceCap::new(&desc).unwrap();
+
+        assert_eq!(cap.buttons_num, 16);
+        assert!(cap.hats[0].is_some() && cap.hats[1].is_none());
+
+        let info = cap.info("pad".to_owned());
+        assert_eq!(info.hats, 1);
+        for ident in [AxisIdent::X, AxisIdent::Y, AxisIdent::Z, AxisIdent::RZ] {
+            assert_eq!(info.axis[ident as usize], Some((0, 255)));
+        }
+        assert_eq!(info.axis[AxisIdent::Throttle as usize], Some((0, 1023)));
+        assert_eq!(info.axis[AxisIdent::RX as usize], None);
+        assert_eq!(
+            info.sliders,
+            [SliderInfo::new(SliderKind::Slider, (-32768, 32767))]
+        );
+    }
+
+    #[test]
+    fn decode_reports() {
+        let mut decoder = decoder(GAMEPAD);
+
+        // hat at 90 degrees, buttons 1, 4 & 12, slider at -2
+        let diff = decoder
+            .feed(&[0x01, 0x10, 0x80, 0xff, 0x00, 0x92, 0x80, 0xfe, 0xff, 0x00])
+            .unwrap();
+        assert_eq!(diff.dpad[0], Some(DPadState::Right));
+        assert_eq!(diff.buttons, (0x809, 0x809));
+        assert_eq!(diff.axis[AxisIdent::X as usize], Some(0x10));
+        assert_eq!(diff.axis[AxisIdent::Y as usize], Some(0x80));
+        assert_eq!(diff.axis[AxisIdent::Z as usize], Some(0xff));
+        assert_eq!(diff.axis[AxisIdent::RZ as usize], Some(0));
+        assert_eq!(diff.sliders[0], Some(-2));
+
+        // throttle at 1000, second button of the array pressed
+        let diff = decoder.feed(&[0x02, 0xe8, 0x03, 0x02, 0x00]).unwrap();
+        assert_eq!(diff.axis[AxisIdent::Throttle as usize], Some(1000));
+        assert_eq!(diff.axis[AxisIdent::X as usize], None);
+        assert_eq!(diff.buttons, (1 << 13, 0x809 | 1 << 13));
+        assert_eq!(diff.dpad[0], None);
+
+        // null hat
+        let diff = decoder
+            .feed(&[0x01, 0x10, 0x80, 0xff, 0x00, 0x98, 0x80, 0xfe, 0xff, 0x00])
+            .unwrap();
+        assert_eq!(diff.dpad[0], Some(DPadState::Null));
+        assert_eq!(diff.buttons.0, 0);
+
+        assert!(decoder.feed(&[0x01, 0x10]).is_err());
+        assert!(decoder.feed(&[]).is_err());
+    }
+
+    #[test]
+    fn decode_without_report_ids() {
+        let desc = ReportDescriptor::parse(JOYSTICK).unwrap();
+        assert_eq!(
+            desc.application,
+            Some((USAGE_PAGE_GENERIC, USAGE_GENERIC_JOYSTICK))
+        );
+        assert!(!desc.report_ids);
+
+        let mut decoder = decoder(JOYSTICK);
+
+        // button 2, hat at position 1 of 4
+        let diff = decoder.feed(&[0b0110]).unwrap();
+        assert_eq!(diff.buttons, (0b10, 0b10));
+        assert_eq!(diff.dpad[0], Some(DPadState::Right));
+        assert!(decoder
+            .cap()
+            .info(String::new())
+            .axis
+            .iter()
+            .all(Option::is_none));
+    }
+
+    #[test]
+    fn signed_bits() {
+        assert_eq!(extract_bits(&[0xf0, 0x0f], 4, 8, false).unwrap(), 0xff);
+        assert_eq!(extract_bits(&[0xf0, 0x0f], 4, 8, true).unwrap(), -1);
+        assert_eq!(extract_bits(&[0x00, 0x80], 8, 8, true).unwrap(), -128);
+        assert!(extract_bits(&[0x00], 4, 8, false).is_err());
+    }
+}
@@ -0,0 +1,487 @@
+//! DualShock 4 input reports, with the vendor defined touchpad, motion & battery data
+//!
+//! Generic HID parsing only sees the buttons & axis of the reports, these are
+//! decoded from the raw bytes of the full reports, USB 0x01 or Bluetooth 0x11,
+//! which the drivers emit as `driver::Event::Ds4`.
+//! Output reports for the rumble & the lightbar are built the same way.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use super::bt_output_crc32;
+use crate::driver::{DeviceInfo, BUS_BLUETOOTH};
+
+pub const VENDOR_SONY: u16 = 0x054c;
+pub const PRODUCT_DS4: u16 = 0x05c4;
+pub const PRODUCT_DS4_V2: u16 = 0x09cc;
+pub const PRODUCT_DS4_DONGLE: u16 = 0x0ba0;
+
+pub const REPORT_USB: u8 = 0x01;
+pub const REPORT_BT: u8 = 0x11;
+
+/// full report over USB, including the report id
+pub const REPORT_USB_LEN: usize = 64;
+/// full report over Bluetooth, including the report id & the trailing CRC
+pub const REPORT_BT_LEN: usize = 78;
+
+pub const OUTPUT_USB: u8 = 0x05;
+pub const OUTPUT_USB_LEN: usize = 32;
+pub const OUTPUT_BT: u8 = 0x11;
+pub const OUTPUT_BT_LEN: usize = 78;
+
+/// touchpad coordinates are in `[0, TOUCHPAD_WIDTH)` & `[0, TOUCHPAD_HEIGHT)`
+pub const TOUCHPAD_WIDTH: u16 = 1920;
+pub const TOUCHPAD_HEIGHT: u16 = 943;
+
+/// number of fingers tracked by the touchpad
+pub const TOUCH_LIMIT: usize = 2;
+
+pub fn is_ds4(vendor_id: u16, product_id: u16) -> bool {
+    vendor_id == VENDOR_SONY
+        && matches!(
+            product_id,
+            PRODUCT_DS4 | PRODUCT_DS4_V2 | PRODUCT_DS4_DONGLE
+        )
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TouchPoint {
+    /// tracking id, incremented on each new touch
+    pub id: u8,
+    pub x: u16,
+    pub y: u16,
+}
+
+/// vendor defined data of an input report
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Ds4Report {
+    /// sensor clock, in units of about 5.33us, wrapping around
+    pub timestamp: u16,
+    /// angular velocities around x, y & z
+    pub gyro: [i16; 3],
+    /// accelerations along x, y & z
+    pub accel: [i16; 3],
+    /// fingers on the touchpad, by slot
+    pub touch: [Option<TouchPoint>; TOUCH_LIMIT],
+    /// in percent, `None` when the controller reports a battery error
+    pub battery: Option<u8>,
+    /// on cable and not full yet
+    pub charging: bool,
+}
+
+impl Ds4Report {
+    /// parse a full input report, starting with its report id
+    pub fn parse(report: &[u8]) -> Result<Self> {
+        // the Bluetooth report is the USB one behind 2 more bytes
+        let data = match report.first() {
+            Some(&REPORT_USB) if report.len() >= REPORT_USB_LEN => &report[1..],
+            Some(&REPORT_USB) => {
+                return Err(anyhow!(
+                    "reduced report of {} bytes, without motion data",
+                    report.len()
+                ))
+            }
+            Some(&REPORT_BT) if report.len() >= REPORT_BT_LEN => &report[3..],
+            Some(&REPORT_BT) => return Err(anyhow!("bluetooth report too short")),
+            Some(id) => return Err(anyhow!("unknown report id {:#04x}", id)),
+            None => return Err(anyhow!("empty report")),
+        };
+
+        let word = |pos: usize| i16::from_le_bytes([data[pos], data[pos + 1]]);
+
+        let (battery, charging) = battery_status(data[29]);
+
+        let mut touch = [None; TOUCH_LIMIT];
+        for (slot, point) in touch.iter_mut().enumerate() {
+            // only the latest touch packet is read
+            let finger = &data[34 + slot * 4..38 + slot * 4];
+            if finger[0] & 0x80 == 0 {
+                point.replace(TouchPoint {
+                    id: finger[0] & 0x7f,
+                    x: u16::from(finger[1]) | (u16::from(finger[2] & 0x0f) << 8),
+                    y: u16::from(finger[2] >> 4) | (u16::from(finger[3]) << 4),
+                });
+            }
+        }
+
+        Ok(Self {
+            timestamp: word(9) as u16,
+            gyro: [word(12), word(14), word(16)],
+            accel: [word(18), word(20), word(22)],
+            touch,
+            battery,
+            charging,
+        })
+    }
+}
+
+/// battery level & charging state of the status byte, mapped like the kernel
+/// hid-playstation driver does
+///
+/// The low nibble counts tenths of the capacity, reported at their midpoints,
+/// up to 10 on battery and 11 on cable, both full; 14 & 15 are errors.
+fn battery_status(status: u8) -> (Option<u8>, bool) {
+    let cable = status & 0x10 != 0;
+    match status & 0x0f {
+        level @ 0..=9 => (Some(level * 10 + 5), cable),
+        10 | 11 => (Some(100), false),
+        _ => (None, false),
+    }
+}
+
+/// changes of the vendor defined data, see `driver::Event::Ds4`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ds4Event {
+    TouchStart(usize, TouchPoint),
+    TouchMove(usize, TouchPoint),
+    TouchEnd(usize, TouchPoint),
+    Gyro([i16; 3]),
+    Accel([i16; 3]),
+    /// the level in percent, `None` on battery errors
+    Battery {
+        level: Option<u8>,
+        charging: bool,
+    },
+}
+
+/// turns the successive reports of a device into events of the changed data
+#[derive(Debug, Default, Clone)]
+pub struct Ds4Decoder {
+    prev: Option<Ds4Report>,
+}
+
+impl Ds4Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// latest parsed report
+    pub fn report(&self) -> Option<&Ds4Report> {
+        self.prev.as_ref()
+    }
+
+    /// parse the report and collect the changes since the previous one
+    pub fn feed(&mut self, report: &[u8]) -> Result<Vec<Ds4Event>> {
+        let report = Ds4Report::parse(report)?;
+        let prev = self.prev.replace(report).unwrap_or_default();
+        let mut events = Vec::new();
+
+        for (slot, (cur, old)) in report.touch.iter().zip(prev.touch.iter()).enumerate() {
+            match (old, cur) {
+                (None, Some(p)) => events.push(Ds4Event::TouchStart(slot, *p)),
+                (Some(o), None) => events.push(Ds4Event::TouchEnd(slot, *o)),
+                // a new tracking id in the same slot is a new touch
+                (Some(o), Some(p)) if o.id != p.id => {
+                    events.push(Ds4Event::TouchEnd(slot, *o));
+                    events.push(Ds4Event::TouchStart(slot, *p));
+                }
+                (Some(o), Some(p)) if o != p => events.push(Ds4Event::TouchMove(slot, *p)),
+                _ => {}
+            }
+        }
+
+        if report.gyro != prev.gyro {
+            events.push(Ds4Event::Gyro(report.gyro));
+        }
+
+        if report.accel != prev.accel {
+            events.push(Ds4Event::Accel(report.accel));
+        }
+
+        if (report.battery, report.charging) != (prev.battery, prev.charging) {
+            events.push(Ds4Event::Battery {
+                level: report.battery,
+                charging: report.charging,
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+/// rumble & lightbar states, the ones left to none are kept unchanged by the report
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Ds4Output {
+    /// strengths of the heavy motor on the left, and of the light one on the right
+    pub rumble: Option<(u8, u8)>,
+    pub lightbar: Option<[u8; 3]>,
+    /// on & off durations of the lightbar flashes, in units of 10ms, steady when both are zero
+    pub flash: Option<(u8, u8)>,
+}
+
+impl Ds4Output {
+    /// the report matching the bus of the device
+    pub fn report(&self, info: &DeviceInfo) -> Vec<u8> {
+        match info.guid.bus() {
+            BUS_BLUETOOTH => self.bt_report().to_vec(),
+            _ => self.usb_report().to_vec(),
+        }
+    }
+
+    pub fn usb_report(&self) -> [u8; OUTPUT_USB_LEN] {
+        let mut report = [0u8; OUTPUT_USB_LEN];
+        report[0] = OUTPUT_USB;
+        self.fill(&mut report[1..]);
+        report
+    }
+
+    pub fn bt_report(&self) -> [u8; OUTPUT_BT_LEN] {
+        let mut report = [0u8; OUTPUT_BT_LEN];
+        report[0] = OUTPUT_BT;
+        // HID & CRC flags, with the fastest input report rate
+        report[1] = 0xc0;
+        self.fill(&mut report[3..]);
+
+        let crc = bt_output_crc32(&report[..OUTPUT_BT_LEN - 4]);
+        report[OUTPUT_BT_LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+        report
+    }
+
+    /// the common part following the report id over USB
+    fn fill(&self, data: &mut [u8]) {
+        if let Some((strong, weak)) = self.rumble {
+            data[0] |= 0x01;
+            data[3] = weak;
+            data[4] = strong;
+        }
+
+        if let Some(rgb) = self.lightbar {
+            data[0] |= 0x02;
+            data[5..8].copy_from_slice(&rgb);
+        }
+
+        if let Some((on, off)) = self.flash {
+            data[0] |= 0x04;
+            data[8] = on;
+            data[9] = off;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{DeviceGuid, BUS_USB};
+
+    /// USB report of a DS4 v2 on cable, fully charged, a finger on the touchpad
+    const USB_REPORT: &str = "017f81807e08002c00009c3a17fdff0500010088ffb81fb40500000000001b00\
+                              00014e05c0731d84000000000000000000000000000000000000000000000000";
+
+    /// the same state over Bluetooth, on battery
+    const BT_REPORT: &str = "11c0007f81807e08002c00009c3a17fdff0500010088ffb81fb4050000000000\
+                             050000014e05c0731d8400000000000000000000000000000000000000000000\
+                             00000000000000000000026f0eee";
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// set the first finger of a USB report, lifted when none
+    fn touch(report: &mut [u8], finger: Option<(u8, u16, u16)>) {
+        report[35..39].copy_from_slice(&match finger {
+            Some((id, x, y)) => [
+                id & 0x7f,
+                x as u8,
+                (x >> 8) as u8 | (y << 4) as u8,
+                (y >> 4) as u8,
+            ],
+            None => [0x85, 0, 0, 0],
+        });
+    }
+
+    #[test]
+    fn output_usb() {
+        let output = Ds4Output {
+            rumble: Some((0x40, 0x80)),
+            lightbar: Some([0x00, 0x00, 0xff]),
+            flash: Some((0x10, 0x20)),
+        };
+
+        assert_eq!(
+            output.usb_report().to_vec(),
+            hex("0507000080400000ff1020000000000000000000000000000000000000000000")
+        );
+    }
+
+    #[test]
+    fn output_bt() {
+        let output = Ds4Output {
+            rumble: Some((0x40, 0x80)),
+            lightbar: Some([0x00, 0x00, 0xff]),
+            flash: Some((0x10, 0x20)),
+        };
+
+        let mut expected = [0u8; OUTPUT_BT_LEN];
+        expected[..13].copy_from_slice(&hex("11c00007000080400000ff1020"));
+        // CRC32 of the 0xa2 output header & the report
+        expected[74..].copy_from_slice(&0xc614_57c5u32.to_le_bytes());
+        assert_eq!(output.bt_report(), expected);
+
+        let rumble = Ds4Output {
+            rumble: Some((0x00, 0xff)),
+            ..Default::default()
+        };
+
+        let mut expected = [0u8; OUTPUT_BT_LEN];
+        expected[..8].copy_from_slice(&hex("11c000010000ff00"));
+        expected[74..].copy_from_slice(&0x0ed6_dc64u32.to_le_bytes());
+        assert_eq!(rumble.bt_report(), expected);
+    }
+
+    #[test]
+    fn output_bus() {
+        let output = Ds4Output {
+            lightbar: Some([0xff, 0x00, 0x00]),
+            ..Default::default()
+        };
+
+        let mut info = DeviceInfo {
+            guid: DeviceGuid::from_ids(BUS_USB, VENDOR_SONY, PRODUCT_DS4_V2, 0x0100),
+            ..Default::default()
+        };
+        assert_eq!(output.report(&info), output.usb_report());
+
+        info.guid = DeviceGuid::from_ids(BUS_BLUETOOTH, VENDOR_SONY, PRODUCT_DS4_V2, 0x0100);
+        assert_eq!(output.report(&info), output.bt_report());
+    }
+
+    #[test]
+    fn parse_usb() {
+        let report = Ds4Report::parse(&hex(USB_REPORT)).unwrap();
+
+        assert_eq!(report.timestamp, 0x3a9c);
+        assert_eq!(report.gyro, [-3, 5, 1]);
+        assert_eq!(report.accel, [-120, 8120, 1460]);
+        assert_eq!(
+            report.touch,
+            [
+                Some(TouchPoint {
+                    id: 5,
+                    x: 960,
+                    y: 471
+                }),
+                None
+            ]
+        );
+        assert_eq!((report.battery, report.charging), (Some(100), false));
+    }
+
+    #[test]
+    fn parse_bt() {
+        let usb = Ds4Report::parse(&hex(USB_REPORT)).unwrap();
+        let bt = Ds4Report::parse(&hex(BT_REPORT)).unwrap();
+
+        assert_eq!(bt.timestamp, usb.timestamp);
+        assert_eq!(bt.gyro, usb.gyro);
+        assert_eq!(bt.accel, usb.accel);
+        assert_eq!(bt.touch, usb.touch);
+        assert_eq!((bt.battery, bt.charging), (Some(55), false));
+    }
+
+    #[test]
+    fn parse_short() {
+        // reduced report sent over Bluetooth before the full ones are requested
+        let reduced = &hex(USB_REPORT)[..10];
+        assert!(Ds4Report::parse(reduced).is_err());
+        assert!(Ds4Report::parse(&hex(BT_REPORT)[..40]).is_err());
+        assert!(Ds4Report::parse(&[0x05, 0x00]).is_err());
+        assert!(Ds4Report::parse(&[]).is_err());
+    }
+
+    #[test]
+    fn battery_nibble() {
+        let mut report = hex(USB_REPORT);
+        for (status, battery, charging) in [
+            (0x00, Some(5), false),
+            (0x07, Some(75), false),
+            (0x0a, Some(100), false),
+            (0x0e, None, false),
+            (0x10, Some(5), true),
+            (0x15, Some(55), true),
+            // full on cable
+            (0x1a, Some(100), false),
+            (0x1b, Some(100), false),
+            // errors
+            (0x1e, None, false),
+            (0x1f, None, false),
+        ] {
+            report[30] = status;
+            let parsed = Ds4Report::parse(&report).unwrap();
+            assert_eq!(
+                (parsed.battery, parsed.charging),
+                (battery, charging),
+                "status {:#04x}",
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn touch_events() {
+        let mut report = hex(USB_REPORT);
+        touch(&mut report, None);
+
+        let mut decoder = Ds4Decoder::new();
+        let first = decoder.feed(&report).unwrap();
+        assert!(!first
+            .iter()
+            .any(|e| matches!(e, Ds4Event::TouchStart(..) | Ds4Event::TouchEnd(..))));
+
+        let point = |id, x, y| TouchPoint { id, x, y };
+
+        touch(&mut report, Some((6, 100, 200)));
+        assert_eq!(
+            decoder.feed(&report).unwrap(),
+            [Ds4Event::TouchStart(0, point(6, 100, 200))]
+        );
+
+        // unchanged reports are silent
+        assert_eq!(decoder.feed(&report).unwrap(), []);
+
+        touch(&mut report, Some((6, 120, 190)));
+        assert_eq!(
+            decoder.feed(&report).unwrap(),
+            [Ds4Event::TouchMove(0, point(6, 120, 190))]
+        );
+
+        // lifted & touched again between two reports
+        touch(&mut report, Some((7, 1800, 900)));
+        assert_eq!(
+            decoder.feed(&report).unwrap(),
+            [
+                Ds4Event::TouchEnd(0, point(6, 120, 190)),
+                Ds4Event::TouchStart(0, point(7, 1800, 900)),
+            ]
+        );
+
+        touch(&mut report, None);
+        assert_eq!(
+            decoder.feed(&report).unwrap(),
+            [Ds4Event::TouchEnd(0, point(7, 1800, 900))]
+        );
+    }
+
+    #[test]
+    fn motion_battery_events() {
+        let mut report = hex(USB_REPORT);
+        let mut decoder = Ds4Decoder::new();
+        decoder.feed(&report).unwrap();
+
+        report[13..15].copy_from_slice(&16i16.to_le_bytes());
+        report[30] = 0x15;
+        assert_eq!(
+            decoder.feed(&report).unwrap(),
+            [
+                Ds4Event::Gyro([16, 5, 1]),
+                Ds4Event::Battery {
+                    level: Some(55),
+                    charging: true
+                },
+            ]
+        );
+    }
+}
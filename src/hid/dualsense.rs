@@ -0,0 +1,135 @@
+//! DualSense output reports, for the rumble, the lightbar & the player LEDs
+
+use super::bt_output_crc32;
+use crate::driver::{DeviceInfo, BUS_BLUETOOTH};
+
+pub const PRODUCT_DUALSENSE: u16 = 0x0ce6;
+pub const PRODUCT_DUALSENSE_EDGE: u16 = 0x0df2;
+
+pub const OUTPUT_USB: u8 = 0x02;
+pub const OUTPUT_USB_LEN: usize = 63;
+pub const OUTPUT_BT: u8 = 0x31;
+pub const OUTPUT_BT_LEN: usize = 78;
+
+/// the LEDs lit for the first players, from the center outwards
+pub const PLAYER_LEDS: [u8; 5] = [0x04, 0x0a, 0x15, 0x1b, 0x1f];
+
+pub fn is_dualsense(vendor_id: u16, product_id: u16) -> bool {
+    vendor_id == super::ds4::VENDOR_SONY
+        && matches!(product_id, PRODUCT_DUALSENSE | PRODUCT_DUALSENSE_EDGE)
+}
+
+/// rumble, lightbar & player LEDs states, the ones left to none are kept unchanged by the report
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DualSenseOutput {
+    /// strengths of the heavy motor on the left, and of the light one on the right
+    pub rumble: Option<(u8, u8)>,
+    pub lightbar: Option<[u8; 3]>,
+    /// lit LEDs of the 5 under the touchpad, in the low bits, see `PLAYER_LEDS`
+    pub player_leds: Option<u8>,
+    /// sequence number of the Bluetooth reports, in `[0, 15]`, to be increased on each report
+    pub seq: u8,
+}
+
+impl DualSenseOutput {
+    /// the report matching the bus of the device
+    pub fn report(&self, info: &DeviceInfo) -> Vec<u8> {
+        match info.guid.bus() {
+            BUS_BLUETOOTH => self.bt_report().to_vec(),
+            _ => self.usb_report().to_vec(),
+        }
+    }
+
+    pub fn usb_report(&self) -> [u8; OUTPUT_USB_LEN] {
+        let mut report = [0u8; OUTPUT_USB_LEN];
+        report[0] = OUTPUT_USB;
+        self.fill(&mut report[1..]);
+        report
+    }
+
+    pub fn bt_report(&self) -> [u8; OUTPUT_BT_LEN] {
+        let mut report = [0u8; OUTPUT_BT_LEN];
+        report[0] = OUTPUT_BT;
+        report[1] = (self.seq & 0x0f) << 4;
+        // tag of the report
+        report[2] = 0x10;
+        self.fill(&mut report[3..]);
+
+        let crc = bt_output_crc32(&report[..OUTPUT_BT_LEN - 4]);
+        report[OUTPUT_BT_LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+        report
+    }
+
+    /// the common part following the report id over USB
+    fn fill(&self, data: &mut [u8]) {
+        if let Some((strong, weak)) = self.rumble {
+            // rumble through the compatible vibration mode
+            data[0] |= 0x03;
+            data[2] = weak;
+            data[3] = strong;
+        }
+
+        if let Some(rgb) = self.lightbar {
+            data[1] |= 0x04;
+            data[44..47].copy_from_slice(&rgb);
+        }
+
+        if let Some(leds) = self.player_leds {
+            data[1] |= 0x10;
+            data[43] = leds & 0x1f;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output() -> DualSenseOutput {
+        DualSenseOutput {
+            rumble: Some((0x40, 0x80)),
+            lightbar: Some([10, 20, 30]),
+            player_leds: Some(PLAYER_LEDS[0]),
+            seq: 3,
+        }
+    }
+
+    #[test]
+    fn output_usb() {
+        let mut expected = [0u8; OUTPUT_USB_LEN];
+        // report id, flags of the compatible rumble, lightbar & player LEDs, weak & strong motors
+        expected[..5].copy_from_slice(&[0x02, 0x03, 0x14, 0x80, 0x40]);
+        expected[1 + 43] = 0x04;
+        expected[1 + 44..1 + 47].copy_from_slice(&[10, 20, 30]);
+
+        assert_eq!(output().usb_report(), expected);
+    }
+
+    #[test]
+    fn output_bt() {
+        let mut expected = [0u8; OUTPUT_BT_LEN];
+        expected[..7].copy_from_slice(&[0x31, 0x30, 0x10, 0x03, 0x14, 0x80, 0x40]);
+        expected[3 + 43] = 0x04;
+        expected[3 + 44..3 + 47].copy_from_slice(&[10, 20, 30]);
+        // CRC32 of the 0xa2 output header & the report
+        expected[74..].copy_from_slice(&0xad34_bf0fu32.to_le_bytes());
+
+        assert_eq!(output().bt_report(), expected);
+    }
+
+    #[test]
+    fn output_unchanged() {
+        let output = DualSenseOutput {
+            player_leds: Some(0xff),
+            seq: 0x1f,
+            ..Default::default()
+        };
+
+        let report = output.bt_report();
+        // the sequence number wraps, the LEDs beyond the 5 ones are dropped
+        assert_eq!(report[1], 0xf0);
+        assert_eq!(&report[3..7], &[0x00, 0x10, 0x00, 0x00]);
+        assert_eq!(report[3 + 43], 0x1f);
+        assert_eq!(&report[3 + 44..3 + 47], &[0, 0, 0]);
+    }
+}
@@ -0,0 +1,210 @@
+//! platform independent HID definitions & parsers
+
+use crate::{AxisIdent, ButtonIdent, DPadState, HatIdent, SliderIdent, SliderKind};
+
+pub mod descriptor;
+pub mod ds4;
+pub mod dualsense;
+
+pub const USAGE_PAGE_GENERIC: u16 = 0x01;
+pub const USAGE_PAGE_SIMULATION: u16 = 0x02;
+pub const USAGE_PAGE_BUTTON: u16 = 0x09;
+
+pub const USAGE_GENERIC_JOYSTICK: u16 = 0x04;
+pub const USAGE_GENERIC_GAMEPAD: u16 = 0x05;
+pub const USAGE_GENERIC_X: u16 = 0x30;
+pub const USAGE_GENERIC_Y: u16 = 0x31;
+pub const USAGE_GENERIC_Z: u16 = 0x32;
+pub const USAGE_GENERIC_RX: u16 = 0x33;
+pub const USAGE_GENERIC_RY: u16 = 0x34;
+pub const USAGE_GENERIC_RZ: u16 = 0x35;
+pub const USAGE_GENERIC_SLIDER: u16 = 0x36;
+pub const USAGE_GENERIC_DIAL: u16 = 0x37;
+pub const USAGE_GENERIC_WHEEL: u16 = 0x38;
+pub const USAGE_GENERIC_HATSWITCH: u16 = 0x39;
+
+pub const USAGE_SIMULATION_RUDDER: u16 = 0xba;
+pub const USAGE_SIMULATION_THROTTLE: u16 = 0xbb;
+pub const USAGE_SIMULATION_ACCELERATOR: u16 = 0xc4;
+pub const USAGE_SIMULATION_BRAKE: u16 = 0xc5;
+pub const USAGE_SIMULATION_CLUTCH: u16 = 0xc6;
+pub const USAGE_SIMULATION_STEERING: u16 = 0xc8;
+
+/// the axis slot of a usage
+pub fn axis_ident(page: u16, usage: u16) -> Option<AxisIdent> {
+    Some(match (page, usage) {
+        (USAGE_PAGE_GENERIC, USAGE_GENERIC_X) => AxisIdent::X,
+        (USAGE_PAGE_GENERIC, USAGE_GENERIC_Y) => AxisIdent::Y,
+        (USAGE_PAGE_GENERIC, USAGE_GENERIC_Z) => AxisIdent::Z,
+        (USAGE_PAGE_GENERIC, USAGE_GENERIC_RX) => AxisIdent::RX,
+        (USAGE_PAGE_GENERIC, USAGE_GENERIC_RY) => AxisIdent::RY,
+        (USAGE_PAGE_GENERIC, USAGE_GENERIC_RZ) => AxisIdent::RZ,
+        (USAGE_PAGE_SIMULATION, USAGE_SIMULATION_THROTTLE) => AxisIdent::Throttle,
+        (USAGE_PAGE_SIMULATION, USAGE_SIMULATION_RUDDER) => AxisIdent::Rudder,
+        (USAGE_PAGE_SIMULATION, USAGE_SIMULATION_STEERING) => AxisIdent::Steering,
+        (USAGE_PAGE_SIMULATION, USAGE_SIMULATION_ACCELERATOR) => AxisIdent::Accelerator,
+        (USAGE_PAGE_SIMULATION, USAGE_SIMULATION_BRAKE) => AxisIdent::Brake,
+        (USAGE_PAGE_SIMULATION, USAGE_SIMULATION_CLUTCH) => AxisIdent::Clutch,
+        _ => return None,
+    })
+}
+
+/// the kind of the slider of a usage
+pub fn slider_kind(page: u16, usage: u16) -> Option<SliderKind> {
+    match (page, usage) {
+        (USAGE_PAGE_GENERIC, USAGE_GENERIC_SLIDER) => Some(SliderKind::Slider),
+        (USAGE_PAGE_GENERIC, USAGE_GENERIC_DIAL) => Some(SliderKind::Dial),
+        (USAGE_PAGE_GENERIC, USAGE_GENERIC_WHEEL) => Some(SliderKind::Wheel),
+        _ => None,
+    }
+}
+
+/// CRC32 of a Bluetooth output report, seeded with the 0xa2 header of HID output transactions
+pub fn bt_output_crc32(report: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in [0xa2].iter().chain(report) {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+
+    !crc
+}
+
+/// the joystick object a HID control is mapped onto
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceObjectIndex {
+    DPad(HatIdent),
+    Button(ButtonIdent),
+    Axis(AxisIdent),
+    Slider(SliderIdent),
+}
+
+/// english rotation system, in degrees
+pub const UNIT_DEGREES: u32 = 0x14;
+
+/// decodes hat switch values into dpad states, from the ranges & unit of the hat
+///
+/// Hats are either counted in positions clockwise from up, e.g. 0..=7, 1..=8 or
+/// 0..=3 for 4-way hats, or reported as angles in degrees. Values out of the
+/// logical range are the null states of the hats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HatDecoder {
+    logical: (i32, i32),
+    kind: HatKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HatKind {
+    /// number of the 8 directions per logical step
+    Positions(i32),
+    /// physical range in degrees
+    Degrees(i32, i32),
+}
+
+impl HatDecoder {
+    /// `None` if the ranges describe neither positions nor angles; the physical
+    /// range defaults to the logical one when both of its bounds are zero
+    pub fn new(logical: (i32, i32), physical: (i32, i32), unit: u32) -> Option<Self> {
+        let (lmin, lmax) = logical;
+        if lmax <= lmin {
+            return None;
+        }
+
+        let (pmin, pmax) = match physical {
+            (0, 0) => logical,
+            p => p,
+        };
+
+        // the ranges come from the devices, spans are computed wide
+        let degrees = pmax > pmin
+            && (unit == UNIT_DEGREES || matches!(pmax as i64 - pmin as i64, 270 | 315 | 359));
+        let kind = if degrees {
+            HatKind::Degrees(pmin, pmax)
+        } else {
+            match lmax as i64 - lmin as i64 + 1 {
+                8 => HatKind::Positions(1),
+                4 => HatKind::Positions(2),
+                _ => return None,
+            }
+        };
+
+        Some(Self { logical, kind })
+    }
+
+    pub fn decode(&self, value: i32) -> DPadState {
+        let (lmin, lmax) = self.logical;
+        if value < lmin || value > lmax {
+            return DPadState::Null;
+        }
+
+        let direction = match self.kind {
+            HatKind::Positions(step) => (value - lmin) * step,
+            HatKind::Degrees(pmin, pmax) => {
+                let angle = pmin as i128
+                    + (value as i128 - lmin as i128) * (pmax as i128 - pmin as i128)
+                        / (lmax as i128 - lmin as i128);
+                ((angle.rem_euclid(360) + 22) / 45 % 8) as i32
+            }
+        };
+
+        match direction {
+            0 => DPadState::Up,
+            1 => DPadState::UpRight,
+            2 => DPadState::Right,
+            3 => DPadState::DownRight,
+            4 => DPadState::Down,
+            5 => DPadState::DownLeft,
+            6 => DPadState::Left,
+            7 => DPadState::UpLeft,
+            _other => DPadState::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hat_positions() {
+        let hat = HatDecoder::new((0, 7), (0, 0), 0).unwrap();
+        assert_eq!(hat.decode(0), DPadState::Up);
+        assert_eq!(hat.decode(3), DPadState::DownRight);
+        assert_eq!(hat.decode(8), DPadState::Null);
+
+        let hat = HatDecoder::new((1, 8), (0, 0), 0).unwrap();
+        assert_eq!(hat.decode(1), DPadState::Up);
+        assert_eq!(hat.decode(0), DPadState::Null);
+
+        let hat = HatDecoder::new((0, 3), (0, 0), 0).unwrap();
+        assert_eq!(hat.decode(3), DPadState::Left);
+
+        assert!(HatDecoder::new((0, 5), (0, 0), 0).is_none());
+    }
+
+    #[test]
+    fn hat_degrees() {
+        let hat = HatDecoder::new((0, 7), (0, 315), UNIT_DEGREES).unwrap();
+        assert_eq!(hat.decode(2), DPadState::Right);
+        assert_eq!(hat.decode(7), DPadState::UpLeft);
+
+        let hat = HatDecoder::new((0, 359), (0, 359), UNIT_DEGREES).unwrap();
+        assert_eq!(hat.decode(180), DPadState::Down);
+        assert_eq!(hat.decode(350), DPadState::Up);
+    }
+
+    #[test]
+    fn hat_extreme_ranges() {
+        assert!(HatDecoder::new((-1, i32::MAX), (0, 0), 0).is_none());
+        assert!(HatDecoder::new((0, 7), (i32::MAX, i32::MIN), UNIT_DEGREES).is_some());
+
+        // values over the whole range decode without overflows
+        let hat =
+            HatDecoder::new((i32::MIN, i32::MAX), (i32::MIN, i32::MAX), UNIT_DEGREES).unwrap();
+        for value in [i32::MIN, -1, 0, i32::MAX] {
+            hat.decode(value);
+        }
+    }
+}
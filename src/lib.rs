@@ -1,8 +1,19 @@
+use std::{collections::BTreeSet, sync::Mutex};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub mod bindings;
 pub mod driver;
+pub mod gesture;
+pub mod hid;
 pub mod logging;
+pub mod normalize;
+pub mod players;
 pub mod profile;
+pub mod response;
+pub mod state;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DPadState {
     Null,
     Up,
@@ -33,6 +44,72 @@ pub enum Button {
     Other(&'static str),
 }
 
+/// names of the built-in variants, shared by `name` and `from_name`
+macro_rules! named_variants {
+    ($t:ident { $($v:ident),+ $(,)? }) => {
+        impl $t {
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$v => stringify!($v),)+
+                    Self::Other(name) => name,
+                }
+            }
+
+            /// parse a variant by its name, unknown names become `Other`
+            pub fn from_name(name: &str) -> Self {
+                match name {
+                    $(stringify!($v) => Self::$v,)+
+                    other => Self::Other(intern(other)),
+                }
+            }
+        }
+
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.name())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let name = String::deserialize(deserializer)?;
+                Ok(Self::from_name(&name))
+            }
+        }
+    };
+}
+
+/// keep a name for the rest of the process, so that it can be carried by `Other` variants
+pub(crate) fn intern(name: &str) -> &'static str {
+    static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+    let mut names = NAMES.lock().unwrap_or_else(|e| e.into_inner());
+    match names.get(name) {
+        Some(n) => n,
+        None => {
+            let n: &'static str = Box::leak(name.to_owned().into_boxed_str());
+            names.insert(n);
+            n
+        }
+    }
+}
+
+named_variants!(Button {
+    Start,
+    Select,
+    Mode,
+    LThumb,
+    RThumb,
+    LShoulder,
+    RShoulder,
+    LTrigger,
+    RTrigger,
+    North,
+    South,
+    East,
+    West,
+});
+
 pub type ButtonIdent = usize;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -51,7 +128,7 @@ impl From<bool> for ButtonState {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Axis {
     LThumbX,
     LThumbY,
@@ -59,11 +136,34 @@ pub enum Axis {
     RThumbY,
     LTrigger,
     RTrigger,
+    Throttle,
+    Rudder,
+    Steering,
+    Accelerator,
+    Brake,
+    Clutch,
     Other(&'static str),
 }
 
+named_variants!(Axis {
+    LThumbX,
+    LThumbY,
+    RThumbX,
+    RThumbY,
+    LTrigger,
+    RTrigger,
+    Throttle,
+    Rudder,
+    Steering,
+    Accelerator,
+    Brake,
+    Clutch,
+});
+
+/// slots of the axis, the generic desktop ones followed by the simulation controls
 #[repr(usize)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum AxisIdent {
     X = 0,
     Y = 1,
@@ -71,14 +171,59 @@ pub enum AxisIdent {
     RX = 3,
     RY = 4,
     RZ = 5,
-    Limit = 6,
+    Throttle = 6,
+    Rudder = 7,
+    Steering = 8,
+    Accelerator = 9,
+    Brake = 10,
+    Clutch = 11,
+    #[serde(skip)]
+    Limit = 12,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// part of the value range of an axis
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AxisRange {
+    #[default]
+    Full,
+    /// from the center to the maximum
+    Positive,
+    /// from the center to the minimum
+    Negative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AxisDef {
     pub typ: Axis,
     pub centered: bool,
-    // TODO: more definitions
+    /// whether the direction of the raw value is reversed
+    #[serde(default)]
+    pub inverted: bool,
+    /// the part of the raw value range in use
+    #[serde(default)]
+    pub input: AxisRange,
+    /// the part of the axis the raw value is mapped onto
+    #[serde(default)]
+    pub output: AxisRange,
+}
+
+impl AxisDef {
+    pub const fn new(typ: Axis, centered: bool) -> Self {
+        Self {
+            typ,
+            centered,
+            inverted: false,
+            input: AxisRange::Full,
+            output: AxisRange::Full,
+        }
+    }
+
+    /// whether the raw value is reported as is, `inverted`, `input` & `output`
+    /// need the range of the axis and are only applied by `normalize::Normalizer`
+    pub fn is_raw(&self) -> bool {
+        !self.inverted && self.input == AxisRange::Full && self.output == AxisRange::Full
+    }
 }
 
 impl From<usize> for AxisIdent {
@@ -90,6 +235,12 @@ impl From<usize> for AxisIdent {
             3 => Self::RX,
             4 => Self::RY,
             5 => Self::RZ,
+            6 => Self::Throttle,
+            7 => Self::Rudder,
+            8 => Self::Steering,
+            9 => Self::Accelerator,
+            10 => Self::Brake,
+            11 => Self::Clutch,
             _ => unreachable!("invalid axis ident {}", v),
         }
     }
@@ -97,18 +248,80 @@ impl From<usize> for AxisIdent {
 
 pub type AxisState = i32;
 
+pub type HatIdent = usize;
+
+/// maximum number of hats reported per device
+pub const HAT_LIMIT: usize = 4;
+
+pub type SliderIdent = usize;
+
+/// maximum number of sliders, dials & wheels reported per device
+pub const SLIDER_LIMIT: usize = 8;
+
+/// sliders, dials & wheels share the slider idents, in the order of the device
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SliderKind {
+    #[default]
+    Slider,
+    Dial,
+    Wheel,
+}
+
 pub type SliderState = i32;
 
 #[derive(Debug)]
 pub enum ObjectDiff {
-    DPad(DPadState),
+    DPad(HatIdent, DPadState),
     Button(Button, ButtonState),
     Axis(Axis, AxisState),
-    Slider(SliderState),
+    /// the kind of the slider is listed in `DeviceInfo::sliders`
+    Slider(SliderIdent, SliderState),
+}
+
+/// layout of a joystick, mapping device objects onto buttons & axis
+pub trait Profile {
+    fn dpad(&self) -> bool;
+
+    /// ident a hat of the device is reported as
+    fn hat(&self, ident: HatIdent) -> HatIdent {
+        ident
+    }
+
+    fn button(&self, ident: ButtonIdent) -> Option<Button>;
+
+    /// definitions of the axis, an axis may be mapped onto more than one definition
+    fn axis(&self, ident: AxisIdent) -> &[AxisDef];
 }
 
+/// compile-time layout of a joystick, use `impl_profile!` to make it a `Profile`
 pub trait Joystick<const BTN_NUM: usize> {
     const DPAD: bool;
     const BUTTONS: [Button; BTN_NUM];
     const AXIS: [Option<AxisDef>; AxisIdent::Limit as usize];
 }
+
+/// implement `Profile` for a type implementing `Joystick`
+#[macro_export]
+macro_rules! impl_profile {
+    ($t:ty, $n:literal) => {
+        impl $crate::Profile for $t {
+            fn dpad(&self) -> bool {
+                <$t as $crate::Joystick<$n>>::DPAD
+            }
+
+            fn button(&self, ident: $crate::ButtonIdent) -> Option<$crate::Button> {
+                <$t as $crate::Joystick<$n>>::BUTTONS.get(ident).cloned()
+            }
+
+            fn axis(&self, ident: $crate::AxisIdent) -> &[$crate::AxisDef] {
+                const AXIS: &[Option<$crate::AxisDef>; $crate::AxisIdent::Limit as usize] =
+                    &<$t as $crate::Joystick<$n>>::AXIS;
+
+                AXIS.get(ident as usize)
+                    .map(|a| a.as_slice())
+                    .unwrap_or_default()
+            }
+        }
+    };
+}
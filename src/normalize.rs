@@ -0,0 +1,473 @@
+//! opt-in conversion of raw axis values into `f32`
+//!
+//! Centered axis are normalized into `[-1, 1]` and the others into `[0, 1]`,
+//! from the ranges reported by the device, or from calibrations overriding them.
+
+use std::{
+    collections::BTreeMap,
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    driver::{Bits, DeviceGuid, DeviceInfo, StableId, StateDiff},
+    Axis, AxisDef, AxisIdent, AxisRange, AxisState, Button, ButtonState, DPadState, HatIdent,
+    Profile, SliderIdent, SliderState, SLIDER_LIMIT,
+};
+
+/// raw range of a single axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub min: i32,
+    pub max: i32,
+    /// the middle of the range if absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub center: Option<i32>,
+}
+
+impl Calibration {
+    pub fn from_range((min, max): (i32, i32)) -> Self {
+        Self {
+            min,
+            max,
+            center: None,
+        }
+    }
+
+    fn center(&self) -> f32 {
+        match self.center {
+            Some(c) => c as f32,
+            None => (self.min as f32 + self.max as f32) / 2.0,
+        }
+    }
+
+    /// raw value in `[0, 1]` over the whole range
+    fn unit(&self, raw: i32) -> f32 {
+        let span = self.max as f32 - self.min as f32;
+        if span <= 0.0 {
+            return 0.0;
+        }
+
+        ((raw as f32 - self.min as f32) / span).clamp(0.0, 1.0)
+    }
+
+    /// raw value in `[-1, 1]` around the center
+    fn signed(&self, raw: i32) -> f32 {
+        let (raw, center) = (raw as f32, self.center());
+        let span = if raw < center {
+            center - self.min as f32
+        } else {
+            self.max as f32 - center
+        };
+
+        if span <= 0.0 {
+            return 0.0;
+        }
+
+        ((raw - center) / span).clamp(-1.0, 1.0)
+    }
+
+    /// normalize a raw value as described by the axis definition
+    pub fn normalize(&self, raw: i32, def: &AxisDef) -> f32 {
+        if !def.centered && def.input == AxisRange::Full && def.output == AxisRange::Full {
+            let v = self.unit(raw);
+            return if def.inverted { 1.0 - v } else { v };
+        }
+
+        let mut signed = self.signed(raw);
+        if def.inverted {
+            signed = -signed;
+        }
+
+        // the part of the raw value in use, in [0, 1] unless the whole range is used
+        let half = match def.input {
+            AxisRange::Full => None,
+            AxisRange::Positive => Some(signed.max(0.0)),
+            AxisRange::Negative => Some((-signed).max(0.0)),
+        };
+
+        match (def.output, half) {
+            (AxisRange::Full, None) if def.centered => signed,
+            (AxisRange::Full, Some(v)) if def.centered => v * 2.0 - 1.0,
+            (AxisRange::Full, Some(v)) => v,
+            (_, None) => {
+                let v = (signed + 1.0) / 2.0;
+                match def.output {
+                    AxisRange::Negative => -v,
+                    _ => v,
+                }
+            }
+            (AxisRange::Positive, Some(v)) => v,
+            (AxisRange::Negative, Some(v)) => -v,
+        }
+    }
+}
+
+/// calibrations of a single device, overriding the reported ranges
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceCalibration {
+    #[serde(default)]
+    pub axis: BTreeMap<AxisIdent, Calibration>,
+    /// sliders, dials & wheels by their idents
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        with = "slider_table"
+    )]
+    pub sliders: BTreeMap<SliderIdent, Calibration>,
+}
+
+/// slider tables are keyed by the idents as strings, TOML keys can't be integers
+mod slider_table {
+    use std::collections::BTreeMap;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::Calibration;
+    use crate::SliderIdent;
+
+    pub fn serialize<S: Serializer>(
+        table: &BTreeMap<SliderIdent, Calibration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(table.iter().map(|(idx, cal)| (idx.to_string(), cal)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<SliderIdent, Calibration>, D::Error> {
+        BTreeMap::<String, Calibration>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(idx, cal)| match idx.parse() {
+                Ok(idx) => Ok((idx, cal)),
+                Err(_) => Err(D::Error::custom(format!("invalid slider ident {:?}", idx))),
+            })
+            .collect()
+    }
+}
+
+/// calibrations of devices keyed by their stable idents, to be persisted &
+/// reloaded; devices without a stable ident are keyed by their GUIDs, which
+/// identical devices share
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalibrationStore {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub devices: BTreeMap<StableId, DeviceCalibration>,
+    /// fallbacks for the devices without a calibration of their own
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub models: BTreeMap<DeviceGuid, DeviceCalibration>,
+}
+
+impl CalibrationStore {
+    /// load a store file, the format is chosen by the file extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content =
+            read_to_string(path).with_context(|| format!("read calibrations {:?}", path))?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&content).context("parse toml calibrations"),
+            Some("json") => serde_json::from_str(&content).context("parse json calibrations"),
+            _ => Err(anyhow!("unknown calibrations format of {:?}", path)),
+        }
+        .with_context(|| format!("load calibrations {:?}", path))
+    }
+
+    /// save into a store file, the format is chosen by the file extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::to_string_pretty(self).context("serialize toml calibrations"),
+            Some("json") => {
+                serde_json::to_string_pretty(self).context("serialize json calibrations")
+            }
+            _ => Err(anyhow!("unknown calibrations format of {:?}", path)),
+        }?;
+
+        write(path, content).with_context(|| format!("write calibrations {:?}", path))
+    }
+
+    /// calibration of the device, or the one of its model
+    pub fn get(&self, info: &DeviceInfo) -> Option<&DeviceCalibration> {
+        let own = match info.stable_id.is_zero() {
+            true => None,
+            false => self.devices.get(&info.stable_id),
+        };
+
+        own.or_else(|| self.models.get(&info.guid))
+    }
+
+    /// store the calibration of the device, or of its model without a stable ident
+    pub fn set(&mut self, info: &DeviceInfo, calibration: DeviceCalibration) {
+        match info.stable_id.is_zero() {
+            true => self.models.insert(info.guid, calibration),
+            false => self.devices.insert(info.stable_id, calibration),
+        };
+    }
+
+    /// normalizer of the device, with the stored calibration if any
+    pub fn normalizer(&self, info: &DeviceInfo) -> Normalizer {
+        let mut normalizer = Normalizer::new(info);
+        if let Some(cal) = self.get(info) {
+            normalizer.apply(cal);
+        }
+
+        normalizer
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalizedDiff {
+    DPad(HatIdent, DPadState),
+    Button(Button, ButtonState),
+    Axis(Axis, f32),
+    Slider(SliderIdent, f32),
+}
+
+/// normalizes the state diffs of a single device
+#[derive(Debug, Clone)]
+pub struct Normalizer {
+    axis: [Option<Calibration>; AxisIdent::Limit as usize],
+    sliders: [Option<Calibration>; SLIDER_LIMIT],
+}
+
+impl Normalizer {
+    /// normalize over the ranges reported by the device
+    pub fn new(info: &DeviceInfo) -> Self {
+        let mut sliders = [None; SLIDER_LIMIT];
+        for (slot, slider) in sliders.iter_mut().zip(info.sliders.iter()) {
+            slot.replace(Calibration::from_range(slider.range));
+        }
+
+        Self {
+            axis: info.axis.map(|r| r.map(Calibration::from_range)),
+            sliders,
+        }
+    }
+
+    /// override the ranges with the given calibration
+    pub fn apply(&mut self, calibration: &DeviceCalibration) {
+        for (ident, cal) in calibration.axis.iter() {
+            self.calibrate(*ident, *cal);
+        }
+
+        for (idx, cal) in calibration.sliders.iter() {
+            self.calibrate_slider(*idx, *cal);
+        }
+    }
+
+    pub fn calibrate(&mut self, ident: AxisIdent, calibration: Calibration) {
+        if let Some(slot) = self.axis.get_mut(ident as usize) {
+            slot.replace(calibration);
+        }
+    }
+
+    pub fn calibration(&self, ident: AxisIdent) -> Option<&Calibration> {
+        self.axis.get(ident as usize).and_then(Option::as_ref)
+    }
+
+    pub fn calibrate_slider(&mut self, idx: SliderIdent, calibration: Calibration) {
+        if let Some(slot) = self.sliders.get_mut(idx) {
+            slot.replace(calibration);
+        }
+    }
+
+    pub fn slider_calibration(&self, idx: SliderIdent) -> Option<&Calibration> {
+        self.sliders.get(idx).and_then(Option::as_ref)
+    }
+
+    /// normalize a raw value of the axis, `None` if the range of the axis is unknown
+    pub fn axis(&self, ident: AxisIdent, raw: AxisState, def: &AxisDef) -> Option<f32> {
+        self.calibration(ident).map(|c| c.normalize(raw, def))
+    }
+
+    /// normalize a raw value of the slider into `[0, 1]`
+    pub fn slider(&self, idx: SliderIdent, raw: SliderState) -> Option<f32> {
+        self.slider_calibration(idx).map(|c| c.unit(raw))
+    }
+
+    /// same as `StateDiff::diffs`, with normalized axis & slider values
+    pub fn diffs<B: Bits, P: Profile + ?Sized>(
+        &self,
+        diff: &StateDiff<B>,
+        profile: &P,
+    ) -> Vec<NormalizedDiff> {
+        let mut obj_diffs = Vec::new();
+
+        for (idx, st) in diff.dpad.iter().enumerate() {
+            if let Some(st) = st {
+                obj_diffs.push(NormalizedDiff::DPad(profile.hat(idx), *st));
+            }
+        }
+
+        for idx in (0..B::CAP).filter(|i| diff.buttons.0.bit(*i).unwrap_or(false)) {
+            if let Some(btn) = profile.button(idx) {
+                obj_diffs.push(NormalizedDiff::Button(
+                    btn,
+                    diff.buttons.1.bit(idx).unwrap_or(false).into(),
+                ));
+            }
+        }
+
+        for (idx, raw) in diff
+            .axis
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| x.map(|raw| (i, raw)))
+        {
+            let ident = AxisIdent::from(idx);
+            for def in profile.axis(ident) {
+                if let Some(v) = self.axis(ident, raw, def) {
+                    obj_diffs.push(NormalizedDiff::Axis(def.typ, v));
+                }
+            }
+        }
+
+        for (idx, raw) in diff
+            .sliders
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| x.map(|raw| (i, raw)))
+        {
+            if let Some(v) = self.slider(idx, raw) {
+                obj_diffs.push(NormalizedDiff::Slider(idx, v));
+            }
+        }
+
+        obj_diffs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_file};
+
+    use super::*;
+
+    fn info(stable_id: u64) -> DeviceInfo {
+        DeviceInfo {
+            guid: DeviceGuid::from_ids(3, 0x054c, 0x09cc, 0x0100),
+            stable_id: StableId(stable_id),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn normalize() {
+        use AxisRange::{Full, Negative, Positive};
+
+        let def = |centered, inverted, input, output| AxisDef {
+            centered,
+            inverted,
+            input,
+            output,
+            ..AxisDef::new(Axis::LThumbX, centered)
+        };
+        let range = Calibration::from_range((0, 100));
+        let centered = Calibration {
+            center: Some(20),
+            ..range
+        };
+        let degenerate = Calibration::from_range((50, 50));
+
+        #[rustfmt::skip]
+        let cases = [
+            // non-centered over the whole range
+            (range, def(false, false, Full, Full), [(0, 0.0), (25, 0.25), (100, 1.0), (150, 1.0)]),
+            (range, def(false, true, Full, Full), [(0, 1.0), (25, 0.75), (100, 0.0), (-50, 1.0)]),
+            // centered over the whole range
+            (range, def(true, false, Full, Full), [(0, -1.0), (50, 0.0), (75, 0.5), (100, 1.0)]),
+            (range, def(true, true, Full, Full), [(0, 1.0), (50, 0.0), (75, -0.5), (100, -1.0)]),
+            // custom center, both sides scaled apart
+            (centered, def(true, false, Full, Full), [(0, -1.0), (10, -0.5), (20, 0.0), (60, 0.5)]),
+            (centered, def(false, false, Full, Full), [(0, 0.0), (20, 0.2), (60, 0.6), (100, 1.0)]),
+            // half of the raw range onto the whole axis
+            (range, def(false, false, Positive, Full), [(0, 0.0), (50, 0.0), (75, 0.5), (100, 1.0)]),
+            (range, def(true, false, Positive, Full), [(0, -1.0), (50, -1.0), (75, 0.0), (100, 1.0)]),
+            (range, def(false, false, Negative, Full), [(0, 1.0), (25, 0.5), (50, 0.0), (100, 0.0)]),
+            (range, def(false, true, Negative, Full), [(0, 0.0), (50, 0.0), (75, 0.5), (100, 1.0)]),
+            // the whole raw range onto half of the axis
+            (range, def(false, false, Full, Positive), [(0, 0.0), (50, 0.5), (75, 0.75), (100, 1.0)]),
+            (range, def(false, false, Full, Negative), [(0, 0.0), (50, -0.5), (75, -0.75), (100, -1.0)]),
+            (range, def(false, true, Full, Negative), [(0, -1.0), (50, -0.5), (75, -0.25), (100, 0.0)]),
+            // half onto half
+            (range, def(false, false, Positive, Negative), [(0, 0.0), (50, 0.0), (75, -0.5), (100, -1.0)]),
+            (centered, def(false, false, Negative, Positive), [(0, 1.0), (10, 0.5), (20, 0.0), (60, 0.0)]),
+            // no span at all
+            (degenerate, def(false, false, Full, Full), [(0, 0.0), (50, 0.0), (100, 0.0), (-1, 0.0)]),
+            (degenerate, def(true, true, Full, Full), [(0, 0.0), (50, 0.0), (100, 0.0), (-1, 0.0)]),
+            (degenerate, def(false, false, Positive, Full), [(0, 0.0), (50, 0.0), (100, 0.0), (-1, 0.0)]),
+        ];
+
+        for (cal, def, values) in cases {
+            for (raw, expected) in values {
+                let v = cal.normalize(raw, &def);
+                assert!(
+                    (v - expected).abs() < 1e-6,
+                    "{:?} {:?} of {}: {} instead of {}",
+                    cal,
+                    def,
+                    raw,
+                    v,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn store_keys() {
+        let cal = |max| DeviceCalibration {
+            axis: [(AxisIdent::X, Calibration::from_range((0, max)))].into(),
+            ..Default::default()
+        };
+
+        let mut store = CalibrationStore::default();
+        assert!(store.get(&info(1)).is_none());
+
+        // identical devices share the calibration of their model
+        store.set(&info(0), cal(100));
+        assert_eq!(store.get(&info(1)), Some(&cal(100)));
+        assert_eq!(store.get(&info(2)), Some(&cal(100)));
+
+        // until they have one of their own
+        store.set(&info(1), cal(200));
+        assert_eq!(store.get(&info(1)), Some(&cal(200)));
+        assert_eq!(store.get(&info(2)), Some(&cal(100)));
+        assert_eq!(store.get(&info(0)), Some(&cal(100)));
+    }
+
+    #[test]
+    fn store_round_trip() {
+        let mut calibration = DeviceCalibration::default();
+        calibration.axis.insert(
+            AxisIdent::X,
+            Calibration {
+                center: Some(120),
+                ..Calibration::from_range((0, 255))
+            },
+        );
+        calibration
+            .sliders
+            .insert(2, Calibration::from_range((-100, 100)));
+
+        let mut store = CalibrationStore::default();
+        store.set(&info(7), calibration.clone());
+        store.set(&info(0), calibration);
+
+        for ext in ["toml", "json"] {
+            let path = temp_dir().join(format!(
+                "joystick-rs-calibrations-{}.{}",
+                std::process::id(),
+                ext
+            ));
+            store.save(&path).unwrap();
+            let loaded = CalibrationStore::load(&path);
+            _ = remove_file(&path);
+            assert_eq!(loaded.unwrap(), store);
+        }
+    }
+}
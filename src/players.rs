@@ -0,0 +1,411 @@
+//! numbered player slots assigned to the attached devices, for local multiplayer
+//!
+//! Devices join on attachment or on a press of the join button, and with sticky
+//! slots a device reconnecting gets the slot it left, matched by its stable ident.
+
+use std::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bindings::PlayerIdent,
+    driver::{Bits, DeviceInfo, Event, StableId},
+    normalize::NormalizedDiff,
+    Button, ButtonState, ObjectDiff,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinPolicy {
+    /// devices join as they are attached, while there are free slots
+    #[default]
+    FirstCome,
+    /// attached devices wait for a press of the join button
+    PressStart,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayersConfig {
+    pub slots: usize,
+    pub join: JoinPolicy,
+    pub join_button: Button,
+    /// keep the slot of a device detached for its reconnect, until the slot is left
+    pub sticky: bool,
+}
+
+impl Default for PlayersConfig {
+    fn default() -> Self {
+        Self {
+            slots: 4,
+            join: JoinPolicy::FirstCome,
+            join_button: Button::Start,
+            sticky: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayerEvent<DI> {
+    PlayerJoined(PlayerIdent, DI),
+    PlayerLeft(PlayerIdent, DI),
+}
+
+#[derive(Debug, Clone)]
+struct Waiting<DI> {
+    id: DI,
+    stable_id: StableId,
+    /// left its slot, only joins again on a press of the join button
+    left: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Slot<DI> {
+    Free,
+    Taken(DI, StableId),
+    /// left by a detached device, for its reconnect
+    Reserved(StableId),
+}
+
+/// assigns the attached devices to player slots, numbered from 0 like the
+/// players of `bindings::BindingConfig`
+#[derive(Debug, Clone)]
+pub struct Players<DI> {
+    config: PlayersConfig,
+    slots: Vec<Slot<DI>>,
+    /// attached devices without a slot, oldest first
+    waiting: Vec<Waiting<DI>>,
+}
+
+impl<DI: Clone + PartialEq> Players<DI> {
+    pub fn new(config: PlayersConfig) -> Self {
+        Self {
+            slots: (0..config.slots).map(|_| Slot::Free).collect(),
+            config,
+            waiting: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &PlayersConfig {
+        &self.config
+    }
+
+    /// player of the device, if it joined
+    pub fn player(&self, id: &DI) -> Option<PlayerIdent> {
+        self.slots
+            .iter()
+            .position(|slot| matches!(slot, Slot::Taken(k, _) if k == id))
+    }
+
+    /// device of the player, if the slot is taken
+    pub fn device(&self, player: PlayerIdent) -> Option<&DI> {
+        match self.slots.get(player) {
+            Some(Slot::Taken(id, _)) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// attached devices without a slot, oldest first
+    pub fn waiting(&self) -> impl Iterator<Item = &DI> {
+        self.waiting.iter().map(|w| &w.id)
+    }
+
+    /// feed the attachments & detachments of a driver, other events are ignored
+    pub fn event<B: Bits>(&mut self, evt: &Event<DI, B>) -> Vec<PlayerEvent<DI>>
+    where
+        DI: Debug,
+    {
+        match evt {
+            Event::Attached(id, info, ..) => self.attached(id.clone(), info),
+            Event::Deattached(id, _) => self.detached(id),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn attached(&mut self, id: DI, info: &DeviceInfo) -> Vec<PlayerEvent<DI>> {
+        // an attachment under the same ident replaces the previous device
+        let mut events = self.detached(&id);
+        let stable_id = info.stable_id;
+
+        let reserved = self.slots.iter().position(
+            |slot| matches!(slot, Slot::Reserved(k) if !stable_id.is_zero() && *k == stable_id),
+        );
+
+        match reserved {
+            Some(player) => {
+                self.slots[player] = Slot::Taken(id.clone(), stable_id);
+                events.push(PlayerEvent::PlayerJoined(player, id));
+            }
+
+            None => {
+                self.waiting.push(Waiting {
+                    id,
+                    stable_id,
+                    left: false,
+                });
+                if self.config.join == JoinPolicy::FirstCome {
+                    self.fill(&mut events);
+                }
+            }
+        }
+
+        events
+    }
+
+    pub fn detached(&mut self, id: &DI) -> Vec<PlayerEvent<DI>> {
+        self.waiting.retain(|w| w.id != *id);
+
+        let player = match self.player(id) {
+            Some(player) => player,
+            None => return Vec::new(),
+        };
+
+        let slot = match &self.slots[player] {
+            Slot::Taken(_, stable_id) if self.config.sticky && !stable_id.is_zero() => {
+                Slot::Reserved(*stable_id)
+            }
+            _ => Slot::Free,
+        };
+        self.slots[player] = slot;
+
+        let mut events = vec![PlayerEvent::PlayerLeft(player, id.clone())];
+        if self.config.join == JoinPolicy::FirstCome {
+            self.fill(&mut events);
+        }
+
+        events
+    }
+
+    /// free the slot of the player, reserved ones included; its device waits
+    /// for a press of the join button to join again, whatever the policy
+    pub fn leave(&mut self, player: PlayerIdent) -> Vec<PlayerEvent<DI>> {
+        let slot = match self.slots.get_mut(player) {
+            Some(slot) => std::mem::replace(slot, Slot::Free),
+            None => return Vec::new(),
+        };
+
+        let mut events = Vec::new();
+        if let Slot::Taken(id, stable_id) = slot {
+            events.push(PlayerEvent::PlayerLeft(player, id.clone()));
+            self.waiting.push(Waiting {
+                id,
+                stable_id,
+                left: true,
+            });
+        }
+
+        if self.config.join == JoinPolicy::FirstCome {
+            self.fill(&mut events);
+        }
+
+        events
+    }
+
+    /// feed a button change of the device, waiting devices join on a press of
+    /// the join button with `JoinPolicy::PressStart`, or after leaving
+    pub fn button(&mut self, id: &DI, button: Button, state: ButtonState) -> Vec<PlayerEvent<DI>> {
+        if button != self.config.join_button || state != ButtonState::Pressed {
+            return Vec::new();
+        }
+
+        let press_start = self.config.join == JoinPolicy::PressStart;
+        let idx = match self
+            .waiting
+            .iter()
+            .position(|w| w.id == *id && (press_start || w.left))
+        {
+            Some(idx) => idx,
+            None => return Vec::new(),
+        };
+
+        match self.free_slot() {
+            Some(player) => {
+                let waiting = self.waiting.remove(idx);
+                self.slots[player] = Slot::Taken(waiting.id.clone(), waiting.stable_id);
+                vec![PlayerEvent::PlayerJoined(player, waiting.id)]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// feed the button diffs of a driver event
+    pub fn diffs(&mut self, id: &DI, diffs: &[ObjectDiff]) -> Vec<PlayerEvent<DI>> {
+        let mut events = Vec::new();
+        for diff in diffs {
+            if let ObjectDiff::Button(button, state) = diff {
+                events.extend(self.button(id, *button, *state));
+            }
+        }
+
+        events
+    }
+
+    /// same as `diffs`, over normalized diffs
+    pub fn normalized_diffs(&mut self, id: &DI, diffs: &[NormalizedDiff]) -> Vec<PlayerEvent<DI>> {
+        let mut events = Vec::new();
+        for diff in diffs {
+            if let NormalizedDiff::Button(button, state) = diff {
+                events.extend(self.button(id, *button, *state));
+            }
+        }
+
+        events
+    }
+
+    fn free_slot(&self) -> Option<PlayerIdent> {
+        self.slots
+            .iter()
+            .position(|slot| matches!(slot, Slot::Free))
+    }
+
+    /// assign the free slots to the waiting devices that didn't leave, oldest first
+    fn fill(&mut self, events: &mut Vec<PlayerEvent<DI>>) {
+        while let Some(idx) = self.waiting.iter().position(|w| !w.left) {
+            let player = match self.free_slot() {
+                Some(player) => player,
+                None => return,
+            };
+
+            let waiting = self.waiting.remove(idx);
+            self.slots[player] = Slot::Taken(waiting.id.clone(), waiting.stable_id);
+            events.push(PlayerEvent::PlayerJoined(player, waiting.id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::{Attachment, Timestamp, B256};
+    use PlayerEvent::{PlayerJoined, PlayerLeft};
+
+    fn info(stable_id: u64) -> DeviceInfo {
+        DeviceInfo {
+            stable_id: StableId(stable_id),
+            ..Default::default()
+        }
+    }
+
+    fn new(slots: usize, join: JoinPolicy, sticky: bool) -> Players<u64> {
+        Players::new(PlayersConfig {
+            slots,
+            join,
+            sticky,
+            ..Default::default()
+        })
+    }
+
+    fn start(players: &mut Players<u64>, id: u64) -> Vec<PlayerEvent<u64>> {
+        players.button(&id, Button::Start, ButtonState::Pressed)
+    }
+
+    #[test]
+    fn first_come() {
+        let mut players = new(2, JoinPolicy::FirstCome, false);
+        let attached =
+            |id| Event::<u64, B256>::Attached(id, info(id), Attachment::New, Timestamp::now());
+
+        assert_eq!(players.event(&attached(1)), [PlayerJoined(0, 1)]);
+        assert_eq!(players.event(&attached(2)), [PlayerJoined(1, 2)]);
+        assert!(players.event(&attached(3)).is_empty());
+        assert!(players.event(&attached(4)).is_empty());
+        assert_eq!(players.waiting().collect::<Vec<_>>(), [&3, &4]);
+
+        // the oldest waiting device fills the slot
+        let detached = Event::<u64, B256>::Deattached(1, Timestamp::now());
+        assert_eq!(
+            players.event(&detached),
+            [PlayerLeft(0, 1), PlayerJoined(0, 3)]
+        );
+        assert_eq!(players.player(&3), Some(0));
+        assert_eq!(players.device(1), Some(&2));
+        assert_eq!(players.waiting().collect::<Vec<_>>(), [&4]);
+
+        // waiting devices leave without events
+        assert!(players.detached(&4).is_empty());
+        assert_eq!(players.waiting().count(), 0);
+    }
+
+    #[test]
+    fn press_start() {
+        let mut players = new(2, JoinPolicy::PressStart, false);
+        for id in 1..=3 {
+            assert!(players.attached(id, &info(id)).is_empty());
+        }
+
+        assert_eq!(start(&mut players, 2), [PlayerJoined(0, 2)]);
+        assert!(players
+            .button(&1, Button::South, ButtonState::Pressed)
+            .is_empty());
+        assert!(players
+            .diffs(
+                &1,
+                &[ObjectDiff::Button(Button::Start, ButtonState::Released)]
+            )
+            .is_empty());
+        assert_eq!(
+            players.diffs(
+                &1,
+                &[ObjectDiff::Button(Button::Start, ButtonState::Pressed)]
+            ),
+            [PlayerJoined(1, 1)]
+        );
+
+        // no slot left, nor filled on a detachment
+        assert!(start(&mut players, 3).is_empty());
+        assert_eq!(players.detached(&2), [PlayerLeft(0, 2)]);
+        assert_eq!(players.device(0), None);
+        assert_eq!(start(&mut players, 3), [PlayerJoined(0, 3)]);
+
+        // already joined
+        assert!(start(&mut players, 3).is_empty());
+    }
+
+    #[test]
+    fn sticky() {
+        let mut players = new(2, JoinPolicy::FirstCome, true);
+        assert_eq!(players.attached(1, &info(10)), [PlayerJoined(0, 1)]);
+        assert_eq!(players.attached(2, &info(20)), [PlayerJoined(1, 2)]);
+
+        // the slot is kept for the reconnect
+        assert_eq!(players.detached(&1), [PlayerLeft(0, 1)]);
+        assert!(players.attached(3, &info(30)).is_empty());
+        assert_eq!(players.attached(4, &info(10)), [PlayerJoined(0, 4)]);
+        assert_eq!(players.waiting().collect::<Vec<_>>(), [&3]);
+
+        // reserved slots are freed by leaving them
+        assert_eq!(players.detached(&2), [PlayerLeft(1, 2)]);
+        assert_eq!(players.waiting().collect::<Vec<_>>(), [&3]);
+        assert_eq!(players.leave(1), [PlayerJoined(1, 3)]);
+        assert!(players.attached(5, &info(20)).is_empty());
+
+        // devices without a stable ident keep no slot
+        let mut players = new(1, JoinPolicy::FirstCome, true);
+        assert_eq!(players.attached(1, &info(0)), [PlayerJoined(0, 1)]);
+        assert_eq!(players.detached(&1), [PlayerLeft(0, 1)]);
+        assert_eq!(players.attached(2, &info(0)), [PlayerJoined(0, 2)]);
+    }
+
+    #[test]
+    fn leave_and_rejoin() {
+        let mut players = new(1, JoinPolicy::FirstCome, false);
+        assert_eq!(players.attached(1, &info(1)), [PlayerJoined(0, 1)]);
+        assert!(players.attached(2, &info(2)).is_empty());
+
+        assert_eq!(players.leave(0), [PlayerLeft(0, 1), PlayerJoined(0, 2)]);
+        assert_eq!(players.waiting().collect::<Vec<_>>(), [&1]);
+        assert!(players.leave(3).is_empty());
+
+        // the device that left waits for the join button
+        assert_eq!(players.detached(&2), [PlayerLeft(0, 2)]);
+        assert_eq!(players.device(0), None);
+        assert!(players
+            .button(&1, Button::South, ButtonState::Pressed)
+            .is_empty());
+        assert_eq!(start(&mut players, 1), [PlayerJoined(0, 1)]);
+
+        // other devices join as they're attached again
+        assert_eq!(players.leave(0), [PlayerLeft(0, 1)]);
+        assert_eq!(players.attached(2, &info(2)), [PlayerJoined(0, 2)]);
+    }
+}
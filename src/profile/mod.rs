@@ -0,0 +1,422 @@
+use std::{collections::BTreeMap, fs::read_to_string, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    driver::DeviceInfo, impl_profile, intern, Axis, AxisDef, AxisIdent, Button, ButtonIdent,
+    HatIdent, Joystick, Profile,
+};
+
+mod registry;
+pub mod sdl;
+
+pub use registry::*;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PS4Compact;
+
+impl Joystick<14> for PS4Compact {
+    const DPAD: bool = true;
+
+    const BUTTONS: [Button; 14] = [
+        Button::West,
+        Button::South,
+        Button::East,
+        Button::North,
+        Button::LShoulder,
+        Button::RShoulder,
+        Button::LTrigger,
+        Button::RTrigger,
+        Button::Select,
+        Button::Start,
+        Button::LThumb,
+        Button::RThumb,
+        Button::Mode,
+        Button::Other("TrackPad"),
+    ];
+
+    const AXIS: [Option<AxisDef>; AxisIdent::Limit as usize] = [
+        Some(AxisDef::new(Axis::LThumbX, true)),
+        Some(AxisDef::new(Axis::LThumbY, true)),
+        Some(AxisDef::new(Axis::RThumbX, true)),
+        Some(AxisDef::new(Axis::LTrigger, false)),
+        Some(AxisDef::new(Axis::RTrigger, false)),
+        Some(AxisDef::new(Axis::RThumbY, true)),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    ];
+}
+
+impl_profile!(PS4Compact, 14);
+
+/// layout defined at runtime, e.g. loaded from a TOML file like
+///
+/// ```toml
+/// dpad = true
+/// buttons = ["West", "South", "East", "North", "TrackPad"]
+///
+/// [axis]
+/// x = { typ = "LThumbX", centered = true }
+/// y = { typ = "LThumbY", centered = true }
+/// rx = { typ = "LTrigger", centered = false }
+/// z = [
+///     { typ = "LTrigger", centered = false, input = "positive" },
+///     { typ = "RTrigger", centered = false, input = "negative" },
+/// ]
+/// ```
+///
+/// buttons & axis with unknown names become `Other` variants. Axis definitions
+/// with `inverted`, `input` or `output` only apply to normalized diffs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DynamicProfile {
+    #[serde(default)]
+    pub dpad: bool,
+    /// hat of the device the dpad is on, reported as hat 0 in its place
+    #[serde(default)]
+    pub dpad_hat: HatIdent,
+    /// buttons in button ident order
+    #[serde(default)]
+    pub buttons: Vec<Button>,
+    #[serde(default, with = "axis_table")]
+    pub axis: BTreeMap<AxisIdent, Vec<AxisDef>>,
+}
+
+impl DynamicProfile {
+    /// copy the layout of a compile-time profile
+    pub fn from_joystick<J: Joystick<N>, const N: usize>() -> Self {
+        Self {
+            dpad: J::DPAD,
+            dpad_hat: 0,
+            buttons: J::BUTTONS.to_vec(),
+            axis: J::AXIS
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, def)| def.map(|d| (AxisIdent::from(idx), vec![d])))
+                .collect(),
+        }
+    }
+
+    /// layout of a device without a known profile: the sticks are taken from the
+    /// X/Y & RX/RY axis, and everything else is reported by its index
+    pub fn generic(info: &DeviceInfo) -> Self {
+        let axis = info
+            .axis
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.is_some())
+            .map(|(idx, _)| {
+                let ident = AxisIdent::from(idx);
+                let (typ, centered) = match ident {
+                    AxisIdent::X => (Axis::LThumbX, true),
+                    AxisIdent::Y => (Axis::LThumbY, true),
+                    AxisIdent::RX => (Axis::RThumbX, true),
+                    AxisIdent::RY => (Axis::RThumbY, true),
+                    AxisIdent::Throttle => (Axis::Throttle, false),
+                    AxisIdent::Rudder => (Axis::Rudder, true),
+                    AxisIdent::Steering => (Axis::Steering, true),
+                    AxisIdent::Accelerator => (Axis::Accelerator, false),
+                    AxisIdent::Brake => (Axis::Brake, false),
+                    AxisIdent::Clutch => (Axis::Clutch, false),
+                    _ => (Axis::Other(intern(&format!("a{}", idx))), true),
+                };
+
+                (ident, vec![AxisDef::new(typ, centered)])
+            })
+            .collect();
+
+        Self {
+            dpad: info.hats > 0,
+            dpad_hat: 0,
+            buttons: (0..info.buttons_num)
+                .map(|idx| Button::Other(intern(&format!("b{}", idx))))
+                .collect(),
+            axis,
+        }
+    }
+
+    pub fn from_toml_str(s: &str) -> Result<Self> {
+        toml::from_str(s).context("parse toml profile")
+    }
+
+    pub fn from_json_str(s: &str) -> Result<Self> {
+        serde_json::from_str(s).context("parse json profile")
+    }
+
+    /// load a profile file, the format is chosen by the file extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = read_to_string(path).with_context(|| format!("read profile {:?}", path))?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml_str(&content),
+            Some("json") => Self::from_json_str(&content),
+            _ => Err(anyhow!("unknown profile format of {:?}", path)),
+        }
+        .with_context(|| format!("load profile {:?}", path))
+    }
+}
+
+impl Profile for DynamicProfile {
+    fn dpad(&self) -> bool {
+        self.dpad
+    }
+
+    fn hat(&self, ident: HatIdent) -> HatIdent {
+        match ident {
+            _ if !self.dpad => ident,
+            0 => self.dpad_hat,
+            _ if ident == self.dpad_hat => 0,
+            _ => ident,
+        }
+    }
+
+    fn button(&self, ident: ButtonIdent) -> Option<Button> {
+        self.buttons.get(ident).cloned()
+    }
+
+    fn axis(&self, ident: AxisIdent) -> &[AxisDef] {
+        self.axis.get(&ident).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// axis tables accept either a single definition or a list for each axis
+mod axis_table {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::{AxisDef, AxisIdent};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Defs {
+        One(AxisDef),
+        Many(Vec<AxisDef>),
+    }
+
+    pub fn serialize<S: Serializer>(
+        table: &BTreeMap<AxisIdent, Vec<AxisDef>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(table.iter().map(|(ident, defs)| {
+            let defs = match defs.as_slice() {
+                [one] => Defs::One(*one),
+                many => Defs::Many(many.to_vec()),
+            };
+
+            (ident, defs)
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<AxisIdent, Vec<AxisDef>>, D::Error> {
+        let table = BTreeMap::<AxisIdent, Defs>::deserialize(deserializer)?;
+        Ok(table
+            .into_iter()
+            .map(|(ident, defs)| {
+                let defs = match defs {
+                    Defs::One(one) => vec![one],
+                    Defs::Many(many) => many,
+                };
+
+                (ident, defs)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{remove_file, write},
+    };
+
+    use super::*;
+    use crate::{
+        driver::{Bits, StateDiff, B256},
+        AxisRange, ButtonState, DPadState, ObjectDiff,
+    };
+
+    const EXAMPLE: &str = r#"
+        dpad = true
+        buttons = ["West", "South", "East", "North", "TrackPad"]
+
+        [axis]
+        x = { typ = "LThumbX", centered = true }
+        y = { typ = "LThumbY", centered = true }
+        rx = { typ = "LTrigger", centered = false }
+        z = [
+            { typ = "LTrigger", centered = false, input = "positive" },
+            { typ = "RTrigger", centered = false, input = "negative" },
+        ]
+    "#;
+
+    #[test]
+    fn documented_example() {
+        let profile = DynamicProfile::from_toml_str(EXAMPLE).unwrap();
+        assert_eq!(profile.button(4), Some(Button::Other("TrackPad")));
+        assert_eq!(profile.button(5), None);
+        assert_eq!(
+            profile.axis(AxisIdent::X),
+            [AxisDef::new(Axis::LThumbX, true)]
+        );
+        assert_eq!(
+            profile.axis(AxisIdent::Z),
+            [
+                AxisDef {
+                    input: AxisRange::Positive,
+                    ..AxisDef::new(Axis::LTrigger, false)
+                },
+                AxisDef {
+                    input: AxisRange::Negative,
+                    ..AxisDef::new(Axis::RTrigger, false)
+                },
+            ]
+        );
+        assert!(profile.axis(AxisIdent::RZ).is_empty());
+
+        let mut diff = StateDiff::<B256>::default();
+        diff.dpad[0] = Some(DPadState::Up);
+        diff.buttons.0.set(4);
+        diff.buttons.1.set(4);
+        diff.buttons.0.set(7);
+        diff.axis[AxisIdent::X as usize] = Some(100);
+        diff.axis[AxisIdent::RX as usize] = Some(10);
+        diff.axis[AxisIdent::Z as usize] = Some(50);
+
+        // the halves of z are only normalized, buttons without a name dropped
+        let diffs = diff.diffs(&profile);
+        assert_eq!(diffs.len(), 4, "{:?}", diffs);
+        assert!(matches!(diffs[0], ObjectDiff::DPad(0, DPadState::Up)));
+        assert!(matches!(
+            diffs[1],
+            ObjectDiff::Button(Button::Other("TrackPad"), ButtonState::Pressed)
+        ));
+        assert!(matches!(diffs[2], ObjectDiff::Axis(Axis::LThumbX, 100)));
+        assert!(matches!(diffs[3], ObjectDiff::Axis(Axis::LTrigger, 10)));
+    }
+
+    #[test]
+    fn load_formats() {
+        let toml = r#"
+            buttons = ["South", "Jump"]
+
+            [axis]
+            x = { typ = "LThumbX", centered = true }
+            y = [{ typ = "LThumbY", centered = true, inverted = true }]
+        "#;
+        let json = r#"{
+            "buttons": ["South", "Jump"],
+            "axis": {
+                "x": [{ "typ": "LThumbX", "centered": true }],
+                "y": { "typ": "LThumbY", "centered": true, "inverted": true }
+            }
+        }"#;
+
+        let expected = DynamicProfile {
+            buttons: vec![Button::South, Button::Other("Jump")],
+            axis: [
+                (AxisIdent::X, vec![AxisDef::new(Axis::LThumbX, true)]),
+                (
+                    AxisIdent::Y,
+                    vec![AxisDef {
+                        inverted: true,
+                        ..AxisDef::new(Axis::LThumbY, true)
+                    }],
+                ),
+            ]
+            .into(),
+            ..Default::default()
+        };
+
+        for (ext, content) in [("toml", toml), ("json", json)] {
+            let path = temp_dir().join(format!(
+                "joystick-rs-profile-{}.{}",
+                std::process::id(),
+                ext
+            ));
+            write(&path, content).unwrap();
+            let loaded = DynamicProfile::load(&path);
+            _ = remove_file(&path);
+            assert_eq!(loaded.unwrap(), expected);
+        }
+
+        // single definitions are written back as such
+        let profile = DynamicProfile::from_toml_str(EXAMPLE).unwrap();
+        let saved = toml::to_string(&profile).unwrap();
+        assert!(saved.contains("[axis.x]"), "{}", saved);
+        assert_eq!(DynamicProfile::from_toml_str(&saved).unwrap(), profile);
+
+        assert!(DynamicProfile::load("profile.yaml").is_err());
+        assert!(DynamicProfile::from_toml_str("[axis]\nx = 1").is_err());
+    }
+
+    #[test]
+    fn hat_remapping() {
+        let mut profile = DynamicProfile {
+            dpad: true,
+            dpad_hat: 2,
+            ..Default::default()
+        };
+
+        // swapped with hat 0
+        assert_eq!(profile.hat(0), 2);
+        assert_eq!(profile.hat(2), 0);
+        assert_eq!(profile.hat(1), 1);
+
+        profile.dpad = false;
+        assert_eq!(profile.hat(0), 0);
+        assert_eq!(profile.hat(2), 2);
+    }
+
+    #[test]
+    fn generic() {
+        let mut info = DeviceInfo {
+            buttons_num: 3,
+            hats: 1,
+            ..Default::default()
+        };
+        for ident in [
+            AxisIdent::X,
+            AxisIdent::Z,
+            AxisIdent::RX,
+            AxisIdent::Throttle,
+        ] {
+            info.axis[ident as usize] = Some((0, 255));
+        }
+
+        let profile = DynamicProfile::generic(&info);
+        assert!(profile.dpad());
+        assert_eq!(
+            profile.buttons,
+            [
+                Button::Other("b0"),
+                Button::Other("b1"),
+                Button::Other("b2")
+            ]
+        );
+        assert_eq!(
+            profile.axis,
+            [
+                (AxisIdent::X, vec![AxisDef::new(Axis::LThumbX, true)]),
+                (AxisIdent::Z, vec![AxisDef::new(Axis::Other("a2"), true)]),
+                (AxisIdent::RX, vec![AxisDef::new(Axis::RThumbX, true)]),
+                (
+                    AxisIdent::Throttle,
+                    vec![AxisDef::new(Axis::Throttle, false)]
+                ),
+            ]
+            .into()
+        );
+
+        let profile = DynamicProfile::generic(&DeviceInfo::default());
+        assert!(!profile.dpad() && profile.buttons.is_empty() && profile.axis.is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tracing::{debug, warn_span};
+
+use super::{sdl::SdlMapping, DynamicProfile};
+use crate::{
+    driver::{DeviceGuid, DeviceInfo},
+    Profile,
+};
+
+pub type SharedProfile = Arc<dyn Profile + Send + Sync>;
+
+#[derive(Clone)]
+enum Entry {
+    Profile(SharedProfile),
+    /// resolved against the objects of each device
+    Sdl(Arc<SdlMapping>),
+}
+
+impl Entry {
+    fn resolve(&self, info: &DeviceInfo) -> SharedProfile {
+        match self {
+            Self::Profile(p) => p.clone(),
+            Self::Sdl(m) => Arc::new(m.profile(info)),
+        }
+    }
+}
+
+/// picks a profile for each attached device, from the most specific match
+///
+/// Lookups go by GUID, then by vendor, product & version, then by vendor &
+/// product, and fall back to `DynamicProfile::generic` at last.
+#[derive(Clone, Default)]
+pub struct ProfileRegistry {
+    by_guid: HashMap<DeviceGuid, Entry>,
+    by_ids: HashMap<(u16, u16, Option<u16>), Entry>,
+}
+
+impl ProfileRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_guid(&mut self, guid: DeviceGuid, profile: SharedProfile) {
+        self.by_guid.insert(guid, Entry::Profile(profile));
+    }
+
+    /// register a profile for a vendor & product, for all versions if `version` is `None`
+    pub fn register_ids(
+        &mut self,
+        vendor: u16,
+        product: u16,
+        version: Option<u16>,
+        profile: SharedProfile,
+    ) {
+        self.by_ids
+            .insert((vendor, product, version), Entry::Profile(profile));
+    }
+
+    /// add SDL mappings of the running platform, later mappings replace earlier ones
+    pub fn add_sdl_mappings<I: IntoIterator<Item = SdlMapping>>(&mut self, mappings: I) {
+        for mapping in mappings.into_iter().filter(|m| m.is_current_platform()) {
+            let guid = mapping.guid;
+            let entry = Entry::Sdl(Arc::new(mapping));
+
+            // most of the entries leave the version empty
+            if let Some((vendor, product, version)) = guid.ids() {
+                let version = Some(version).filter(|v| *v != 0);
+                self.by_ids
+                    .insert((vendor, product, version), entry.clone());
+            }
+
+            self.by_guid.insert(guid, entry);
+        }
+    }
+
+    /// the best-matching profile for the device
+    pub fn select(&self, info: &DeviceInfo) -> SharedProfile {
+        let _span = warn_span!("select profile", guid = %info.guid).entered();
+
+        let (vendor, product, version) = (info.vendor_id, info.product_id, info.version);
+        let entry = self.by_guid.get(&info.guid).or_else(|| {
+            self.by_ids
+                .get(&(vendor, product, Some(version)))
+                .or_else(|| self.by_ids.get(&(vendor, product, None)))
+        });
+
+        match entry {
+            Some(entry) => entry.resolve(info),
+            None => {
+                debug!(
+                    name = info.name.as_str(),
+                    "fall back to the generic profile"
+                );
+                Arc::new(DynamicProfile::generic(info))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Button;
+
+    fn profile(name: &'static str) -> SharedProfile {
+        Arc::new(DynamicProfile {
+            buttons: vec![Button::Other(name)],
+            ..Default::default()
+        })
+    }
+
+    fn info(bus: u16, product: u16, version: u16) -> DeviceInfo {
+        DeviceInfo {
+            buttons_num: 2,
+            vendor_id: 0x054c,
+            product_id: product,
+            version,
+            guid: DeviceGuid::from_ids(bus, 0x054c, product, version),
+            ..Default::default()
+        }
+    }
+
+    fn sdl(product: u16, mapping: &str) -> SdlMapping {
+        let guid = DeviceGuid::from_ids(3, 0x054c, product, 0);
+        SdlMapping::parse_line(&format!("{},Pad,{},", guid, mapping))
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn select_precedence() {
+        let mut registry = ProfileRegistry::new();
+        let select = |registry: &ProfileRegistry, info| registry.select(&info).button(0);
+
+        assert_eq!(select(&registry, info(3, 1, 1)), Some(Button::Other("b0")));
+
+        registry.register_ids(0x054c, 1, None, profile("ids"));
+        assert_eq!(select(&registry, info(3, 1, 1)), Some(Button::Other("ids")));
+        assert_eq!(select(&registry, info(3, 2, 1)), Some(Button::Other("b0")));
+
+        registry.register_ids(0x054c, 1, Some(1), profile("version"));
+        assert_eq!(
+            select(&registry, info(3, 1, 1)),
+            Some(Button::Other("version"))
+        );
+        assert_eq!(select(&registry, info(3, 1, 2)), Some(Button::Other("ids")));
+
+        registry.register_guid(info(3, 1, 1).guid, profile("guid"));
+        assert_eq!(
+            select(&registry, info(3, 1, 1)),
+            Some(Button::Other("guid"))
+        );
+        // same ids over another bus
+        assert_eq!(
+            select(&registry, info(5, 1, 1)),
+            Some(Button::Other("version"))
+        );
+        assert_eq!(select(&registry, info(5, 1, 2)), Some(Button::Other("ids")));
+    }
+
+    #[test]
+    fn sdl_platforms() {
+        let mappings: Vec<_> = [
+            "platform:Windows",
+            "platform:Mac OS X",
+            "platform:Linux",
+            "",
+        ]
+        .into_iter()
+        .zip(1..)
+        .map(|(platform, product)| sdl(product, &format!("a:b1,{}", platform)))
+        .collect();
+        assert!(mappings[3].is_current_platform());
+        assert!(mappings.iter().filter(|m| m.is_current_platform()).count() <= 2);
+
+        let mut registry = ProfileRegistry::new();
+        registry.add_sdl_mappings(mappings.clone());
+
+        for (mapping, product) in mappings.iter().zip(1..) {
+            // by GUID, or by ids for the other versions
+            for version in [0, 0x0110] {
+                let expected = match mapping.is_current_platform() {
+                    true => Button::South,
+                    false => Button::Other("b1"),
+                };
+
+                let profile = registry.select(&info(3, product, version));
+                assert_eq!(profile.button(1), Some(expected), "{:?}", mapping.platform);
+            }
+        }
+
+        // later mappings replace earlier ones
+        registry.add_sdl_mappings([sdl(4, "a:b0")]);
+        assert_eq!(
+            registry.select(&info(3, 4, 0)).button(0),
+            Some(Button::South)
+        );
+        assert_eq!(
+            registry.select(&info(3, 4, 0)).button(1),
+            Some(Button::Other("b1"))
+        );
+    }
+}
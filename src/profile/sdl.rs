@@ -0,0 +1,508 @@
+//! import of SDL GameControllerDB mappings, as found in `gamecontrollerdb.txt`
+//!
+//! A mapping line looks like `GUID,name,a:b0,b:b1,leftx:a0,dpup:h0.1,...,platform:Windows,`.
+//! SDL numbers the axis of a device in order of presence, so a mapping has to
+//! be resolved against the `DeviceInfo` of the device to become a profile.
+//!
+//! Half axis & inverted bindings, e.g. `+lefty:-a1` or `leftx:a0~`, need the range
+//! of the axis: the profiles are meant for `normalize::Normalizer::diffs`, the raw
+//! `StateDiff::diffs` skips those bindings.
+
+use std::{fs::read_to_string, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use tracing::{trace, warn, warn_span};
+
+use super::DynamicProfile;
+use crate::{
+    driver::{DeviceGuid, DeviceInfo},
+    intern, Axis, AxisDef, AxisIdent, AxisRange, Button,
+};
+
+/// an input element of the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SdlSource {
+    Button(usize),
+    /// hat index & direction mask, 1 for up, 2 for right, 4 for down and 8 for left
+    Hat(usize, u8),
+    Axis {
+        idx: usize,
+        range: AxisRange,
+        inverted: bool,
+    },
+}
+
+/// a gamepad element in SDL's naming
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SdlTarget {
+    Button(Button),
+    DPad(DPadDirection),
+    Axis(Axis, AxisRange),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DPadDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdlBinding {
+    pub target: SdlTarget,
+    pub source: SdlSource,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdlMapping {
+    pub guid: DeviceGuid,
+    pub name: String,
+    pub platform: Option<String>,
+    pub bindings: Vec<SdlBinding>,
+}
+
+impl SdlMapping {
+    /// parse a single line, `None` for empty lines & comments
+    pub fn parse_line(line: &str) -> Result<Option<Self>> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let mut fields = line.split(',');
+        let guid = fields.next().unwrap_or_default().trim().parse()?;
+
+        let name = fields
+            .next()
+            .ok_or_else(|| anyhow!("name absent"))?
+            .trim()
+            .to_owned();
+
+        let mut mapping = SdlMapping {
+            guid,
+            name,
+            platform: None,
+            bindings: Vec::new(),
+        };
+
+        for field in fields.map(str::trim).filter(|f| !f.is_empty()) {
+            let (key, value) = field
+                .split_once(':')
+                .ok_or_else(|| anyhow!("invalid field {:?}", field))?;
+
+            match key {
+                "platform" => {
+                    mapping.platform.replace(value.to_owned());
+                }
+
+                // e.g. crc, hint & sdk version requirements
+                "crc" | "hint" | "sdk>=" | "sdk<=" => {}
+
+                _ => match (parse_target(key), parse_source(value)) {
+                    (Some(target), Some(source)) => {
+                        mapping.bindings.push(SdlBinding { target, source });
+                    }
+
+                    _ => {
+                        trace!(key, value, "unknown field");
+                    }
+                },
+            }
+        }
+
+        Ok(Some(mapping))
+    }
+
+    /// parse the content of a database file, invalid lines are skipped
+    pub fn parse_db(content: &str) -> Vec<Self> {
+        content
+            .lines()
+            .enumerate()
+            .filter_map(|(no, line)| match Self::parse_line(line) {
+                Ok(m) => m,
+                Err(e) => {
+                    warn!(line = no + 1, "invalid mapping: {:?}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn load_db<P: AsRef<Path>>(path: P) -> Result<Vec<Self>> {
+        let content = read_to_string(path.as_ref())
+            .with_context(|| format!("read mapping db {:?}", path.as_ref()))?;
+
+        Ok(Self::parse_db(&content))
+    }
+
+    /// whether the mapping is meant for the running platform, mappings without one always are
+    pub fn is_current_platform(&self) -> bool {
+        let current = if cfg!(windows) {
+            "Windows"
+        } else if cfg!(target_os = "macos") {
+            "Mac OS X"
+        } else if cfg!(target_os = "linux") {
+            "Linux"
+        } else {
+            return self.platform.is_none();
+        };
+
+        self.platform.as_deref().is_none_or(|p| p == current)
+    }
+
+    /// resolve the mapping against the objects of the device
+    pub fn profile(&self, info: &DeviceInfo) -> DynamicProfile {
+        let _span = warn_span!("sdl mapping", guid = %self.guid).entered();
+
+        // SDL counts the axis present on the device
+        let axis_slots: Vec<AxisIdent> = info
+            .axis
+            .iter()
+            .enumerate()
+            .filter(|(_, a)| a.is_some())
+            .map(|(idx, _)| AxisIdent::from(idx))
+            .collect();
+
+        let mut profile = DynamicProfile::default();
+        let mut buttons = vec![None; info.buttons_num];
+
+        for binding in self.bindings.iter() {
+            match (binding.target, binding.source) {
+                (SdlTarget::Button(btn), SdlSource::Button(idx)) => {
+                    set_button(&mut buttons, idx, btn);
+                }
+
+                (SdlTarget::DPad(dir), SdlSource::Button(idx)) => {
+                    set_button(&mut buttons, idx, dpad_button(dir));
+                }
+
+                (SdlTarget::DPad(dir), SdlSource::Hat(hat, mask)) => {
+                    if mask != hat_mask(dir) {
+                        warn!(?dir, hat, mask, "remapped hat directions unsupported");
+                    } else if hat >= info.hats {
+                        warn!(hat, "hat not present on the device");
+                    } else if profile.dpad && profile.dpad_hat != hat {
+                        warn!(?dir, hat, "dpad split over hats unsupported");
+                    } else {
+                        profile.dpad = true;
+                        profile.dpad_hat = hat;
+                    }
+                }
+
+                (
+                    SdlTarget::Axis(typ @ (Axis::LTrigger | Axis::RTrigger), AxisRange::Full),
+                    SdlSource::Button(idx),
+                ) => {
+                    let btn = match typ {
+                        Axis::LTrigger => Button::LTrigger,
+                        _ => Button::RTrigger,
+                    };
+                    set_button(&mut buttons, idx, btn);
+                }
+
+                (
+                    SdlTarget::Axis(typ, output),
+                    SdlSource::Axis {
+                        idx,
+                        range,
+                        inverted,
+                    },
+                ) => match axis_slots.get(idx) {
+                    Some(ident) => {
+                        profile.axis.entry(*ident).or_default().push(AxisDef {
+                            typ,
+                            centered: !matches!(typ, Axis::LTrigger | Axis::RTrigger),
+                            inverted,
+                            input: range,
+                            output,
+                        });
+                    }
+
+                    None => {
+                        warn!(idx, "axis not present on the device");
+                    }
+                },
+
+                (target, source) => {
+                    warn!(?target, ?source, "unsupported binding");
+                }
+            }
+        }
+
+        // unmapped buttons are still reported, by their SDL names
+        profile.buttons = buttons
+            .into_iter()
+            .enumerate()
+            .map(|(idx, btn)| btn.unwrap_or_else(|| Button::Other(intern(&format!("b{}", idx)))))
+            .collect();
+
+        profile
+    }
+}
+
+fn set_button(buttons: &mut [Option<Button>], idx: usize, btn: Button) {
+    match buttons.get_mut(idx) {
+        Some(slot) => {
+            slot.replace(btn);
+        }
+        None => {
+            warn!(idx, ?btn, "button not present on the device");
+        }
+    }
+}
+
+#[inline]
+fn dpad_button(dir: DPadDirection) -> Button {
+    Button::Other(match dir {
+        DPadDirection::Up => "DPadUp",
+        DPadDirection::Down => "DPadDown",
+        DPadDirection::Left => "DPadLeft",
+        DPadDirection::Right => "DPadRight",
+    })
+}
+
+/// direction mask of SDL hat bindings
+#[inline]
+fn hat_mask(dir: DPadDirection) -> u8 {
+    match dir {
+        DPadDirection::Up => 1,
+        DPadDirection::Right => 2,
+        DPadDirection::Down => 4,
+        DPadDirection::Left => 8,
+    }
+}
+
+fn parse_target(key: &str) -> Option<SdlTarget> {
+    let (output, name) = match key.as_bytes().first() {
+        Some(b'+') => (AxisRange::Positive, &key[1..]),
+        Some(b'-') => (AxisRange::Negative, &key[1..]),
+        _ => (AxisRange::Full, key),
+    };
+
+    let axis = match name {
+        "leftx" => Some(Axis::LThumbX),
+        "lefty" => Some(Axis::LThumbY),
+        "rightx" => Some(Axis::RThumbX),
+        "righty" => Some(Axis::RThumbY),
+        "lefttrigger" => Some(Axis::LTrigger),
+        "righttrigger" => Some(Axis::RTrigger),
+        _ => None,
+    };
+
+    if let Some(axis) = axis {
+        return Some(SdlTarget::Axis(axis, output));
+    }
+
+    if output != AxisRange::Full {
+        return None;
+    }
+
+    let btn = match name {
+        "a" => Button::South,
+        "b" => Button::East,
+        "x" => Button::West,
+        "y" => Button::North,
+        "back" => Button::Select,
+        "start" => Button::Start,
+        "guide" => Button::Mode,
+        "leftstick" => Button::LThumb,
+        "rightstick" => Button::RThumb,
+        "leftshoulder" => Button::LShoulder,
+        "rightshoulder" => Button::RShoulder,
+        "touchpad" => Button::Other("TrackPad"),
+        "misc1" => Button::Other("Misc1"),
+        "paddle1" => Button::Other("Paddle1"),
+        "paddle2" => Button::Other("Paddle2"),
+        "paddle3" => Button::Other("Paddle3"),
+        "paddle4" => Button::Other("Paddle4"),
+        "dpup" => return Some(SdlTarget::DPad(DPadDirection::Up)),
+        "dpdown" => return Some(SdlTarget::DPad(DPadDirection::Down)),
+        "dpleft" => return Some(SdlTarget::DPad(DPadDirection::Left)),
+        "dpright" => return Some(SdlTarget::DPad(DPadDirection::Right)),
+        _ => return None,
+    };
+
+    Some(SdlTarget::Button(btn))
+}
+
+fn parse_source(value: &str) -> Option<SdlSource> {
+    let (range, rest) = match value.as_bytes().first() {
+        Some(b'+') => (AxisRange::Positive, &value[1..]),
+        Some(b'-') => (AxisRange::Negative, &value[1..]),
+        _ => (AxisRange::Full, value),
+    };
+
+    let (inverted, rest) = match rest.strip_suffix('~') {
+        Some(r) => (true, r),
+        None => (false, rest),
+    };
+
+    let mut chars = rest.chars();
+    let kind = chars.next()?;
+    let num = chars.as_str();
+
+    match kind {
+        'a' => Some(SdlSource::Axis {
+            idx: num.parse().ok()?,
+            range,
+            inverted,
+        }),
+
+        'b' if range == AxisRange::Full && !inverted => Some(SdlSource::Button(num.parse().ok()?)),
+
+        'h' if range == AxisRange::Full && !inverted => {
+            let (hat, mask) = num.split_once('.')?;
+            Some(SdlSource::Hat(hat.parse().ok()?, mask.parse().ok()?))
+        }
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{driver::StateDiff, ObjectDiff, Profile, HAT_LIMIT, SLIDER_LIMIT};
+
+    const LINE: &str = "030000005e0400008e02000010010000,Pad,a:b0,leftx:a0,+lefty:-a1,\
+        righttrigger:a2~,dpup:h1.1,dpleft:h1.8,dpdown:h0.4,dpright:h1.4,platform:Linux,";
+
+    fn info() -> DeviceInfo {
+        let mut info = DeviceInfo {
+            name: "Pad".to_owned(),
+            buttons_num: 1,
+            hats: 2,
+            ..Default::default()
+        };
+        for ident in [AxisIdent::X, AxisIdent::Y, AxisIdent::Z] {
+            info.axis[ident as usize] = Some((0, 255));
+        }
+
+        info
+    }
+
+    #[test]
+    fn parse() {
+        let mapping = SdlMapping::parse_line(LINE).unwrap().unwrap();
+        assert_eq!(mapping.platform.as_deref(), Some("Linux"));
+
+        let sources: Vec<_> = mapping.bindings.iter().map(|b| b.source).collect();
+        assert_eq!(
+            sources,
+            [
+                SdlSource::Button(0),
+                SdlSource::Axis {
+                    idx: 0,
+                    range: AxisRange::Full,
+                    inverted: false,
+                },
+                SdlSource::Axis {
+                    idx: 1,
+                    range: AxisRange::Negative,
+                    inverted: false,
+                },
+                SdlSource::Axis {
+                    idx: 2,
+                    range: AxisRange::Full,
+                    inverted: true,
+                },
+                SdlSource::Hat(1, 1),
+                SdlSource::Hat(1, 8),
+                SdlSource::Hat(0, 4),
+                SdlSource::Hat(1, 4),
+            ]
+        );
+        assert_eq!(
+            mapping.bindings[2].target,
+            SdlTarget::Axis(Axis::LThumbY, AxisRange::Positive)
+        );
+    }
+
+    #[test]
+    fn profile_hats() {
+        let profile = SdlMapping::parse_line(LINE)
+            .unwrap()
+            .unwrap()
+            .profile(&info());
+
+        // the hat of dpdown & the remapped dpright are skipped
+        assert!(profile.dpad);
+        assert_eq!(profile.dpad_hat, 1);
+        assert_eq!(profile.hat(0), 1);
+        assert_eq!(profile.hat(1), 0);
+        assert_eq!(profile.hat(2), 2);
+    }
+
+    #[test]
+    fn profile_axis() {
+        let profile = SdlMapping::parse_line(LINE)
+            .unwrap()
+            .unwrap()
+            .profile(&info());
+
+        assert_eq!(profile.button(0), Some(Button::South));
+        assert_eq!(
+            profile.axis(AxisIdent::X),
+            [AxisDef::new(Axis::LThumbX, true)]
+        );
+        assert_eq!(
+            profile.axis(AxisIdent::Y),
+            [AxisDef {
+                input: AxisRange::Negative,
+                output: AxisRange::Positive,
+                ..AxisDef::new(Axis::LThumbY, true)
+            }]
+        );
+        assert_eq!(
+            profile.axis(AxisIdent::Z),
+            [AxisDef {
+                inverted: true,
+                ..AxisDef::new(Axis::RTrigger, false)
+            }]
+        );
+
+        // the raw diffs only report the axis without modifiers
+        let mut diff = StateDiff::<u32> {
+            dpad: [None; HAT_LIMIT],
+            buttons: (0, 0),
+            axis: [None; AxisIdent::Limit as usize],
+            sliders: [None; SLIDER_LIMIT],
+        };
+        for ident in [AxisIdent::X, AxisIdent::Y, AxisIdent::Z] {
+            diff.axis[ident as usize] = Some(10);
+        }
+
+        let axis: Vec<_> = diff
+            .diffs(&profile)
+            .into_iter()
+            .filter_map(|d| match d {
+                ObjectDiff::Axis(typ, st) => Some((typ, st)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(axis, [(Axis::LThumbX, 10)]);
+    }
+
+    #[test]
+    fn invalid_sources() {
+        let line = "030000005e0400008e02000010010000,Pad,a:é1,b:b99999999999999,x:b1,";
+        let mapping = SdlMapping::parse_line(line).unwrap().unwrap();
+        assert_eq!(mapping.bindings.len(), 2);
+
+        // buttons beyond the device are skipped
+        let profile = mapping.profile(&info());
+        assert_eq!(profile.buttons, [Button::Other("b0")]);
+
+        let mapping = SdlMapping::parse_line(LINE).unwrap().unwrap();
+        let profile = SdlMapping {
+            bindings: vec![SdlBinding {
+                target: SdlTarget::Button(Button::North),
+                source: SdlSource::Button(usize::MAX),
+            }],
+            ..mapping
+        }
+        .profile(&info());
+        assert_eq!(profile.buttons.len(), 1);
+    }
+}
@@ -0,0 +1,395 @@
+//! deadzones & response curves, applied on normalized values
+//!
+//! Values inside a deadzone are reported as zero, and unchanged outputs are
+//! dropped, so that the jitter of a resting stick produces no diffs at all.
+
+use std::{collections::BTreeMap, fs::read_to_string, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{normalize::NormalizedDiff, Axis};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    #[default]
+    Linear,
+    Quadratic,
+    /// samples evenly spaced over `[0, 1]`, linearly interpolated
+    Lut(Vec<f32>),
+}
+
+impl Curve {
+    /// map a magnitude in `[0, 1]`
+    pub fn apply(&self, v: f32) -> f32 {
+        let v = v.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => v,
+            Self::Quadratic => v * v,
+            Self::Lut(points) => match points.as_slice() {
+                [] => v,
+                [one] => *one,
+                points => {
+                    let pos = v * (points.len() - 1) as f32;
+                    let idx = (pos as usize).min(points.len() - 2);
+                    let frac = pos - idx as f32;
+                    points[idx] + (points[idx + 1] - points[idx]) * frac
+                }
+            },
+        }
+    }
+}
+
+/// inner & outer deadzones in normalized magnitude, with the curve applied in between
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    /// magnitudes below are reported as zero
+    #[serde(default)]
+    pub inner: f32,
+    /// magnitudes above are reported as full
+    #[serde(default = "Response::default_outer")]
+    pub outer: f32,
+    #[serde(default)]
+    pub curve: Curve,
+}
+
+impl Default for Response {
+    fn default() -> Self {
+        Self {
+            inner: 0.0,
+            outer: Self::default_outer(),
+            curve: Curve::default(),
+        }
+    }
+}
+
+impl Response {
+    fn default_outer() -> f32 {
+        1.0
+    }
+
+    /// map a magnitude in `[0, 1]`
+    pub fn magnitude(&self, m: f32) -> f32 {
+        if m <= self.inner {
+            return 0.0;
+        }
+
+        let span = self.outer - self.inner;
+        let scaled = if span <= 0.0 {
+            1.0
+        } else {
+            (m - self.inner) / span
+        };
+
+        self.curve.apply(scaled)
+    }
+
+    /// map a single value, keeping its sign
+    pub fn value(&self, v: f32) -> f32 {
+        let m = self.magnitude(v.abs());
+        if v < 0.0 && m > 0.0 {
+            -m
+        } else {
+            m
+        }
+    }
+
+    /// map the components of a stick together, by the length of the vector
+    pub fn radial(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let m = x.hypot(y);
+        let out = self.magnitude(m.min(1.0));
+        if out == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        (x / m * out, y / m * out)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sticks {
+    /// `LThumbX` & `LThumbY`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left: Option<Response>,
+    /// `RThumbX` & `RThumbY`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right: Option<Response>,
+}
+
+impl Sticks {
+    fn get(&self, stick: Stick) -> Option<&Response> {
+        match stick {
+            Stick::Left => self.left.as_ref(),
+            Stick::Right => self.right.as_ref(),
+        }
+    }
+}
+
+/// responses of the axis, e.g. loaded from a TOML file like
+///
+/// ```toml
+/// [axis.LTrigger]
+/// inner = 0.05
+/// curve = "quadratic"
+///
+/// [sticks.left]
+/// inner = 0.1
+/// outer = 0.95
+/// curve = { lut = [0.0, 0.2, 0.6, 1.0] }
+/// ```
+///
+/// radial stick responses take precedence over the responses of their axis.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResponseConfig {
+    #[serde(default)]
+    pub axis: BTreeMap<Axis, Response>,
+    #[serde(default)]
+    pub sticks: Sticks,
+}
+
+impl ResponseConfig {
+    pub fn from_toml_str(s: &str) -> Result<Self> {
+        toml::from_str(s).context("parse toml response config")
+    }
+
+    pub fn from_json_str(s: &str) -> Result<Self> {
+        serde_json::from_str(s).context("parse json response config")
+    }
+
+    /// load a config file, the format is chosen by the file extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content =
+            read_to_string(path).with_context(|| format!("read response config {:?}", path))?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml_str(&content),
+            Some("json") => Self::from_json_str(&content),
+            _ => Err(anyhow!("unknown response config format of {:?}", path)),
+        }
+        .with_context(|| format!("load response config {:?}", path))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stick {
+    Left,
+    Right,
+}
+
+impl Stick {
+    /// stick of the axis, and whether it is the X component
+    fn of(axis: Axis) -> Option<(Self, bool)> {
+        match axis {
+            Axis::LThumbX => Some((Self::Left, true)),
+            Axis::LThumbY => Some((Self::Left, false)),
+            Axis::RThumbX => Some((Self::Right, true)),
+            Axis::RThumbY => Some((Self::Right, false)),
+            _ => None,
+        }
+    }
+
+    fn axis(&self) -> (Axis, Axis) {
+        match self {
+            Self::Left => (Axis::LThumbX, Axis::LThumbY),
+            Self::Right => (Axis::RThumbX, Axis::RThumbY),
+        }
+    }
+}
+
+/// applies the responses on the normalized diffs of a single device
+#[derive(Debug, Clone)]
+pub struct ResponseFilter {
+    config: ResponseConfig,
+    /// normalized inputs of the sticks
+    sticks: [(f32, f32); 2],
+    /// last reported outputs
+    outputs: BTreeMap<Axis, f32>,
+}
+
+impl ResponseFilter {
+    pub fn new(config: ResponseConfig) -> Self {
+        Self {
+            config,
+            sticks: Default::default(),
+            outputs: BTreeMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ResponseConfig {
+        &self.config
+    }
+
+    /// keep the output if it differs from the last reported one
+    fn report(&mut self, axis: Axis, v: f32, out: &mut Vec<NormalizedDiff>) {
+        if self.outputs.insert(axis, v) != Some(v) {
+            out.push(NormalizedDiff::Axis(axis, v));
+        }
+    }
+
+    /// map the axis values, dropping the unchanged ones; other diffs are kept as is
+    ///
+    /// The components of the radial sticks are all folded before the sticks
+    /// are mapped, so that a stick moved along both of its axis is reported
+    /// once, after the other diffs.
+    pub fn filter(&mut self, diffs: Vec<NormalizedDiff>) -> Vec<NormalizedDiff> {
+        let mut out = Vec::with_capacity(diffs.len());
+        let mut moved = [false; 2];
+
+        for diff in diffs {
+            let (axis, v) = match diff {
+                NormalizedDiff::Axis(axis, v) => (axis, v),
+                other => {
+                    out.push(other);
+                    continue;
+                }
+            };
+
+            if let Some((stick, is_x)) = Stick::of(axis) {
+                if self.config.sticks.get(stick).is_some() {
+                    let input = &mut self.sticks[stick as usize];
+                    if is_x {
+                        input.0 = v;
+                    } else {
+                        input.1 = v;
+                    }
+
+                    moved[stick as usize] = true;
+                    continue;
+                }
+            }
+
+            let v = match self.config.axis.get(&axis) {
+                Some(resp) => resp.value(v),
+                None => v,
+            };
+
+            self.report(axis, v, &mut out);
+        }
+
+        for stick in [Stick::Left, Stick::Right] {
+            let resp = match self.config.sticks.get(stick) {
+                Some(resp) if moved[stick as usize] => resp,
+                _ => continue,
+            };
+
+            // both of the components change with the length of the vector
+            let (x, y) = resp.radial(self.sticks[stick as usize]);
+            let (x_axis, y_axis) = stick.axis();
+            self.report(x_axis, x, &mut out);
+            self.report(y_axis, y, &mut out);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Button, ButtonState};
+
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn curves() {
+        assert_eq!(Curve::Linear.apply(0.3), 0.3);
+        assert_eq!(Curve::Quadratic.apply(0.5), 0.25);
+        assert_eq!(Curve::Quadratic.apply(-2.0), 0.0);
+
+        assert_eq!(Curve::Lut(vec![]).apply(0.3), 0.3);
+        assert_eq!(Curve::Lut(vec![0.7]).apply(0.3), 0.7);
+
+        let lut = Curve::Lut(vec![0.0, 0.2, 0.6, 1.0]);
+        assert_eq!(lut.apply(0.0), 0.0);
+        assert!(approx(lut.apply(0.5), 0.4));
+        assert!(approx(lut.apply(2.0 / 3.0), 0.6));
+        // the last sample, and beyond the range
+        assert_eq!(lut.apply(1.0), 1.0);
+        assert_eq!(lut.apply(1.5), 1.0);
+        assert_eq!(lut.apply(-0.5), 0.0);
+    }
+
+    #[test]
+    fn magnitudes() {
+        let resp = Response {
+            inner: 0.2,
+            outer: 0.8,
+            curve: Curve::Linear,
+        };
+        assert_eq!(resp.magnitude(0.0), 0.0);
+        assert_eq!(resp.magnitude(0.2), 0.0);
+        assert!(approx(resp.magnitude(0.5), 0.5));
+        assert_eq!(resp.magnitude(0.8), 1.0);
+        assert_eq!(resp.magnitude(1.0), 1.0);
+        assert!(approx(resp.value(-0.5), -0.5));
+        assert_eq!(resp.value(-0.1), 0.0);
+
+        // no span left, full past the inner deadzone
+        for outer in [0.5, 0.3] {
+            let resp = Response {
+                inner: 0.5,
+                outer,
+                curve: Curve::Quadratic,
+            };
+            assert_eq!(resp.magnitude(0.5), 0.0);
+            assert_eq!(resp.magnitude(0.51), 1.0);
+        }
+    }
+
+    #[test]
+    fn radial() {
+        let resp = Response {
+            inner: 0.2,
+            ..Default::default()
+        };
+
+        // the direction is kept
+        let (x, y) = resp.radial((0.36, 0.48));
+        assert!(approx(x, 0.3) && approx(y, 0.4));
+
+        // diagonals beyond the unit circle are clamped
+        let (x, y) = resp.radial((1.0, -1.0));
+        assert!(approx(x, 0.5f32.sqrt()) && approx(y, -0.5f32.sqrt()));
+
+        // both of the components inside the deadzone
+        assert_eq!(resp.radial((0.1, -0.15)), (0.0, 0.0));
+    }
+
+    #[test]
+    fn filter_stick() {
+        let mut filter = ResponseFilter::new(ResponseConfig {
+            sticks: Sticks {
+                left: Some(Response::default()),
+                right: None,
+            },
+            ..Default::default()
+        });
+
+        let press = NormalizedDiff::Button(Button::South, ButtonState::Pressed);
+        let out = filter.filter(vec![
+            NormalizedDiff::Axis(Axis::LThumbX, 0.6),
+            press,
+            NormalizedDiff::Axis(Axis::RThumbX, 0.3),
+            NormalizedDiff::Axis(Axis::LThumbY, 0.8),
+        ]);
+
+        // reported once with both of the components
+        assert_eq!(
+            out,
+            [
+                press,
+                NormalizedDiff::Axis(Axis::RThumbX, 0.3),
+                NormalizedDiff::Axis(Axis::LThumbX, 0.6),
+                NormalizedDiff::Axis(Axis::LThumbY, 0.8),
+            ]
+        );
+
+        // unchanged outputs dropped
+        let out = filter.filter(vec![NormalizedDiff::Axis(Axis::LThumbY, 0.8)]);
+        assert!(out.is_empty());
+    }
+}
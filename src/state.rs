@@ -0,0 +1,127 @@
+//! full states of a device, folded from its state diffs
+
+use std::fmt;
+
+use crate::{
+    driver::{Bits, DeviceInfo, ObjectStates, StateDiff},
+    normalize::Normalizer,
+    profile::SharedProfile,
+    Axis, AxisIdent, AxisState, Button, ButtonState, DPadState, HatIdent, SliderIdent, SliderState,
+};
+
+/// snapshot of the objects of a device, queried through its profile
+#[derive(Clone)]
+pub struct DeviceState<B: Bits> {
+    info: DeviceInfo,
+    profile: SharedProfile,
+    normalizer: Normalizer,
+    states: ObjectStates<B>,
+}
+
+// the profile is a trait object without `Debug`
+impl<B: Bits> fmt::Debug for DeviceState<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceState")
+            .field("info", &self.info)
+            .field("normalizer", &self.normalizer)
+            .field("states", &self.states)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: Bits> DeviceState<B> {
+    /// states of a freshly attached device, nothing is reported until the first diff
+    pub fn new(info: DeviceInfo, profile: SharedProfile, normalizer: Normalizer) -> Self {
+        Self {
+            info,
+            profile,
+            normalizer,
+            states: ObjectStates::default(),
+        }
+    }
+
+    pub fn apply(&mut self, diff: &StateDiff<B>) {
+        self.states.apply(diff);
+    }
+
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    pub fn profile(&self) -> &SharedProfile {
+        &self.profile
+    }
+
+    /// state of the first hat
+    pub fn dpad(&self) -> DPadState {
+        self.hat(0)
+    }
+
+    pub fn hat(&self, idx: HatIdent) -> DPadState {
+        self.states
+            .dpad
+            .get(idx)
+            .copied()
+            .flatten()
+            .unwrap_or(DPadState::Null)
+    }
+
+    /// whether any of the buttons mapped onto `btn` is pressed
+    pub fn is_pressed(&self, btn: Button) -> bool {
+        (0..B::CAP).any(|idx| {
+            self.states.buttons.bit(idx).unwrap_or(false) && self.profile.button(idx) == Some(btn)
+        })
+    }
+
+    /// states of all the buttons of the profile
+    pub fn buttons(&self) -> Vec<(Button, ButtonState)> {
+        (0..self.info.buttons_num.min(B::CAP))
+            .filter_map(|idx| {
+                self.profile.button(idx).map(|btn| {
+                    let pressed = self.states.buttons.bit(idx).unwrap_or(false);
+                    (btn, pressed.into())
+                })
+            })
+            .collect()
+    }
+
+    pub fn raw_axis(&self, ident: AxisIdent) -> Option<AxisState> {
+        self.states.axis.get(ident as usize).copied().flatten()
+    }
+
+    /// normalized value of the axis, `None` before its first report
+    ///
+    /// If the axis is mapped from more than one definition, e.g. both halves of
+    /// a stick from two raw axis, the value with the largest magnitude wins.
+    pub fn axis(&self, axis: Axis) -> Option<f32> {
+        let mut value: Option<f32> = None;
+        for (idx, raw) in self
+            .states
+            .axis
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| x.map(|raw| (i, raw)))
+        {
+            let ident = AxisIdent::from(idx);
+            for def in self.profile.axis(ident).iter().filter(|d| d.typ == axis) {
+                if let Some(v) = self.normalizer.axis(ident, raw, def) {
+                    if value.is_none_or(|cur| v.abs() > cur.abs()) {
+                        value.replace(v);
+                    }
+                }
+            }
+        }
+
+        value
+    }
+
+    pub fn raw_slider(&self, idx: SliderIdent) -> Option<SliderState> {
+        self.states.sliders.get(idx).copied().flatten()
+    }
+
+    /// normalized value of the slider in `[0, 1]`, its kind is listed in the device info
+    pub fn slider(&self, idx: SliderIdent) -> Option<f32> {
+        self.raw_slider(idx)
+            .and_then(|raw| self.normalizer.slider(idx, raw))
+    }
+}